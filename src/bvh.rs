@@ -0,0 +1,362 @@
+use super::ray::Ray;
+use super::tup::Tup;
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Tup,
+    pub max: Tup,
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Aabb {
+            min: Tup(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Tup(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    /// Bounds of shapes that extend forever, like planes.
+    pub fn infinite() -> Self {
+        Aabb {
+            min: Tup(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            max: Tup(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        [self.min, self.max]
+            .iter()
+            .all(|p| p.0.is_finite() && p.1.is_finite() && p.2.is_finite())
+    }
+
+    pub fn union(self, rhs: Aabb) -> Aabb {
+        Aabb {
+            min: Tup(
+                self.min.0.min(rhs.min.0),
+                self.min.1.min(rhs.min.1),
+                self.min.2.min(rhs.min.2),
+            ),
+            max: Tup(
+                self.max.0.max(rhs.max.0),
+                self.max.1.max(rhs.max.1),
+                self.max.2.max(rhs.max.2),
+            ),
+        }
+    }
+
+    pub fn grow(self, p: Tup) -> Aabb {
+        self.union(Aabb { min: p, max: p })
+    }
+
+    pub fn centroid(&self) -> Tup {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        if d.0 < 0. || d.1 < 0. || d.2 < 0. {
+            return 0.;
+        }
+        2. * (d.0 * d.1 + d.1 * d.2 + d.2 * d.0)
+    }
+
+    fn axis(&self, axis: usize) -> (f64, f64) {
+        (component(self.min, axis), component(self.max, axis))
+    }
+
+    /// Slab test. Returns the distance at which the ray enters the box, if it
+    /// does so before `t_max`.
+    fn hit(&self, o: Tup, inv_d: Tup, t_max: f64) -> Option<f64> {
+        let mut t0 = 0.;
+        let mut t1 = t_max;
+        for (axis, (o, inv_d)) in [(o.0, inv_d.0), (o.1, inv_d.1), (o.2, inv_d.2)]
+            .into_iter()
+            .enumerate()
+        {
+            let (min, max) = self.axis(axis);
+            let mut near = (min - o) * inv_d;
+            let mut far = (max - o) * inv_d;
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // Widen the far distance to absorb rounding error (pbrt's gamma(3) bound).
+            far *= 1. + 2. * GAMMA_3;
+            // NaNs come from 0 * inf when the ray lies in a slab plane; ignore them.
+            if near > t0 {
+                t0 = near;
+            }
+            if far < t1 {
+                t1 = far;
+            }
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some(t0)
+    }
+}
+
+fn component(t: Tup, axis: usize) -> f64 {
+    match axis {
+        0 => t.0,
+        1 => t.1,
+        _ => t.2,
+    }
+}
+
+const GAMMA_3: f64 = 3. * f64::EPSILON * 0.5 / (1. - 3. * f64::EPSILON * 0.5);
+
+const BUCKETS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+/// Larger leaves are allowed when the SAH finds no split worth taking, which
+/// happens with heavily overlapping primitives such as the Cornell box walls.
+const MAX_SAH_LEAF_SIZE: usize = 16;
+/// Below this depth nodes are split at the median so the tree, and with it the
+/// traversal stack, stays shallow.
+const MAX_SAH_DEPTH: usize = 32;
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+struct Node {
+    bounds: Aabb,
+    /// First primitive for leaves, second child for interior nodes.
+    offset: usize,
+    /// Number of primitives; zero for interior nodes.
+    count: usize,
+    axis: usize,
+}
+
+/// A bounding volume hierarchy built with the surface area heuristic and stored
+/// as a flat array in depth-first order. It only knows the bounds of the
+/// primitives; intersecting them is left to the caller.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+struct BuildItem {
+    index: usize,
+    bounds: Aabb,
+    centroid: Tup,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut items: Vec<BuildItem> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, b)| BuildItem {
+                index,
+                bounds: *b,
+                centroid: b.centroid(),
+            })
+            .collect();
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: Vec::with_capacity(bounds.len()),
+        };
+        if !items.is_empty() {
+            bvh.build_recursive(&mut items, 0);
+        }
+        bvh
+    }
+
+    fn build_recursive(&mut self, items: &mut [BuildItem], depth: usize) -> usize {
+        let bounds = items.iter().fold(Aabb::empty(), |b, i| b.union(i.bounds));
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+
+        match self.split(items, &bounds, depth) {
+            Some((axis, mid)) => {
+                self.build_recursive(&mut items[..mid], depth + 1);
+                let second = self.build_recursive(&mut items[mid..], depth + 1);
+                self.nodes[node].offset = second;
+                self.nodes[node].axis = axis;
+            }
+            None => {
+                self.nodes[node].offset = self.indices.len();
+                self.nodes[node].count = items.len();
+                self.indices.extend(items.iter().map(|i| i.index));
+            }
+        }
+        node
+    }
+
+    /// Partitions `items` along the axis and bucket boundary with the lowest SAH
+    /// cost. Returns `None` when a leaf is cheaper.
+    fn split(
+        &self,
+        items: &mut [BuildItem],
+        bounds: &Aabb,
+        depth: usize,
+    ) -> Option<(usize, usize)> {
+        let n = items.len();
+        if n == 1 {
+            return None;
+        }
+        let centroids = items.iter().fold(Aabb::empty(), |b, i| b.grow(i.centroid));
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.0 > extent.1 && extent.0 > extent.2 {
+            0
+        } else if extent.1 > extent.2 {
+            1
+        } else {
+            2
+        };
+        let (cmin, cmax) = centroids.axis(axis);
+
+        if cmax <= cmin || depth >= MAX_SAH_DEPTH {
+            // Either all centroids coincide, so no plane separates them, or the
+            // tree is getting deep; fall back to splitting at the median.
+            if n <= MAX_LEAF_SIZE {
+                return None;
+            }
+            items.sort_by(|a, b| {
+                component(a.centroid, axis).total_cmp(&component(b.centroid, axis))
+            });
+            return Some((axis, n / 2));
+        }
+
+        let bucket_of = |i: &BuildItem| {
+            let b =
+                (BUCKETS as f64 * (component(i.centroid, axis) - cmin) / (cmax - cmin)) as usize;
+            b.min(BUCKETS - 1)
+        };
+        let mut counts = [0usize; BUCKETS];
+        let mut boxes = [Aabb::empty(); BUCKETS];
+        for item in items.iter() {
+            let b = bucket_of(item);
+            counts[b] += 1;
+            boxes[b] = boxes[b].union(item.bounds);
+        }
+
+        // Sweep from both sides to get the cost of splitting after each bucket.
+        let mut costs = [0.; BUCKETS - 1];
+        let mut below = (0, Aabb::empty());
+        for i in 0..BUCKETS - 1 {
+            below = (below.0 + counts[i], below.1.union(boxes[i]));
+            costs[i] = below.0 as f64 * below.1.surface_area();
+        }
+        let mut above = (0, Aabb::empty());
+        for i in (1..BUCKETS).rev() {
+            above = (above.0 + counts[i], above.1.union(boxes[i]));
+            costs[i - 1] += above.0 as f64 * above.1.surface_area();
+        }
+        let (best, min_cost) =
+            costs.iter().enumerate().fold(
+                (0, f64::INFINITY),
+                |acc, (i, &c)| if c < acc.1 { (i, c) } else { acc },
+            );
+
+        // Traversal is assumed to cost an eighth of a primitive test.
+        let area = bounds.surface_area();
+        let split_cost = if area > 0. && area.is_finite() {
+            0.125 + min_cost / area
+        } else {
+            0.125
+        };
+        if n <= MAX_SAH_LEAF_SIZE && split_cost >= n as f64 {
+            return None;
+        }
+
+        let mut mid = 0;
+        for i in 0..n {
+            if bucket_of(&items[i]) <= best {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+        if mid == 0 || mid == n {
+            mid = n / 2;
+        }
+        Some((axis, mid))
+    }
+
+    /// Visits the primitives whose bounds the ray may hit, near child first.
+    /// `visit` is called with a primitive index and returns the distance to the
+    /// closest hit found so far, which is used to skip farther nodes.
+    pub fn traverse(&self, ray: &Ray, mut t_max: f64, mut visit: impl FnMut(usize) -> f64) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inv_d = Tup(1. / ray.d.0, 1. / ray.d.1, 1. / ray.d.2);
+        let dir_is_neg = [inv_d.0 < 0., inv_d.1 < 0., inv_d.2 < 0.];
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut top = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.bounds.hit(ray.o, inv_d, t_max).is_some() {
+                if node.count > 0 {
+                    for &index in &self.indices[node.offset..node.offset + node.count] {
+                        t_max = visit(index);
+                    }
+                } else {
+                    let (near, far) = if dir_is_neg[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[top] = far;
+                    top += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if top == 0 {
+                break;
+            }
+            top -= 1;
+            current = stack[top];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box(c: Tup) -> Aabb {
+        Aabb {
+            min: c - Tup(0.5, 0.5, 0.5),
+            max: c + Tup(0.5, 0.5, 0.5),
+        }
+    }
+
+    #[test]
+    fn box_hit() {
+        let b = unit_box(Tup(0., 0., -5.));
+        let o = Tup(0., 0., 0.);
+        let d = Tup(0., 0., -1.);
+        let inv_d = Tup(1. / d.0, 1. / d.1, 1. / d.2);
+        assert_eq!(b.hit(o, inv_d, f64::INFINITY), Some(4.5));
+        assert_eq!(b.hit(o, inv_d, 4.), None);
+        assert_eq!(b.hit(Tup(2., 0., 0.), inv_d, f64::INFINITY), None);
+    }
+
+    #[test]
+    fn traversal_visits_every_box_on_the_ray() {
+        let bounds: Vec<Aabb> = (0..100)
+            .map(|i| unit_box(Tup((i % 10) as f64 * 2., (i / 10) as f64 * 2., 0.)))
+            .collect();
+        let bvh = Bvh::build(&bounds);
+        let ray = Ray {
+            o: Tup(4., 6., 10.),
+            d: Tup(0., 0., -1.),
+        };
+        let mut visited = vec![];
+        bvh.traverse(&ray, f64::INFINITY, |i| {
+            visited.push(i);
+            f64::INFINITY
+        });
+        assert!(visited.contains(&32));
+        assert!(visited.len() < 100);
+    }
+}
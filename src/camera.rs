@@ -0,0 +1,581 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::ray::Ray;
+use super::tup::Tup;
+
+/// Distance smallpt's camera moves rays forward so that they start inside the
+/// Cornell box, past its front wall.
+pub const SMALLPT_NEAR: f64 = 140.;
+
+/// How film positions map to directions, as written in scene files, e.g.
+/// `{ "type": "fisheye", "mapping": "equisolid", "fov": 180 }`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Parallel rays from an image plane `height` units high. Without a height
+    /// the plane matches the perspective view at the focus distance.
+    Orthographic {
+        #[serde(default)]
+        height: Option<f64>,
+    },
+    /// A circular image, inscribed in the film, covering `fov` degrees.
+    Fisheye {
+        #[serde(default)]
+        mapping: FisheyeMapping,
+        #[serde(default = "half_turn")]
+        fov: f64,
+    },
+    /// Longitude across and latitude up the film, all around the camera; use
+    /// an image twice as wide as high.
+    Equirectangular,
+}
+
+fn half_turn() -> f64 {
+    180.
+}
+
+/// How the distance from the centre of a fisheye image grows with the angle
+/// from the viewing direction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FisheyeMapping {
+    /// Proportionally to the angle.
+    #[default]
+    Equidistant,
+    /// So that equal areas of the image cover equal solid angles.
+    Equisolid,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" => Ok(Projection::Orthographic { height: None }),
+            "fisheye" => Ok(Projection::Fisheye {
+                mapping: FisheyeMapping::Equidistant,
+                fov: half_turn(),
+            }),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            _ => Err(format!(
+                "unknown projection '{}' (expected 'perspective', 'orthographic', 'fisheye' or \
+                 'equirectangular')",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for FisheyeMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "equidistant" => Ok(FisheyeMapping::Equidistant),
+            "equisolid" => Ok(FisheyeMapping::Equisolid),
+            _ => Err(format!(
+                "unknown fisheye mapping '{}' (expected 'equidistant' or 'equisolid')",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic { .. } => "orthographic",
+            Projection::Fisheye { .. } => "fisheye",
+            Projection::Equirectangular => "equirectangular",
+        })
+    }
+}
+
+/// A camera with one of several projections and, for the perspective and
+/// orthographic ones, an optional thin lens.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "CameraFile")]
+pub struct Camera {
+    pub origin: Tup,
+    /// Orthonormal frame: the viewing direction and the directions of the
+    /// image's x and y axes.
+    pub forward: Tup,
+    pub right: Tup,
+    pub up: Tup,
+    pub projection: Projection,
+    /// Height of the perspective image plane at unit distance,
+    /// `2 tan(fov / 2)`; smallpt uses 0.5135.
+    pub fov_scale: f64,
+    /// Width over height of the image plane. Without it the image's own aspect
+    /// ratio is used, which keeps pixels square.
+    pub aspect: Option<f64>,
+    /// Radius of the lens; zero for a pinhole camera.
+    pub aperture: f64,
+    /// Distance along `forward` of the plane that is in focus.
+    pub focus_distance: f64,
+    /// Distance along `forward` at which rays start. Fisheye and
+    /// equirectangular rays, which may point anywhere, start at `origin`.
+    pub near: f64,
+}
+
+impl Camera {
+    /// A pinhole camera at `origin` looking at `target`, with `up` pointing
+    /// roughly up in the image and a vertical field of view of `fov` degrees.
+    pub fn look_at(origin: Tup, target: Tup, up: Tup, fov: f64) -> Self {
+        let fov_scale = 2. * (fov.to_radians() / 2.).tan();
+        let mut camera = Camera::looking(origin, target - origin, up, fov_scale);
+        camera.focus_distance = (target - origin).dot(camera.forward);
+        camera
+    }
+
+    /// A pinhole camera looking along `direction`, with `up` pointing roughly
+    /// up in the image and an image plane `fov_scale` high at unit distance.
+    pub fn looking(origin: Tup, direction: Tup, up: Tup, fov_scale: f64) -> Self {
+        let forward = direction.norm();
+        let right = forward.cross(up).norm();
+        Camera {
+            origin,
+            forward,
+            right,
+            up: right.cross(forward),
+            projection: Projection::Perspective,
+            fov_scale,
+            aspect: None,
+            aperture: 0.,
+            focus_distance: 1.,
+            near: 0.,
+        }
+    }
+
+    pub fn with_aspect(self, aspect: f64) -> Self {
+        Camera {
+            aspect: Some(aspect),
+            ..self
+        }
+    }
+
+    /// Gives the camera a lens of radius `aperture` focused at
+    /// `focus_distance`.
+    pub fn with_thin_lens(self, aperture: f64, focus_distance: f64) -> Self {
+        Camera {
+            aperture,
+            focus_distance,
+            ..self
+        }
+    }
+
+    pub fn with_near(self, near: f64) -> Self {
+        Camera { near, ..self }
+    }
+
+    pub fn with_projection(self, projection: Projection) -> Self {
+        Camera { projection, ..self }
+    }
+
+    /// Returns the ray through film position (`fx`, `fy`) of a `w` by `h` image,
+    /// measured in pixels from the bottom-left corner, leaving the lens at the
+    /// point `lens` in [0, 1)^2 maps to. Film positions outside a fisheye's
+    /// image circle see nothing.
+    pub fn ray(&self, w: usize, h: usize, fx: f64, fy: f64, lens: (f64, f64)) -> Option<Ray> {
+        let aspect = self.aspect.unwrap_or(w as f64 / h as f64);
+        // Film position relative to the centre, in [-0.5, 0.5].
+        let (sx, sy) = (fx / w as f64 - 0.5, fy / h as f64 - 0.5);
+        match self.projection {
+            Projection::Perspective => {
+                // Direction through the image plane at unit distance along `forward`.
+                let d = self.right * (aspect * self.fov_scale * sx)
+                    + self.up * (self.fov_scale * sy)
+                    + self.forward;
+                if self.aperture == 0. {
+                    return Some(Ray {
+                        o: self.origin + d * self.near,
+                        d: d.norm(),
+                    });
+                }
+                Some(self.through_lens(self.origin, d * self.focus_distance, lens))
+            }
+            Projection::Orthographic { height } => {
+                let height = height.unwrap_or(self.fov_scale * self.focus_distance);
+                let o = self.origin + self.right * (aspect * height * sx) + self.up * (height * sy);
+                if self.aperture == 0. {
+                    return Some(Ray {
+                        o: o + self.forward * self.near,
+                        d: self.forward,
+                    });
+                }
+                Some(self.through_lens(o, self.forward * self.focus_distance, lens))
+            }
+            Projection::Fisheye { mapping, fov } => {
+                // Distance from the centre relative to the radius of the image
+                // circle, which touches the nearer edges of the film.
+                let (px, py) = (fx - w as f64 / 2., fy - h as f64 / 2.);
+                let r = (px * px + py * py).sqrt() / (w.min(h) as f64 / 2.);
+                if r > 1. {
+                    return None;
+                }
+                let half_fov = fov.to_radians() / 2.;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2. * (r * (half_fov / 2.).sin()).asin(),
+                };
+                let phi = py.atan2(px);
+                Some(Ray {
+                    o: self.origin,
+                    d: self.forward * theta.cos()
+                        + (self.right * phi.cos() + self.up * phi.sin()) * theta.sin(),
+                })
+            }
+            Projection::Equirectangular => {
+                let (phi, lat) = (2. * PI * sx, PI * sy);
+                Some(Ray {
+                    o: self.origin,
+                    d: self.forward * (lat.cos() * phi.cos())
+                        + self.right * (lat.cos() * phi.sin())
+                        + self.up * lat.sin(),
+                })
+            }
+        }
+    }
+
+    /// The ray from the point of the lens centred on `centre` that `lens` maps
+    /// to, through the point `to_focus` away from `centre`. It starts on the
+    /// near plane.
+    fn through_lens(&self, centre: Tup, to_focus: Tup, lens: (f64, f64)) -> Ray {
+        let (lx, ly) = concentric_disk(lens);
+        let lens = centre + (self.right * lx + self.up * ly) * self.aperture;
+        let dir = (centre + to_focus - lens).norm();
+        Ray {
+            o: lens + dir * (self.near / dir.dot(self.forward)),
+            d: dir,
+        }
+    }
+}
+
+/// Maps the unit square onto the unit disk, keeping strata compact
+/// (Shirley and Chiu's concentric mapping).
+fn concentric_disk((u, v): (f64, f64)) -> (f64, f64) {
+    let (a, b) = (2. * u - 1., 2. * v - 1.);
+    if a == 0. && b == 0. {
+        return (0., 0.);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4. * (b / a))
+    } else {
+        (b, PI / 2. - PI / 4. * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// How scene files describe a camera: looking along `direction` as smallpt
+/// does, or at the point `look_at`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraFile {
+    origin: Tup,
+    direction: Option<Tup>,
+    look_at: Option<Tup>,
+    #[serde(default = "default_up")]
+    up: Tup,
+    #[serde(default)]
+    projection: Projection,
+    /// Vertical field of view in degrees.
+    fov: Option<f64>,
+    fov_scale: Option<f64>,
+    aspect: Option<f64>,
+    #[serde(default)]
+    aperture: f64,
+    focus_distance: Option<f64>,
+    near: Option<f64>,
+}
+
+fn default_up() -> Tup {
+    Tup(0., 1., 0.)
+}
+
+impl TryFrom<CameraFile> for Camera {
+    type Error = String;
+
+    fn try_from(file: CameraFile) -> Result<Self, Self::Error> {
+        let fov_scale = match (file.fov, file.fov_scale) {
+            (Some(fov), None) if fov > 0. && fov < 180. => 2. * (fov.to_radians() / 2.).tan(),
+            (Some(fov), None) => {
+                return Err(format!(
+                    "fov must be between 0 and 180 degrees, got {}",
+                    fov
+                ))
+            }
+            (None, Some(s)) if s > 0. && s.is_finite() => s,
+            (None, Some(s)) => return Err(format!("fov_scale must be positive, got {}", s)),
+            (None, None) => 0.5135,
+            (Some(_), Some(_)) => return Err("give either fov or fov_scale, not both".to_string()),
+        };
+        let (direction, near) = match (file.direction, file.look_at) {
+            (Some(d), None) => (d, SMALLPT_NEAR),
+            (None, Some(target)) => (target - file.origin, 0.),
+            _ => return Err("give either direction or look_at".to_string()),
+        };
+        if direction.dot(direction) == 0. {
+            return Err("the camera must look somewhere".to_string());
+        }
+        match file.projection {
+            Projection::Orthographic { height: Some(h) } if !(h > 0. && h.is_finite()) => {
+                return Err(format!("orthographic height must be positive, got {}", h))
+            }
+            Projection::Fisheye { fov, .. } if !(fov > 0. && fov <= 360.) => {
+                return Err(format!(
+                    "fisheye fov must be between 0 and 360 degrees, got {}",
+                    fov
+                ))
+            }
+            _ => {}
+        }
+        let mut camera = Camera::looking(file.origin, direction, file.up, fov_scale)
+            .with_projection(file.projection);
+        if !camera.right.dot(camera.right).is_finite() {
+            return Err("up must not be parallel to the viewing direction".to_string());
+        }
+        if file.look_at.is_some() {
+            camera.focus_distance = direction.dot(camera.forward);
+        }
+        camera.near = file.near.unwrap_or(near);
+        if let Some(aspect) = file.aspect {
+            if !(aspect > 0. && aspect.is_finite()) {
+                return Err(format!("aspect must be positive, got {}", aspect));
+            }
+            camera.aspect = Some(aspect);
+        }
+        if !(file.aperture >= 0. && file.aperture.is_finite()) {
+            return Err(format!(
+                "aperture must not be negative, got {}",
+                file.aperture
+            ));
+        }
+        camera.aperture = file.aperture;
+        match file.focus_distance {
+            Some(f) if f > 0. && f.is_finite() => camera.focus_distance = f,
+            Some(f) => return Err(format!("focus_distance must be positive, got {}", f)),
+            None if camera.aperture > 0. && file.look_at.is_none() => {
+                return Err(
+                    "a camera with an aperture needs a focus_distance or look_at".to_string(),
+                )
+            }
+            None => {}
+        }
+        Ok(camera)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Tup, b: Tup) -> bool {
+        (a - b).dot(a - b) < 1e-20
+    }
+
+    #[test]
+    fn centre_ray_follows_direction() {
+        let cam = Camera::looking(Tup(0., 0., 0.), Tup(0., 0., -1.), Tup(0., 1., 0.), 0.5135)
+            .with_near(140.);
+        let ray = cam.ray(640, 480, 320., 240., (0.5, 0.5)).unwrap();
+        assert_eq!(ray.d, Tup(0., 0., -1.));
+        assert_eq!(ray.o, Tup(0., 0., -140.));
+    }
+
+    #[test]
+    fn matches_smallpt() {
+        // smallpt's camera, as it was built inline in `main`.
+        let (o, d, w, h) = (
+            Tup(50., 52., 295.6),
+            Tup(0., -0.042612, -1.).norm(),
+            640,
+            480,
+        );
+        let cx = Tup(w as f64 * 0.5135 / h as f64, 0., 0.);
+        let cy = cx.cross(d).norm() * 0.5135;
+        let cam = Camera::looking(o, d, Tup(0., 1., 0.), 0.5135).with_near(SMALLPT_NEAR);
+        for (fx, fy) in [(0., 0.), (320., 240.), (639.5, 10.25)] {
+            let dir = cx * (fx / w as f64 - 0.5) + cy * (fy / h as f64 - 0.5) + d;
+            let ray = cam.ray(w, h, fx, fy, (0.5, 0.5)).unwrap();
+            assert!(close(ray.d, dir.norm()), "{:?} {:?}", ray.d, dir.norm());
+            assert!(close(ray.o, o + dir * 140.), "{:?}", ray.o);
+        }
+    }
+
+    #[test]
+    fn look_at_frames_the_target() {
+        let cam = Camera::look_at(Tup(1., 2., 3.), Tup(1., 2., -7.), Tup(0., 1., 0.), 90.)
+            .with_aspect(2.);
+        assert_eq!(cam.focus_distance, 10.);
+        let ray = cam.ray(100, 100, 50., 50., (0.5, 0.5)).unwrap();
+        assert!(close(ray.d, Tup(0., 0., -1.)));
+        // A 90 degree field of view reaches 45 degrees up at the top edge, and
+        // twice as far sideways at the right edge.
+        let ray = cam.ray(100, 100, 50., 100., (0.5, 0.5)).unwrap();
+        assert!(close(ray.d, Tup(0., 1., -1.).norm()));
+        let ray = cam.ray(100, 100, 100., 50., (0.5, 0.5)).unwrap();
+        assert!(close(ray.d, Tup(2., 0., -1.).norm()));
+    }
+
+    #[test]
+    fn thin_lens_focuses_on_the_focal_plane() {
+        let cam = Camera::look_at(Tup(0., 0., 0.), Tup(0., 0., -5.), Tup(0., 1., 0.), 40.)
+            .with_thin_lens(0.5, 5.)
+            .with_near(1.);
+        for (fx, fy) in [(3., 4.), (12.5, 0.5)] {
+            let pinhole = cam
+                .clone()
+                .with_thin_lens(0., 5.)
+                .ray(16, 9, fx, fy, (0.5, 0.5))
+                .unwrap();
+            let focus = pinhole.o + pinhole.d * (4. / pinhole.d.dot(cam.forward));
+            let mut spread: f64 = 0.;
+            for lens in [(0.1, 0.2), (0.9, 0.5), (0.3, 0.99)] {
+                let ray = cam.ray(16, 9, fx, fy, lens).unwrap();
+                // Every ray through the lens starts on the near plane, within
+                // the aperture, and passes through the same point in focus.
+                assert!((ray.o.dot(cam.forward) - 1.).abs() < 1e-12);
+                let t = (5. - ray.o.dot(cam.forward)) / ray.d.dot(cam.forward);
+                assert!(close(ray.o + ray.d * t, focus), "{:?}", ray.o + ray.d * t);
+                let at_lens = ray.o - ray.d * (1. / ray.d.dot(cam.forward));
+                assert!(at_lens.dot(at_lens) <= 0.25 + 1e-12);
+                spread = spread.max(at_lens.dot(at_lens));
+            }
+            assert!(spread > 0.01);
+        }
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let cam = Camera::look_at(Tup(0., 0., 10.), Tup(0., 0., 0.), Tup(0., 1., 0.), 60.)
+            .with_projection(Projection::Orthographic { height: Some(4.) })
+            .with_near(1.);
+        let ray = cam.ray(20, 10, 20., 10., (0.5, 0.5)).unwrap();
+        assert_eq!(ray.d, Tup(0., 0., -1.));
+        assert!(close(ray.o, Tup(4., 2., 9.)), "{:?}", ray.o);
+        // Without a height the view matches the perspective one at the focus
+        // distance.
+        let cam = cam.with_projection(Projection::Orthographic { height: None });
+        let ray = cam.ray(20, 10, 10., 10., (0.5, 0.5)).unwrap();
+        assert!((ray.o.1 - cam.fov_scale * 10. / 2.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn fisheye_maps_radius_to_angle() {
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let cam = Camera::looking(Tup(0., 0., 0.), Tup(0., 0., -1.), Tup(0., 1., 0.), 1.)
+                .with_projection(Projection::Fisheye { mapping, fov: 180. })
+                .with_near(SMALLPT_NEAR);
+            let centre = cam.ray(30, 20, 15., 10., (0.5, 0.5)).unwrap();
+            assert!(close(centre.d, Tup(0., 0., -1.)));
+            assert_eq!(centre.o, Tup(0., 0., 0.));
+            // The image circle touches the top and bottom of the film, at 90
+            // degrees from the viewing direction.
+            let top = cam.ray(30, 20, 15., 20., (0.5, 0.5)).unwrap();
+            assert!(close(top.d, Tup(0., 1., 0.)), "{:?}", top.d);
+            let side = cam.ray(30, 20, 5., 10., (0.5, 0.5)).unwrap();
+            assert!(close(side.d, Tup(-1., 0., 0.)), "{:?}", side.d);
+            assert!(cam.ray(30, 20, 1., 10., (0.5, 0.5)).is_none());
+            assert!(cam.ray(30, 20, 25., 19., (0.5, 0.5)).is_none());
+        }
+        // Halfway out, equidistant gives half the angle, equisolid more.
+        let at = |mapping| {
+            let cam = Camera::looking(Tup(0., 0., 0.), Tup(0., 0., -1.), Tup(0., 1., 0.), 1.)
+                .with_projection(Projection::Fisheye { mapping, fov: 180. });
+            let d = cam.ray(20, 20, 10., 15., (0.5, 0.5)).unwrap().d;
+            d.1.atan2(-d.2).to_degrees()
+        };
+        assert!((at(FisheyeMapping::Equidistant) - 45.).abs() < 1e-9);
+        let equisolid = 2. * (0.5 * (45f64).to_radians().sin()).asin().to_degrees();
+        assert!((at(FisheyeMapping::Equisolid) - equisolid).abs() < 1e-9);
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let cam = Camera::looking(Tup(1., 2., 3.), Tup(0., 0., -1.), Tup(0., 1., 0.), 1.)
+            .with_projection(Projection::Equirectangular)
+            .with_near(2.);
+        let ray = |fx, fy| cam.ray(40, 20, fx, fy, (0.5, 0.5)).unwrap();
+        assert!(close(ray(20., 10.).d, Tup(0., 0., -1.)));
+        // Rays behind the camera must not start past whatever is behind it.
+        assert_eq!(ray(0., 10.).o, Tup(1., 2., 3.));
+        assert!(close(ray(30., 10.).d, Tup(1., 0., 0.)));
+        assert!(close(ray(0., 10.).d, Tup(0., 0., 1.)));
+        assert!(close(ray(7., 20.).d, Tup(0., 1., 0.)));
+        assert!(close(ray(33., 0.).d, Tup(0., -1., 0.)));
+    }
+
+    #[test]
+    fn projection_names_round_trip() {
+        for name in ["perspective", "orthographic", "fisheye", "equirectangular"] {
+            assert_eq!(name.parse::<Projection>().unwrap().to_string(), name);
+        }
+        assert!("stereographic".parse::<Projection>().is_err());
+        let cam: Camera = serde_json::from_str(
+            r#"{ "origin": [0, 0, 0], "direction": [0, 0, -1],
+                 "projection": { "type": "fisheye", "mapping": "equisolid" } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cam.projection,
+            Projection::Fisheye {
+                mapping: FisheyeMapping::Equisolid,
+                fov: 180.
+            }
+        );
+    }
+
+    #[test]
+    fn concentric_disk_stays_inside() {
+        assert_eq!(concentric_disk((0.5, 0.5)), (0., 0.));
+        let (x, y) = concentric_disk((1., 0.5));
+        assert!((x - 1.).abs() < 1e-12 && y.abs() < 1e-12);
+        for i in 0..32 {
+            for j in 0..32 {
+                let (x, y) = concentric_disk((i as f64 / 31., j as f64 / 31.));
+                assert!(x * x + y * y <= 1. + 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_both_forms() {
+        let legacy: Camera = serde_json::from_str(
+            r#"{ "origin": [50, 52, 295.6], "direction": [0, -0.042612, -1], "fov_scale": 0.5135 }"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.near, SMALLPT_NEAR);
+        assert_eq!(legacy.aperture, 0.);
+        let dof: Camera = serde_json::from_str(
+            r#"{ "origin": [0, 0, 0], "look_at": [0, 0, -4], "fov": 60, "aperture": 0.1 }"#,
+        )
+        .unwrap();
+        assert_eq!(dof.near, 0.);
+        assert_eq!(dof.focus_distance, 4.);
+        assert!((dof.fov_scale - 2. * (30f64).to_radians().tan()).abs() < 1e-15);
+
+        for bad in [
+            r#"{ "origin": [0, 0, 0] }"#,
+            r#"{ "origin": [0, 0, 0], "direction": [0, 0, 0] }"#,
+            r#"{ "origin": [0, 0, 0], "direction": [0, 1, 0] }"#,
+            r#"{ "origin": [0, 0, 0], "direction": [0, 0, -1], "fov": 180 }"#,
+            r#"{ "origin": [0, 0, 0], "direction": [0, 0, -1], "aperture": 1 }"#,
+            r#"{ "origin": [0, 0, 0], "look_at": [0, 0, -1], "aperture": -1 }"#,
+            r#"{ "origin": [0, 0, 0], "look_at": [0, 0, -1], "lens": 1 }"#,
+            r#"{ "origin": [0, 0, 0], "look_at": [0, 0, -1],
+                 "projection": { "type": "fisheye", "fov": 400 } }"#,
+            r#"{ "origin": [0, 0, 0], "look_at": [0, 0, -1],
+                 "projection": { "type": "orthographic", "height": 0 } }"#,
+        ] {
+            assert!(serde_json::from_str::<Camera>(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::render::{ProgressiveRender, RenderSettings};
+use super::scene::Scene;
+
+/// Marks checkpoint files and the version of their layout.
+const MAGIC: &[u8; 8] = b"SPTCKPT1";
+
+/// Everything needed to continue a progressive render where it stopped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// [`Scene::hash`] of the scene being rendered.
+    pub scene_hash: u64,
+    #[serde(with = "json")]
+    pub settings: RenderSettings,
+    /// Samples per pixel taken per pass. Continuing with the same passes gives
+    /// the same sums, so a resumed render matches an uninterrupted one.
+    pub pass_samples: u32,
+    pub render: ProgressiveRender,
+}
+
+impl Checkpoint {
+    pub fn new(
+        scene: &Scene,
+        settings: &RenderSettings,
+        pass_samples: u32,
+        render: ProgressiveRender,
+    ) -> Self {
+        Checkpoint {
+            scene_hash: scene.hash,
+            settings: settings.clone(),
+            pass_samples,
+            render,
+        }
+    }
+
+    /// Writes the checkpoint to `path`. The file is replaced only once the new
+    /// one is complete, so a process killed while saving leaves the previous
+    /// checkpoint intact.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        {
+            let mut out = BufWriter::new(File::create(&temp)?);
+            out.write_all(MAGIC)?;
+            bincode::serialize_into(&mut out, self).map_err(io::Error::other)?;
+            out.into_inner()?.sync_all()?;
+        }
+        fs::rename(&temp, path)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; MAGIC.len()];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a checkpoint file",
+            ));
+        }
+        bincode::deserialize_from(file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Deletes the checkpoint at `path`, once the render it continues has
+    /// been completed and written. A missing file is not an error.
+    pub fn remove(path: impl AsRef<Path>) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the checkpoint continues a render of `scene` with
+    /// `settings` in passes of `pass_samples`.
+    pub fn matches(
+        &self,
+        scene: &Scene,
+        settings: &RenderSettings,
+        pass_samples: u32,
+    ) -> Result<(), String> {
+        if self.scene_hash != scene.hash {
+            return Err("the checkpoint was made for a different scene".to_string());
+        }
+        // A render without a seed drew its own, which the checkpoint keeps.
+        let unseeded = |settings: &RenderSettings| RenderSettings {
+            seed: None,
+            ..settings.clone()
+        };
+        if settings.seed.is_some_and(|seed| seed != self.render.seed)
+            || unseeded(&self.settings) != unseeded(settings)
+        {
+            return Err("the checkpoint was made with different render settings".to_string());
+        }
+        if self.pass_samples != pass_samples {
+            return Err(format!(
+                "the checkpoint was made with passes of {} samples per pixel",
+                self.pass_samples
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Stores a value as a JSON string. Render settings rely on serde features
+/// that only self-describing formats support, which bincode is not.
+mod json {
+    use serde::de::{DeserializeOwned, Error as _};
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Serialize, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&serde_json::to_string(value).map_err(S::Error::custom)?)
+    }
+
+    pub fn deserialize<'de, T: DeserializeOwned, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<T, D::Error> {
+        serde_json::from_str(&String::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumed_renders_match_uninterrupted_ones() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 10,
+            height: 6,
+            samples: 3,
+            ..Default::default()
+        };
+        let mut render = ProgressiveRender::new(&settings);
+        render.pass(&scene, &settings, 4, |_, _| {});
+
+        let path = std::env::temp_dir().join(format!("smallpt-{}.ckpt", std::process::id()));
+        Checkpoint::new(&scene, &settings, 4, render.clone())
+            .save(&path)
+            .unwrap();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(checkpoint.matches(&scene, &settings, 4), Ok(()));
+        let mut resumed = checkpoint.render;
+        assert_eq!(resumed, render);
+
+        while !render.is_done(&settings) {
+            render.pass(&scene, &settings, 4, |_, _| {});
+            resumed.pass(&scene, &settings, 4, |_, _| {});
+        }
+        assert_eq!(resumed, render);
+    }
+
+    #[test]
+    fn rejects_other_renders() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 4,
+            height: 4,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let checkpoint = Checkpoint::new(&scene, &settings, 2, ProgressiveRender::new(&settings));
+        let other = Scene::from_json(&crate::scene::CORNELL_BOX.replace("295.6", "295")).unwrap();
+        assert!(checkpoint.matches(&other, &settings, 2).is_err());
+        let wider = RenderSettings {
+            width: 5,
+            ..settings.clone()
+        };
+        assert!(checkpoint.matches(&scene, &wider, 2).is_err());
+        let reseeded = RenderSettings {
+            seed: Some(2),
+            ..settings.clone()
+        };
+        assert!(checkpoint.matches(&scene, &reseeded, 2).is_err());
+        assert!(checkpoint.matches(&scene, &settings, 1).is_err());
+    }
+
+    #[test]
+    fn removes_finished_checkpoints() {
+        let settings = RenderSettings::default();
+        let path = std::env::temp_dir().join(format!("smallpt-{}-done.ckpt", std::process::id()));
+        Checkpoint::new(&Scene::cornell_box(), &settings, 4, ProgressiveRender::new(&settings))
+            .save(&path)
+            .unwrap();
+        Checkpoint::remove(&path).unwrap();
+        let err = Checkpoint::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        Checkpoint::remove(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("smallpt-{}.not-ckpt", std::process::id()));
+        fs::write(&path, "P6\n1 1\n255\n\0\0\0").unwrap();
+        let err = Checkpoint::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
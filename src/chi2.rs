@@ -0,0 +1,224 @@
+//! Chi-square tests that a material samples directions with the density its
+//! `pdf` reports, and that each sample's weight agrees with `eval`.
+
+use std::f64::consts::PI;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::material::{Material, Surface};
+use super::tup::Tup;
+
+const THETA_BINS: usize = 20;
+const PHI_BINS: usize = 40;
+const SAMPLES: usize = 200_000;
+/// Points per bin along each axis when integrating the pdf over a bin. Bins
+/// get wide in the polar angle near the poles, where lobes often peak.
+const THETA_SUBDIVISIONS: usize = 32;
+const PHI_SUBDIVISIONS: usize = 8;
+/// Significance of a single test.
+const SIGNIFICANCE: f64 = 1e-3;
+
+/// Directions are binned by the cosine of their angle to `s.n` and their
+/// azimuth around it, which gives bins of equal solid angle over the sphere.
+fn bin(s: &Surface, (t, b): (Tup, Tup), w: Tup) -> usize {
+    let z = w.dot(s.n).clamp(-1., 1.);
+    let phi = w.dot(b).atan2(w.dot(t)).rem_euclid(2. * PI);
+    let i = (((z + 1.) / 2. * THETA_BINS as f64) as usize).min(THETA_BINS - 1);
+    let j = ((phi / (2. * PI) * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+    i * PHI_BINS + j
+}
+
+/// Samples `material` at `s` seen from `wo` and panics unless the histogram
+/// of the sampled directions matches the integral of its pdf over each bin.
+pub fn assert_sampling_matches_pdf(material: &dyn Material, s: &Surface, wo: Tup, seed: u64) {
+    let frame = s.n.basis();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut observed = vec![0.; THETA_BINS * PHI_BINS];
+    for _ in 0..SAMPLES {
+        let Some(b) = material.sample(s, wo, rng.gen(), (rng.gen(), rng.gen())) else {
+            continue;
+        };
+        assert!(!b.specular, "{:?}", b);
+        assert!((b.wi.dot(b.wi) - 1.).abs() < 1e-9, "{:?}", b);
+        let pdf = material.pdf(s, wo, b.wi);
+        assert!(
+            (pdf - b.pdf).abs() <= 1e-6 * pdf.max(1.),
+            "sampled pdf {} but pdf() gives {}",
+            b.pdf,
+            pdf
+        );
+        let weight = material.eval(s, wo, b.wi) * (b.wi.dot(s.n).abs() / b.pdf);
+        let error = weight - b.weight;
+        assert!(
+            error.0.abs().max(error.1.abs()).max(error.2.abs()) <= 1e-6 * b.weight.0.max(1.),
+            "weight {:?} but eval gives {:?}",
+            b.weight,
+            weight
+        );
+        observed[bin(s, frame, b.wi)] += 1.;
+    }
+
+    // Integrate over each bin in the polar angle rather than its cosine, in
+    // which peaks at the poles are much smoother.
+    let (t, b) = frame;
+    let dphi = 2. * PI / PHI_BINS as f64;
+    let mut expected = vec![0.; THETA_BINS * PHI_BINS];
+    for i in 0..THETA_BINS {
+        let theta_of = |i: usize| {
+            (2. * i as f64 / THETA_BINS as f64 - 1.)
+                .clamp(-1., 1.)
+                .acos()
+        };
+        let (theta0, theta1) = (theta_of(i + 1), theta_of(i));
+        let dtheta = (theta1 - theta0) / THETA_SUBDIVISIONS as f64;
+        for j in 0..PHI_BINS {
+            let mut integral = 0.;
+            for k in 0..THETA_SUBDIVISIONS {
+                let theta = theta0 + (k as f64 + 0.5) * dtheta;
+                for l in 0..PHI_SUBDIVISIONS {
+                    let phi = (j as f64 + (l as f64 + 0.5) / PHI_SUBDIVISIONS as f64) * dphi;
+                    let (z, r) = (theta.cos(), theta.sin());
+                    let w = t * (r * phi.cos()) + b * (r * phi.sin()) + s.n * z;
+                    integral += material.pdf(s, wo, w) * r;
+                }
+            }
+            expected[i * PHI_BINS + j] =
+                integral * dtheta * dphi / PHI_SUBDIVISIONS as f64 * SAMPLES as f64;
+        }
+    }
+
+    let p = chi_square_p_value(&observed, &expected);
+    assert!(
+        p > SIGNIFICANCE,
+        "{:?} failed the chi-square test from {:?}: p = {}",
+        material,
+        wo,
+        p
+    );
+}
+
+/// The probability of a chi-square statistic at least as large as the one of
+/// `observed` against `expected`, with bins that expect fewer than 5 samples
+/// pooled together.
+fn chi_square_p_value(observed: &[f64], expected: &[f64]) -> f64 {
+    let mut bins: Vec<(f64, f64)> = expected
+        .iter()
+        .copied()
+        .zip(observed.iter().copied())
+        .collect();
+    bins.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let (mut pooled_expected, mut pooled_observed) = (0., 0.);
+    let mut statistic = 0.;
+    let mut dof = 0;
+    for (e, o) in bins {
+        if e == 0. {
+            assert!(o == 0., "{} samples where the pdf is zero", o);
+            continue;
+        }
+        if e < 5. {
+            pooled_expected += e;
+            pooled_observed += o;
+            continue;
+        }
+        statistic += (o - e) * (o - e) / e;
+        dof += 1;
+    }
+    if pooled_expected > 0. {
+        statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+        dof += 1;
+    }
+    1. - regularized_gamma((dof - 1) as f64 / 2., statistic / 2.)
+}
+
+/// The regularized lower incomplete gamma function P(a, x).
+fn regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1. {
+        // Series expansion.
+        let (mut term, mut sum, mut n) = (1. / a, 1. / a, a);
+        while term.abs() > sum.abs() * 1e-15 {
+            n += 1.;
+            term *= x / n;
+            sum += term;
+        }
+        sum * log_prefix.exp()
+    } else {
+        // Continued fraction for Q(a, x), evaluated with Lentz's method.
+        let tiny = 1e-300;
+        let mut b = x + 1. - a;
+        let mut c = 1. / tiny;
+        let mut d = 1. / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1. / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.).abs() < 1e-15 {
+                break;
+            }
+        }
+        1. - log_prefix.exp() * h
+    }
+}
+
+/// Lanczos approximation of ln Γ(x) for positive `x`.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut y = x;
+    let series = COEFFICIENTS.iter().fold(1.000000000190015, |acc, c| {
+        y += 1.;
+        acc + c / y
+    });
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Diffuse;
+
+    #[test]
+    fn gamma_functions_match_known_values() {
+        assert!((ln_gamma(5.) - 24f64.ln()).abs() < 1e-10);
+        assert!((ln_gamma(0.5) - PI.sqrt().ln()).abs() < 1e-10);
+        // P(1, x) = 1 - e^-x.
+        for x in [0.1, 1., 5., 30.] {
+            assert!((regularized_gamma(1., x) - (1. - (-x).exp())).abs() < 1e-10);
+        }
+        // The 95th percentile of a chi-square distribution with 10 degrees of freedom.
+        assert!((regularized_gamma(5., 18.307 / 2.) - 0.95).abs() < 1e-4);
+    }
+
+    #[test]
+    fn diffuse_passes_the_chi_square_test() {
+        let s = Surface {
+            n: Tup(0., 1., 0.),
+            n1: Tup(0., 1., 0.),
+            color: Tup(0.5, 0.5, 0.5),
+            point: Tup::zeros(),
+            uv: (0., 0.),
+        };
+        assert_sampling_matches_pdf(&Diffuse, &s, Tup(0.6, 0.8, 0.), 1);
+    }
+}
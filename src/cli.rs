@@ -0,0 +1,554 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use smallpt::camera::{FisheyeMapping, Projection};
+use smallpt::filter::{FilterMethod, FilterType};
+use smallpt::output::{BitDepth, Compression, Format, OutputOptions, Precision};
+use smallpt::sampler::SamplerType;
+use smallpt::tonemap::{Operator, ToneMapping};
+use smallpt::{IntegrationType, RenderSettings};
+
+/// Render a smallpt scene. Settings given here override those in the scene file.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Scene file to render. Defaults to the bundled Cornell box.
+    #[arg(long)]
+    pub scene: Option<PathBuf>,
+
+    /// Image width in pixels [default: 640].
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Image height in pixels [default: 480].
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// Samples per subpixel. Each pixel receives `samples * subpixels^2` samples [default: 50].
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub samples: Option<u32>,
+
+    /// Size of the subpixel grid; 2 gives smallpt's 2x2 stratification [default: 2].
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub subpixels: Option<u32>,
+
+    /// Maximum number of bounces per path. Without it only Russian roulette ends paths.
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub max_depth: Option<i32>,
+
+    /// Integrator to use: `iterative`, `recursive`, `nee`, `mis`, `mis-power` or
+    /// `mis-balance` [default: iterative].
+    #[arg(long)]
+    pub integrator: Option<IntegrationType>,
+
+    /// Sample generator: `independent`, `stratified`, `halton` or `sobol`
+    /// [default: independent].
+    #[arg(long)]
+    pub sampler: Option<SamplerType>,
+
+    /// Pixel reconstruction filter: `box`, `tent`, `gaussian`, `mitchell` or
+    /// `lanczos` [default: tent].
+    #[arg(long)]
+    pub filter: Option<FilterType>,
+
+    /// Filter radius in pixels. Defaults to the filter's own radius.
+    #[arg(long, value_parser = parse_positive)]
+    pub filter_radius: Option<f64>,
+
+    /// How samples reach the pixels: `importance` samples the filter around
+    /// each pixel, `splat` adds each sample to all pixels it covers
+    /// [default: importance].
+    #[arg(long)]
+    pub filter_method: Option<FilterMethod>,
+
+    /// Stop sampling a pixel once the standard error of its mean is below this
+    /// fraction of the mean, in it and its neighbours. `--samples` then caps
+    /// the samples per pixel.
+    #[arg(long, value_parser = parse_positive)]
+    pub adaptive_threshold: Option<f64>,
+
+    /// Samples per pixel every pixel takes before adaptive sampling may stop
+    /// it [default: 16].
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub adaptive_min_samples: Option<u32>,
+
+    /// Camera projection: `perspective`, `orthographic`, `fisheye` or
+    /// `equirectangular`. Defaults to the one of the scene's camera.
+    #[arg(long)]
+    pub projection: Option<Projection>,
+
+    /// How a `fisheye` projection maps angles to the image: `equidistant` or
+    /// `equisolid` [default: equidistant].
+    #[arg(long)]
+    pub fisheye_mapping: Option<FisheyeMapping>,
+
+    /// Field of view of a `fisheye` projection in degrees, up to 360
+    /// [default: 180].
+    #[arg(long, value_parser = parse_fisheye_fov)]
+    pub fisheye_fov: Option<f64>,
+
+    /// Number of worker threads. Defaults to one per logical core.
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Seed for the samplers. The same seed gives the same image regardless of
+    /// thread count; without one a random seed is used.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Render progressively in passes of this many samples per pixel over the
+    /// whole image, writing the image as it improves.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub pass_samples: Option<u32>,
+
+    /// In progressive mode, write the image every this many passes. Without it
+    /// or `--snapshot-seconds` the image is written after every pass.
+    #[arg(long, requires = "pass_samples", value_parser = clap::value_parser!(u32).range(1..))]
+    pub snapshot_passes: Option<u32>,
+
+    /// In progressive mode, write the image after the first pass that ends
+    /// this many seconds after the last write.
+    #[arg(long, requires = "pass_samples", value_parser = parse_positive)]
+    pub snapshot_seconds: Option<f64>,
+
+    /// Save the render state to this file periodically and on Ctrl-C, so that
+    /// an interrupted render can be continued with `--resume`. Implies
+    /// rendering in passes.
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoints; one is saved after the first pass that
+    /// ends this long after the last.
+    #[arg(long, default_value = "300", requires = "checkpoint", value_parser = parse_positive)]
+    pub checkpoint_seconds: f64,
+
+    /// Continue the render saved in the `--checkpoint` file. The scene and
+    /// render settings must be the same as those of the interrupted render.
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    /// Output image path. The extension picks the format: `.ppm` (binary),
+    /// `.png`, `.pfm` (unclamped floating point radiance) or `.exr` (radiance
+    /// plus albedo, normal, depth, object id and variance layers).
+    #[arg(short, long, default_value = "image.ppm", value_parser = parse_output)]
+    pub output: PathBuf,
+
+    /// Also write a heatmap of the samples taken per pixel to this image.
+    #[arg(long, value_parser = parse_image_output)]
+    pub spp_map: Option<PathBuf>,
+
+    /// Bits per channel for PPM and PNG output: 8 or 16.
+    #[arg(long, default_value = "8")]
+    pub bit_depth: BitDepth,
+
+    /// Exposure adjustment in stops, applied before tone mapping.
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    pub exposure: f64,
+
+    /// Tone mapping operator for PPM and PNG output: `linear`, `reinhard`,
+    /// `reinhard-extended`, `aces` or `hable`.
+    #[arg(long, default_value = "linear")]
+    pub tonemap: Operator,
+
+    /// Luminance mapped to white by `reinhard-extended`. Defaults to the
+    /// brightest pixel of the image.
+    #[arg(long, value_parser = parse_positive)]
+    pub white: Option<f64>,
+
+    /// Sample type of the EXR colour layers: `half` or `float`.
+    #[arg(long, default_value = "float")]
+    pub exr_precision: Precision,
+
+    /// EXR compression: `zip` or `none`.
+    #[arg(long, default_value = "zip")]
+    pub exr_compression: Compression,
+}
+
+impl Args {
+    /// Overrides `settings` with the values given on the command line.
+    /// `camera_projection` is that of the scene's camera, which the fisheye
+    /// flags adjust when no other projection is chosen.
+    pub fn apply(
+        &self,
+        mut settings: RenderSettings,
+        camera_projection: Projection,
+    ) -> Result<RenderSettings, String> {
+        if let Some(width) = self.width {
+            settings.width = width as usize;
+        }
+        if let Some(height) = self.height {
+            settings.height = height as usize;
+        }
+        if let Some(samples) = self.samples {
+            settings.samples = samples;
+        }
+        if let Some(subpixels) = self.subpixels {
+            settings.subpixels = subpixels;
+        }
+        if self.max_depth.is_some() {
+            settings.max_depth = self.max_depth;
+        }
+        if let Some(integrator) = self.integrator {
+            settings.integrator = integrator;
+        }
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler;
+        }
+        if let Some(filter) = self.filter {
+            settings.filter = filter;
+        }
+        if let Some(radius) = self.filter_radius {
+            settings.filter = settings.filter.with_radius(radius);
+        }
+        if let Some(filter_method) = self.filter_method {
+            settings.filter_method = filter_method;
+        }
+        if self.projection.is_some() {
+            settings.projection = self.projection;
+        }
+        if self.fisheye_mapping.is_some() || self.fisheye_fov.is_some() {
+            match settings.projection.unwrap_or(camera_projection) {
+                Projection::Fisheye { mapping, fov } => {
+                    settings.projection = Some(Projection::Fisheye {
+                        mapping: self.fisheye_mapping.unwrap_or(mapping),
+                        fov: self.fisheye_fov.unwrap_or(fov),
+                    });
+                }
+                projection => {
+                    return Err(format!(
+                        "--fisheye-mapping and --fisheye-fov need a fisheye projection, not {}",
+                        projection
+                    ))
+                }
+            }
+        }
+        if self.adaptive_threshold.is_some() {
+            settings.adaptive_threshold = self.adaptive_threshold;
+        }
+        if let Some(min_samples) = self.adaptive_min_samples {
+            settings.adaptive_min_samples = min_samples;
+        }
+        if self.seed.is_some() {
+            settings.seed = self.seed;
+        }
+        Ok(settings)
+    }
+
+    pub fn output_options(&self) -> OutputOptions {
+        let operator = match self.tonemap {
+            Operator::ExtendedReinhard { .. } => Operator::ExtendedReinhard { white: self.white },
+            operator => operator,
+        };
+        OutputOptions {
+            bit_depth: self.bit_depth,
+            precision: self.exr_precision,
+            compression: self.exr_compression,
+            tone_mapping: ToneMapping {
+                exposure: self.exposure,
+                operator,
+            },
+        }
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(r) if r > 0. && r.is_finite() => Ok(r),
+        _ => Err(format!("'{}' is not a positive number", s)),
+    }
+}
+
+fn parse_fisheye_fov(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fov) if fov > 0. && fov <= 360. => Ok(fov),
+        _ => Err(format!("'{}' is not an angle between 0 and 360 degrees", s)),
+    }
+}
+
+fn parse_output(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    Format::from_path(&path)?;
+    Ok(path)
+}
+
+fn parse_image_output(s: &str) -> Result<PathBuf, String> {
+    let path = parse_output(s)?;
+    if Format::from_path(&path)? == Format::Exr {
+        return Err("EXR output holds a whole film; use .ppm, .png or .pfm".to_string());
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_leave_settings_to_the_scene() {
+        let args = Args::try_parse_from(["smallpt-rs"]).unwrap();
+        assert_eq!(args.scene, None);
+        assert_eq!(args.width, None);
+        assert_eq!(args.samples, None);
+        assert_eq!(args.max_depth, None);
+        assert_eq!(args.integrator, None);
+        assert_eq!(args.output, PathBuf::from("image.ppm"));
+        assert_eq!(args.output_options(), OutputOptions::default());
+    }
+
+    #[test]
+    fn parses_render_settings() {
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "--scene",
+            "box.json",
+            "--width",
+            "320",
+            "--height",
+            "240",
+            "-s",
+            "8",
+            "--integrator",
+            "recursive",
+            "--sampler",
+            "sobol",
+            "--seed",
+            "7",
+            "-o",
+            "out.png",
+            "--bit-depth",
+            "16",
+        ])
+        .unwrap();
+        assert_eq!(args.scene, Some(PathBuf::from("box.json")));
+        assert_eq!(args.width, Some(320));
+        assert_eq!(args.height, Some(240));
+        assert_eq!(args.samples, Some(8));
+        assert_eq!(args.integrator, Some(IntegrationType::Recursive));
+        assert_eq!(args.sampler, Some(SamplerType::Sobol));
+        assert_eq!(args.seed, Some(7));
+        assert_eq!(args.output, PathBuf::from("out.png"));
+        assert_eq!(args.bit_depth, BitDepth::Sixteen);
+
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "-o",
+            "out.exr",
+            "--exr-precision",
+            "half",
+            "--exr-compression",
+            "none",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.output_options(),
+            OutputOptions {
+                bit_depth: BitDepth::Eight,
+                precision: Precision::Half,
+                compression: Compression::None,
+                tone_mapping: ToneMapping::default(),
+            }
+        );
+
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "--exposure",
+            "-1.5",
+            "--tonemap",
+            "reinhard-extended",
+            "--white",
+            "4",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.output_options().tone_mapping,
+            ToneMapping {
+                exposure: -1.5,
+                operator: Operator::ExtendedReinhard { white: Some(4.) },
+            }
+        );
+    }
+
+    #[test]
+    fn flags_override_settings() {
+        let args = Args::try_parse_from(["smallpt-rs", "--width", "32", "--max-depth", "3"]).unwrap();
+        let settings = args
+            .apply(RenderSettings::default(), Projection::default())
+            .unwrap();
+        assert_eq!(settings.width, 32);
+        assert_eq!(settings.height, 480);
+        assert_eq!(settings.max_depth, Some(3));
+
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "--filter",
+            "gaussian",
+            "--filter-radius",
+            "2",
+            "--filter-method",
+            "splat",
+            "--projection",
+            "equirectangular",
+        ])
+        .unwrap();
+        let settings = args
+            .apply(RenderSettings::default(), Projection::default())
+            .unwrap();
+        assert_eq!(settings.projection, Some(Projection::Equirectangular));
+        assert_eq!(
+            settings.filter,
+            FilterType::Gaussian {
+                radius: 2.,
+                sigma: 0.5
+            }
+        );
+        assert_eq!(settings.filter_method, FilterMethod::Splat);
+        // A radius alone resizes the default filter.
+        let args = Args::try_parse_from(["smallpt-rs", "--filter-radius", "0.5"]).unwrap();
+        assert_eq!(
+            args.apply(RenderSettings::default(), Projection::default())
+                .unwrap()
+                .filter,
+            FilterType::Tent { radius: 0.5 }
+        );
+    }
+
+    #[test]
+    fn parses_fisheye_settings() {
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "--projection",
+            "fisheye",
+            "--fisheye-mapping",
+            "equisolid",
+            "--fisheye-fov",
+            "220",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.apply(RenderSettings::default(), Projection::default())
+                .unwrap()
+                .projection,
+            Some(Projection::Fisheye {
+                mapping: FisheyeMapping::Equisolid,
+                fov: 220.
+            })
+        );
+        // Without `--projection` they adjust a fisheye camera of the scene.
+        let args = Args::try_parse_from(["smallpt-rs", "--fisheye-fov", "90"]).unwrap();
+        let camera_projection = Projection::Fisheye {
+            mapping: FisheyeMapping::Equisolid,
+            fov: 180.,
+        };
+        assert_eq!(
+            args.apply(RenderSettings::default(), camera_projection)
+                .unwrap()
+                .projection,
+            Some(Projection::Fisheye {
+                mapping: FisheyeMapping::Equisolid,
+                fov: 90.
+            })
+        );
+        // They do not apply to any other projection.
+        assert!(args
+            .apply(RenderSettings::default(), Projection::default())
+            .is_err());
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "--projection",
+            "equirectangular",
+            "--fisheye-mapping",
+            "equisolid",
+        ])
+        .unwrap();
+        let err = args
+            .apply(RenderSettings::default(), camera_projection)
+            .unwrap_err();
+        assert!(err.contains("not equirectangular"), "{}", err);
+        for fov in ["0", "361"] {
+            assert!(Args::try_parse_from([
+                "smallpt-rs",
+                "--projection",
+                "fisheye",
+                "--fisheye-fov",
+                fov
+            ])
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn parses_progressive_settings() {
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "--pass-samples",
+            "4",
+            "--snapshot-passes",
+            "5",
+            "--snapshot-seconds",
+            "30",
+        ])
+        .unwrap();
+        assert_eq!(args.pass_samples, Some(4));
+        assert_eq!(args.snapshot_passes, Some(5));
+        assert_eq!(args.snapshot_seconds, Some(30.));
+        // Snapshots only make sense for progressive renders.
+        assert!(Args::try_parse_from(["smallpt-rs", "--snapshot-passes", "2"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--pass-samples", "0"]).is_err());
+    }
+
+    #[test]
+    fn parses_checkpoint_settings() {
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "--checkpoint",
+            "render.ckpt",
+            "--checkpoint-seconds",
+            "60",
+            "--resume",
+        ])
+        .unwrap();
+        assert_eq!(args.checkpoint, Some(PathBuf::from("render.ckpt")));
+        assert_eq!(args.checkpoint_seconds, 60.);
+        assert!(args.resume);
+        assert!(Args::try_parse_from(["smallpt-rs", "--resume"]).is_err());
+    }
+
+    #[test]
+    fn parses_adaptive_settings() {
+        let args = Args::try_parse_from([
+            "smallpt-rs",
+            "--adaptive-threshold",
+            "0.02",
+            "--adaptive-min-samples",
+            "32",
+            "--spp-map",
+            "spp.png",
+        ])
+        .unwrap();
+        let settings = args
+            .apply(RenderSettings::default(), Projection::default())
+            .unwrap();
+        assert_eq!(settings.adaptive_threshold, Some(0.02));
+        assert_eq!(settings.adaptive_min_samples, 32);
+        assert_eq!(args.spp_map, Some(PathBuf::from("spp.png")));
+        assert!(Args::try_parse_from(["smallpt-rs", "--spp-map", "spp.exr"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--adaptive-min-samples", "0"]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert!(Args::try_parse_from(["smallpt-rs", "--width", "0"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--samples", "-3"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--integrator", "bdpt"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--sampler", "random"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--filter-radius", "0"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "-o", "image.jpg"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "-o", "image"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--bit-depth", "12"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--exr-compression", "piz"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--tonemap", "drago"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--projection", "pinhole"]).is_err());
+        assert!(Args::try_parse_from(["smallpt-rs", "--white", "-1"]).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use super::material::{reflect, specular, BsdfSample, Material, Surface};
+use super::microfacet::{fresnel_conductor, Frame, Ggx};
+use super::tup::Tup;
+
+/// A metal whose microfacets follow the GGX distribution. Its reflectance
+/// comes from the complex index of refraction `eta + i k` of each color
+/// channel and is tinted by the object's color. Without roughness it is a
+/// polished mirror.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conductor {
+    pub eta: Tup,
+    pub k: Tup,
+    pub distribution: Ggx,
+}
+
+impl Conductor {
+    pub fn new(eta: Tup, k: Tup) -> Self {
+        Conductor {
+            eta,
+            k,
+            distribution: Ggx::from_roughness((0., 0.)),
+        }
+    }
+
+    /// Roughness between 0 and 1 along the two tangents of the surface frame;
+    /// see [`Frame`].
+    pub fn with_roughness(self, roughness: (f64, f64)) -> Self {
+        Conductor {
+            distribution: Ggx::from_roughness(roughness),
+            ..self
+        }
+    }
+
+    /// A named metal: `gold`, `copper`, `aluminium` or `silver`, with the
+    /// index of refraction at the red, green and blue wavelengths.
+    pub fn preset(name: &str) -> Option<Self> {
+        let (eta, k) = match name {
+            "gold" => (Tup(0.143, 0.374, 1.442), Tup(3.983, 2.385, 1.603)),
+            "copper" => (Tup(0.200, 0.924, 1.102), Tup(3.912, 2.452, 2.142)),
+            "aluminium" | "aluminum" => (Tup(1.657, 0.880, 0.521), Tup(9.224, 6.270, 4.837)),
+            "silver" => (Tup(0.155, 0.117, 0.138), Tup(4.828, 3.122, 2.147)),
+            _ => return None,
+        };
+        Some(Conductor::new(eta, k))
+    }
+
+    fn fresnel(&self, cos: f64) -> Tup {
+        fresnel_conductor(cos, self.eta, self.k)
+    }
+}
+
+impl Material for Conductor {
+    fn sample(&self, s: &Surface, wo: Tup, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let frame = Frame::new(s.n1);
+        let wo_l = frame.to_local(wo);
+        if wo_l.2 <= 0. {
+            return None;
+        }
+        if self.distribution.is_smooth() {
+            let weight = s.color * self.fresnel(wo_l.2);
+            return Some(specular(reflect(wo, s.n1), weight, 1.));
+        }
+
+        let wm = self.distribution.sample_visible(wo_l, u);
+        let wi_l = reflect(wo_l, wm);
+        if wi_l.2 <= 0. {
+            return None;
+        }
+        let d = &self.distribution;
+        Some(BsdfSample {
+            wi: frame.from_local(wi_l),
+            weight: s.color * self.fresnel(wo_l.dot(wm)) * (d.g(wo_l, wi_l) / d.g1(wo_l)),
+            pdf: d.visible_d(wo_l, wm) / (4. * wo_l.dot(wm)),
+            specular: false,
+        })
+    }
+
+    fn eval(&self, s: &Surface, wo: Tup, wi: Tup) -> Tup {
+        if self.distribution.is_smooth() {
+            return Tup::zeros();
+        }
+        let frame = Frame::new(s.n1);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wo.2 <= 0. || wi.2 <= 0. {
+            return Tup::zeros();
+        }
+        let wm = (wo + wi).norm();
+        let d = &self.distribution;
+        s.color * self.fresnel(wo.dot(wm)) * (d.d(wm) * d.g(wo, wi) / (4. * wo.2 * wi.2))
+    }
+
+    fn pdf(&self, s: &Surface, wo: Tup, wi: Tup) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.;
+        }
+        let frame = Frame::new(s.n1);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wo.2 <= 0. || wi.2 <= 0. {
+            return 0.;
+        }
+        let wm = (wo + wi).norm();
+        self.distribution.visible_d(wo, wm) / (4. * wo.dot(wm))
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chi2::assert_sampling_matches_pdf;
+
+    fn surface() -> Surface {
+        Surface {
+            n: Tup(0., 0., 1.),
+            n1: Tup(0., 0., 1.),
+            color: Tup::ones(),
+            point: Tup::zeros(),
+            uv: (0., 0.),
+        }
+    }
+
+    #[test]
+    fn rough_conductors_pass_the_chi_square_test() {
+        let gold = Conductor::preset("gold").unwrap();
+        for (i, (roughness, wo)) in [
+            ((0.5, 0.5), Tup(0., 0., 1.)),
+            ((0.3, 0.3), Tup(0.6, 0., 0.8)),
+            ((0.8, 0.8), Tup(0.96, 0., 0.28)),
+            ((0.2, 0.7), Tup(0.48, 0.36, 0.8)),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let metal = gold.with_roughness(roughness);
+            assert_sampling_matches_pdf(&metal, &surface(), wo, i as u64);
+        }
+    }
+
+    #[test]
+    fn polished_conductors_are_mirrors() {
+        let copper = Conductor::preset("copper").unwrap();
+        assert!(copper.is_specular());
+        let wo = Tup(0.6, 0., 0.8);
+        let b = copper.sample(&surface(), wo, 0.5, (0.5, 0.5)).unwrap();
+        assert_eq!(b.wi, Tup(-0.6, 0., 0.8));
+        assert!(b.specular);
+        // Copper reflects red more than blue.
+        assert!(b.weight.0 > 0.9 && b.weight.2 < 0.7, "{:?}", b.weight);
+        assert_eq!(copper.eval(&surface(), wo, b.wi), Tup::zeros());
+    }
+
+    /// A white furnace test: with roughness, some light is lost to masking,
+    /// but never more than reaches the surface, and less the smoother it is.
+    #[test]
+    fn rough_conductors_do_not_create_energy() {
+        let white = Conductor::new(Tup::zeros(), Tup(1e9, 1e9, 1e9));
+        let wo = Tup(0.6, 0., 0.8);
+        let albedo = |r: f64| {
+            let metal = white.with_roughness((r, r));
+            let n = 100_000;
+            (0..n)
+                .filter_map(|i| {
+                    let u = ((i as f64 + 0.5) / n as f64, (i as f64 * 0.618034).fract());
+                    metal.sample(&surface(), wo, 0.5, u)
+                })
+                .map(|b| b.weight.0)
+                .sum::<f64>()
+                / n as f64
+        };
+        let (smooth, rough) = (albedo(0.1), albedo(0.9));
+        assert!(smooth <= 1. + 1e-9 && smooth > 0.99, "{}", smooth);
+        // Rough surfaces lose the light that scatters more than once between
+        // microfacets, a little over half of it here.
+        assert!(rough < smooth && rough > 0.4, "{}", rough);
+    }
+
+    #[test]
+    fn presets_are_known_metals() {
+        for name in ["gold", "copper", "aluminium", "aluminum", "silver"] {
+            assert!(Conductor::preset(name).is_some(), "{}", name);
+        }
+        assert!(Conductor::preset("unobtainium").is_none());
+    }
+}
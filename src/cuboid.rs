@@ -0,0 +1,153 @@
+use super::bvh::Aabb;
+use super::ray::Ray;
+use super::shape::{Hit, Intersection, Shape, EPS};
+use super::tup::Tup;
+
+/// A solid axis-aligned box.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cuboid {
+    pub min: Tup,
+    pub max: Tup,
+}
+
+impl Cuboid {
+    pub fn new(min: Tup, max: Tup) -> Result<Self, String> {
+        if !(min.0 < max.0 && min.1 < max.1 && min.2 < max.2) {
+            return Err(format!(
+                "box min {:?} must be below max {:?} on every axis",
+                min, max
+            ));
+        }
+        Ok(Cuboid { min, max })
+    }
+}
+
+fn axes(t: Tup) -> [f64; 3] {
+    [t.0, t.1, t.2]
+}
+
+impl Shape for Cuboid {
+    fn bounds(&self) -> Aabb {
+        Aabb {
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    /// Slab test. The axis of the face hit is kept in the intersection.
+    fn intersect(&self, ray: &Ray, t_max: f64) -> Option<Intersection> {
+        let (o, d) = (axes(ray.o), axes(ray.d));
+        let (min, max) = (axes(self.min), axes(self.max));
+        let (mut near, mut far) = (f64::NEG_INFINITY, f64::INFINITY);
+        let (mut near_axis, mut far_axis) = (0, 0);
+        for axis in 0..3 {
+            let inv = 1. / d[axis];
+            let mut t0 = (min[axis] - o[axis]) * inv;
+            let mut t1 = (max[axis] - o[axis]) * inv;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // A ray inside a slab plane gives NaN; it does not limit the range.
+            if t0 > near {
+                near = t0;
+                near_axis = axis;
+            }
+            if t1 < far {
+                far = t1;
+                far_axis = axis;
+            }
+        }
+        if near > far {
+            return None;
+        }
+        let (t, axis) = if near > EPS {
+            (near, near_axis)
+        } else {
+            (far, far_axis)
+        };
+        (t > EPS && t < t_max).then_some(Intersection {
+            t,
+            primitive: axis,
+            b: (0., 0.),
+        })
+    }
+
+    /// The normal points out of the box. The uv coordinates run from 0 to 1
+    /// across the face, along the next two axes in cyclic order.
+    fn hit(&self, ray: &Ray, i: &Intersection) -> Hit {
+        let point = ray.o + ray.d * i.t;
+        let axis = i.primitive;
+        let (p, min, max) = (axes(point), axes(self.min), axes(self.max));
+        let upper = p[axis] - min[axis] > max[axis] - p[axis];
+        let mut n = [0.; 3];
+        n[axis] = if upper { 1. } else { -1. };
+        let normal = Tup(n[0], n[1], n[2]);
+        let along = |a: usize| (p[a] - min[a]) / (max[a] - min[a]);
+        Hit {
+            t: i.t,
+            point,
+            normal,
+            shading_normal: normal,
+            uv: (along((axis + 1) % 3), along((axis + 2) % 3)),
+            object: 0,
+            primitive: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Cuboid {
+        Cuboid::new(Tup(0., 0., 0.), Tup(1., 2., 3.)).unwrap()
+    }
+
+    #[test]
+    fn rays_hit_the_faces_they_cross() {
+        let cuboid = unit_box();
+        for (o, d, t, normal, uv) in [
+            (
+                Tup(0.5, 1., 5.),
+                Tup(0., 0., -1.),
+                2.,
+                Tup(0., 0., 1.),
+                (0.5, 0.5),
+            ),
+            (
+                Tup(-1., 0.5, 0.75),
+                Tup(1., 0., 0.),
+                1.,
+                Tup(-1., 0., 0.),
+                (0.25, 0.25),
+            ),
+            (
+                Tup(0.5, 1., 1.5),
+                Tup(0., 1., 0.),
+                1.,
+                Tup(0., 1., 0.),
+                (0.5, 0.5),
+            ),
+        ] {
+            let ray = Ray { o, d };
+            let i = cuboid.intersect(&ray, f64::INFINITY).unwrap();
+            assert_eq!(i.t, t);
+            let hit = cuboid.hit(&ray, &i);
+            assert_eq!(hit.normal, normal);
+            assert_eq!(hit.uv, uv);
+        }
+    }
+
+    #[test]
+    fn rays_miss_the_box() {
+        let cuboid = unit_box();
+        for (o, d) in [
+            (Tup(2., 1., 5.), Tup(0., 0., -1.)),
+            (Tup(0.5, 1., 5.), Tup(0., 0., 1.)),
+            (Tup(0.5, 3., 1.), Tup(1., 0., 0.)),
+        ] {
+            assert!(cuboid.intersect(&Ray { o, d }, f64::INFINITY).is_none());
+        }
+        assert!(Cuboid::new(Tup::zeros(), Tup(1., 0., 1.)).is_err());
+    }
+}
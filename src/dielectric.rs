@@ -0,0 +1,231 @@
+use super::material::{beer_lambert, reflect, specular, BsdfSample, Material, Surface};
+use super::microfacet::{fresnel_dielectric, refract, Frame, Ggx};
+use super::tup::Tup;
+
+/// A dielectric such as glass or water whose surface microfacets follow the
+/// GGX distribution, after Walter et al., "Microfacet Models for Refraction
+/// through Rough Surfaces" (2007). Unlike [`Glass`](super::material::Glass) it
+/// uses the exact Fresnel equations. Light inside it is absorbed as in
+/// `Glass`, and the outside is taken to be vacuum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dielectric {
+    pub ior: f64,
+    pub absorption: Tup,
+    pub distribution: Ggx,
+}
+
+impl Dielectric {
+    pub fn new(ior: f64) -> Self {
+        Dielectric {
+            ior,
+            absorption: Tup::zeros(),
+            distribution: Ggx::from_roughness((0., 0.)),
+        }
+    }
+
+    pub fn with_absorption(self, absorption: Tup) -> Self {
+        Dielectric { absorption, ..self }
+    }
+
+    /// Roughness between 0 and 1 along the two tangents of the surface frame;
+    /// see [`Frame`].
+    pub fn with_roughness(self, roughness: (f64, f64)) -> Self {
+        Dielectric {
+            distribution: Ggx::from_roughness(roughness),
+            ..self
+        }
+    }
+
+    /// The microfacet normal that scatters `wo` into `wi`, facing out of the
+    /// surface, with the relative index of refraction along the way. `None`
+    /// when no microfacet visible from both directions does so.
+    fn half_vector(&self, wo: Tup, wi: Tup) -> Option<(Tup, f64)> {
+        if wo.2 == 0. || wi.2 == 0. {
+            return None;
+        }
+        let etap = if wo.2 * wi.2 > 0. {
+            1.
+        } else if wo.2 > 0. {
+            self.ior
+        } else {
+            1. / self.ior
+        };
+        let wm = wi * etap + wo;
+        if wm.dot(wm) == 0. {
+            return None;
+        }
+        let wm = wm.norm();
+        let wm = if wm.2 < 0. { wm * -1. } else { wm };
+        if wm.dot(wi) * wi.2 < 0. || wm.dot(wo) * wo.2 < 0. {
+            return None;
+        }
+        Some((wm, etap))
+    }
+}
+
+impl Material for Dielectric {
+    fn sample(&self, s: &Surface, wo: Tup, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let frame = Frame::new(s.n);
+        let wo_l = frame.to_local(wo);
+        if wo_l.2 == 0. {
+            return None;
+        }
+        if self.distribution.is_smooth() {
+            let r = fresnel_dielectric(wo_l.2, self.ior);
+            return if uc < r {
+                let wi = Tup(-wo_l.0, -wo_l.1, wo_l.2);
+                Some(specular(frame.from_local(wi), s.color, r))
+            } else {
+                let (wi, etap) = refract(wo_l, Tup(0., 0., 1.), self.ior)?;
+                let weight = s.color * (1. / (etap * etap));
+                Some(specular(frame.from_local(wi), weight, 1. - r))
+            };
+        }
+
+        let d = &self.distribution;
+        let wm = d.sample_visible(wo_l, u);
+        let r = fresnel_dielectric(wo_l.dot(wm), self.ior);
+        let (wi_l, pdf, etap) = if uc < r {
+            let wi = reflect(wo_l, wm);
+            if wo_l.2 * wi.2 <= 0. {
+                return None;
+            }
+            (
+                wi,
+                d.visible_d(wo_l, wm) / (4. * wo_l.dot(wm).abs()) * r,
+                1.,
+            )
+        } else {
+            let (wi, etap) = refract(wo_l, wm, self.ior)?;
+            if wo_l.2 * wi.2 >= 0. {
+                return None;
+            }
+            let denom = wi.dot(wm) + wo_l.dot(wm) / etap;
+            let dwm_dwi = wi.dot(wm).abs() / (denom * denom);
+            (wi, d.visible_d(wo_l, wm) * dwm_dwi * (1. - r), etap)
+        };
+        Some(BsdfSample {
+            wi: frame.from_local(wi_l),
+            weight: s.color * (d.g(wo_l, wi_l) / (d.g1(wo_l) * etap * etap)),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn eval(&self, s: &Surface, wo: Tup, wi: Tup) -> Tup {
+        if self.distribution.is_smooth() {
+            return Tup::zeros();
+        }
+        let frame = Frame::new(s.n);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        let Some((wm, etap)) = self.half_vector(wo, wi) else {
+            return Tup::zeros();
+        };
+        let d = &self.distribution;
+        let f = fresnel_dielectric(wo.dot(wm), self.ior);
+        let value = if etap == 1. {
+            d.d(wm) * d.g(wo, wi) * f / (4. * wi.2 * wo.2).abs()
+        } else {
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            let denom = denom * denom * wi.2 * wo.2;
+            d.d(wm) * (1. - f) * d.g(wo, wi) * (wi.dot(wm) * wo.dot(wm) / denom).abs()
+                / (etap * etap)
+        };
+        s.color * value
+    }
+
+    fn pdf(&self, s: &Surface, wo: Tup, wi: Tup) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.;
+        }
+        let frame = Frame::new(s.n);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        let Some((wm, etap)) = self.half_vector(wo, wi) else {
+            return 0.;
+        };
+        let d = &self.distribution;
+        let r = fresnel_dielectric(wo.dot(wm), self.ior);
+        if etap == 1. {
+            d.visible_d(wo, wm) / (4. * wo.dot(wm).abs()) * r
+        } else {
+            let denom = wi.dot(wm) + wo.dot(wm) / etap;
+            d.visible_d(wo, wm) * wi.dot(wm).abs() / (denom * denom) * (1. - r)
+        }
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    fn transmittance(&self, _s: &Surface, distance: f64) -> Tup {
+        beer_lambert(self.absorption, distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chi2::assert_sampling_matches_pdf;
+
+    fn surface(inside: bool) -> Surface {
+        Surface {
+            n: Tup(0., 0., 1.),
+            n1: Tup(0., 0., if inside { -1. } else { 1. }),
+            color: Tup::ones(),
+            point: Tup::zeros(),
+            uv: (0., 0.),
+        }
+    }
+
+    #[test]
+    fn rough_dielectrics_pass_the_chi_square_test() {
+        for (i, (roughness, wo, ior)) in [
+            ((0.5, 0.5), Tup(0., 0., 1.), 1.5),
+            ((0.3, 0.3), Tup(0.6, 0., 0.8), 1.5),
+            ((0.7, 0.7), Tup(0.96, 0., 0.28), 1.33),
+            ((0.2, 0.6), Tup(0.48, 0.36, 0.8), 2.4),
+            // From inside, including beyond the critical angle.
+            ((0.5, 0.5), Tup(0., 0., -1.), 1.5),
+            ((0.4, 0.4), Tup(0.8, 0., -0.6), 1.5),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let glass = Dielectric::new(ior).with_roughness(roughness);
+            assert_sampling_matches_pdf(&glass, &surface(wo.2 < 0.), wo, i as u64);
+        }
+    }
+
+    /// Without absorption, rough glass keeps all the energy that is not lost
+    /// to masking, and all of it once the change of radiance on crossing the
+    /// surface is undone.
+    #[test]
+    fn rough_dielectrics_conserve_energy() {
+        let glass = Dielectric::new(1.5).with_roughness((0.1, 0.1));
+        let wo = Tup(0.6, 0., 0.8);
+        let n = 100_000;
+        let total: f64 = (0..n)
+            .filter_map(|i| {
+                let u = ((i as f64 + 0.5) / n as f64, (i as f64 * 0.618034).fract());
+                glass.sample(&surface(false), wo, (i as f64 * 0.414214).fract(), u)
+            })
+            .map(|b| b.weight.0 * if b.wi.2 < 0. { 1.5 * 1.5 } else { 1. })
+            .sum::<f64>()
+            / n as f64;
+        assert!(total <= 1.001 && total > 0.98, "{}", total);
+    }
+
+    #[test]
+    fn smooth_dielectrics_split_by_fresnel() {
+        let glass = Dielectric::new(1.5);
+        assert!(glass.is_specular());
+        let s = surface(false);
+        let wo = Tup(0., 0., 1.);
+        let reflected = glass.sample(&s, wo, 0.01, (0.5, 0.5)).unwrap();
+        assert_eq!(reflected.wi, wo);
+        assert!((reflected.pdf - 0.04).abs() < 1e-12);
+        let refracted = glass.sample(&s, wo, 0.5, (0.5, 0.5)).unwrap();
+        assert!((refracted.wi - Tup(0., 0., -1.)).dot(Tup::ones()).abs() < 1e-12);
+        assert!((refracted.pdf - 0.96).abs() < 1e-12);
+    }
+}
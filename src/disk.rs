@@ -0,0 +1,105 @@
+use std::f64::consts::PI;
+
+use super::bvh::Aabb;
+use super::plane::{plane_distance, unit_normal};
+use super::ray::Ray;
+use super::shape::{Hit, Intersection, Shape};
+use super::tup::Tup;
+
+/// A flat disk around `center`, facing along `normal`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disk {
+    pub center: Tup,
+    pub normal: Tup,
+    pub radius: f64,
+}
+
+impl Disk {
+    pub fn new(center: Tup, normal: Tup, radius: f64) -> Result<Self, String> {
+        if !(radius > 0. && radius.is_finite()) {
+            return Err(format!("radius must be positive, got {}", radius));
+        }
+        Ok(Disk {
+            center,
+            normal: unit_normal(normal)?,
+            radius,
+        })
+    }
+}
+
+impl Shape for Disk {
+    fn bounds(&self) -> Aabb {
+        let n = self.normal;
+        let extent = |c: f64| self.radius * (1. - c * c).max(0.).sqrt();
+        let e = Tup(extent(n.0), extent(n.1), extent(n.2));
+        Aabb {
+            min: self.center - e,
+            max: self.center + e,
+        }
+    }
+
+    fn intersect(&self, ray: &Ray, t_max: f64) -> Option<Intersection> {
+        let t = plane_distance(ray, self.center, self.normal, t_max)?;
+        let offset = ray.o + ray.d * t - self.center;
+        (offset.dot(offset) <= self.radius * self.radius).then_some(Intersection {
+            t,
+            primitive: 0,
+            b: (0., 0.),
+        })
+    }
+
+    /// The uv coordinates are polar: u is the distance from the center as a
+    /// fraction of the radius, v the angle as a fraction of a turn.
+    fn hit(&self, ray: &Ray, i: &Intersection) -> Hit {
+        let point = ray.o + ray.d * i.t;
+        let offset = point - self.center;
+        let (u, v) = self.normal.basis();
+        let phi = offset.dot(v).atan2(offset.dot(u));
+        let turn = if phi < 0. {
+            phi / (2. * PI) + 1.
+        } else {
+            phi / (2. * PI)
+        };
+        Hit {
+            t: i.t,
+            point,
+            normal: self.normal,
+            shading_normal: self.normal,
+            uv: (offset.dot(offset).sqrt() / self.radius, turn),
+            object: 0,
+            primitive: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_hits_inside_the_radius_only() {
+        let disk = Disk::new(Tup(0., 1., 0.), Tup(0., -1., 0.), 2.).unwrap();
+        let down = |x: f64, z: f64| Ray {
+            o: Tup(x, 3., z),
+            d: Tup(0., -1., 0.),
+        };
+        let i = disk.intersect(&down(1., 1.), f64::INFINITY).unwrap();
+        assert_eq!(i.t, 2.);
+        let hit = disk.hit(&down(1., 1.), &i);
+        assert_eq!(hit.normal, Tup(0., -1., 0.));
+        assert!((hit.uv.0 - 2f64.sqrt() / 2.).abs() < 1e-12);
+        assert!(disk.intersect(&down(1.5, 1.5), f64::INFINITY).is_none());
+        assert!(Disk::new(Tup::zeros(), Tup(0., 1., 0.), 0.).is_err());
+    }
+
+    #[test]
+    fn bounds_are_tight() {
+        let disk = Disk::new(Tup(1., 2., 3.), Tup(0., 0., 1.), 2.).unwrap();
+        assert_eq!(disk.bounds().min, Tup(-1., 0., 3.));
+        assert_eq!(disk.bounds().max, Tup(3., 4., 3.));
+        let tilted = Disk::new(Tup::zeros(), Tup(1., 1., 0.), 1.).unwrap();
+        let b = tilted.bounds();
+        assert!((b.max.0 - 0.5f64.sqrt()).abs() < 1e-12);
+        assert_eq!(b.max.2, 1.);
+    }
+}
@@ -0,0 +1,410 @@
+use std::f64::consts::PI;
+
+use super::material::{reflect, sample_cosine, BsdfSample, Material, Surface};
+use super::microfacet::{fresnel_dielectric, refract, Frame, Ggx};
+use super::tup::Tup;
+
+/// Disney's principled BSDF, after Burley, "Physically Based Shading at
+/// Disney" (2012), with the specular transmission of its 2015 extension. The
+/// base color is the object's color, and every parameter but `ior` is between
+/// 0 and 1:
+///
+/// - `metallic` blends from a dielectric to a metal, whose reflections take
+///   the base color
+/// - `roughness` widens the specular highlight and flattens the diffuse lobe
+/// - `specular` scales the reflectance of dielectrics; the default 0.5 is
+///   that of an index of refraction of 1.5
+/// - `sheen` adds a white rim at grazing angles, as on cloth
+/// - `clearcoat` adds a second, colorless specular layer, as on car paint,
+///   whose highlight `clearcoat_gloss` sharpens
+/// - `transmission` turns the diffuse lobe of dielectrics into rough
+///   refraction into a medium with index of refraction `ior`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Principled {
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub sheen: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub ior: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            metallic: 0.,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.,
+            clearcoat: 0.,
+            clearcoat_gloss: 1.,
+            transmission: 0.,
+            ior: 1.5,
+        }
+    }
+}
+
+/// The lobes of a principled BSDF.
+#[derive(Clone, Copy)]
+enum Lobe {
+    Diffuse,
+    Specular,
+    Clearcoat,
+    Transmission,
+}
+
+const LOBES: [Lobe; 4] = [
+    Lobe::Diffuse,
+    Lobe::Specular,
+    Lobe::Clearcoat,
+    Lobe::Transmission,
+];
+
+impl Principled {
+    /// How much each lobe contributes, in the order of `LOBES`. The specular
+    /// lobe's strength comes from its Fresnel term instead.
+    fn weights(&self) -> [f64; 4] {
+        let dielectric = 1. - self.metallic;
+        [
+            dielectric * (1. - self.transmission),
+            1.,
+            0.25 * self.clearcoat,
+            dielectric * self.transmission,
+        ]
+    }
+
+    /// The probabilities of sampling each lobe for light leaving towards
+    /// `wo`. The specular and transmission lobes split their share by the
+    /// Fresnel reflectance of the macro surface, keeping some transmission
+    /// where that is total, since rough microfacets may still transmit.
+    fn probabilities(&self, wo: Tup, eta: f64) -> [f64; 4] {
+        let [diffuse, _, clearcoat, transmission] = self.weights();
+        let f = self.dielectric_fresnel(wo.2, eta);
+        let w = [
+            diffuse,
+            (1. - self.metallic) * f + self.metallic,
+            clearcoat,
+            transmission * (1. - f).max(0.1),
+        ];
+        let total: f64 = w.iter().sum();
+        w.map(|w| w / total)
+    }
+
+    /// Fresnel reflectance of the dielectric part at `cos` to the microfacet
+    /// normal. Its opaque part follows Schlick from the reflectance `specular`
+    /// sets, and its transmissive part the exact equations with relative index
+    /// of refraction `eta`, so that its reflection and refraction add up to
+    /// one and it totally reflects what cannot leave.
+    fn dielectric_fresnel(&self, cos: f64, eta: f64) -> f64 {
+        let f0 = 0.08 * self.specular;
+        let opaque = f0 + (1. - f0) * schlick_weight(cos);
+        (1. - self.transmission) * opaque + self.transmission * fresnel_dielectric(cos, eta)
+    }
+
+    fn distribution(&self) -> Ggx {
+        Ggx::from_roughness((self.roughness, self.roughness))
+    }
+
+    fn clearcoat_alpha(&self) -> f64 {
+        0.1 + (0.001 - 0.1) * self.clearcoat_gloss
+    }
+
+    /// The shading frame turned towards `wo`, so that reflection always
+    /// happens above the surface, with the relative index of refraction of
+    /// the other side.
+    fn frame(&self, s: &Surface, wo: Tup) -> (Frame, f64) {
+        if s.n.dot(wo) < 0. {
+            (Frame::new(s.n * -1.), 1. / self.ior)
+        } else {
+            (Frame::new(s.n), self.ior)
+        }
+    }
+
+    /// The microfacet normal that refracts `wo` into `wi`, if it faces both.
+    fn transmission_half_vector(wo: Tup, wi: Tup, eta: f64) -> Option<Tup> {
+        let wm = wi * eta + wo;
+        if wm.dot(wm) == 0. {
+            return None;
+        }
+        let wm = wm.norm();
+        let wm = if wm.2 < 0. { wm * -1. } else { wm };
+        if wm.dot(wo) <= 0. || wm.dot(wi) >= 0. {
+            return None;
+        }
+        Some(wm)
+    }
+
+    /// The BSDF in the local shading frame, with `wo` above the surface.
+    fn eval_local(&self, color: Tup, wo: Tup, wi: Tup, eta: f64) -> Tup {
+        if wo.2 <= 0. || wi.2 == 0. {
+            return Tup::zeros();
+        }
+        let [diffuse, _, clearcoat, transmission] = self.weights();
+        let d = self.distribution();
+        if wi.2 < 0. {
+            if transmission == 0. {
+                return Tup::zeros();
+            }
+            let Some(wm) = Self::transmission_half_vector(wo, wi, eta) else {
+                return Tup::zeros();
+            };
+            let f = fresnel_dielectric(wo.dot(wm), eta);
+            let denom = wi.dot(wm) + wo.dot(wm) / eta;
+            let value = d.d(wm) * d.g(wo, wi) * (1. - f) * (wi.dot(wm) * wo.dot(wm)).abs()
+                / (wi.2.abs() * wo.2 * denom * denom * eta * eta);
+            // Light crossing an object passes its surface twice.
+            let tint = Tup(color.0.sqrt(), color.1.sqrt(), color.2.sqrt());
+            return tint * (transmission * value);
+        }
+
+        let wh = (wo + wi).norm();
+        let cos_d = wi.dot(wh);
+        let fd90 = 0.5 + 2. * self.roughness * cos_d * cos_d;
+        let retro =
+            (1. + (fd90 - 1.) * schlick_weight(wo.2)) * (1. + (fd90 - 1.) * schlick_weight(wi.2));
+        let diffuse_value =
+            color * (retro / PI) + Tup::ones() * (self.sheen * schlick_weight(cos_d));
+
+        let metal = color + (Tup::ones() - color) * schlick_weight(cos_d);
+        let fresnel = Tup::ones() * ((1. - self.metallic) * self.dielectric_fresnel(cos_d, eta))
+            + metal * self.metallic;
+        let specular = fresnel * (d.d(wh) * d.g(wo, wi) / (4. * wo.2 * wi.2));
+
+        let coat = clearcoat_masking();
+        let clearcoat_value = (0.04 + 0.96 * schlick_weight(cos_d))
+            * gtr1(wh.2, self.clearcoat_alpha())
+            * coat.g1(wo)
+            * coat.g1(wi)
+            / (4. * wo.2 * wi.2);
+
+        diffuse_value * diffuse + specular + Tup::ones() * (clearcoat * clearcoat_value)
+    }
+
+    /// The pdf of `wi` in the local shading frame, with `wo` above the
+    /// surface.
+    fn pdf_local(&self, wo: Tup, wi: Tup, eta: f64) -> f64 {
+        if wo.2 <= 0. || wi.2 == 0. {
+            return 0.;
+        }
+        let [diffuse, specular, clearcoat, transmission] = self.probabilities(wo, eta);
+        let d = self.distribution();
+        if wi.2 < 0. {
+            if transmission == 0. {
+                return 0.;
+            }
+            let Some(wm) = Self::transmission_half_vector(wo, wi, eta) else {
+                return 0.;
+            };
+            let denom = wi.dot(wm) + wo.dot(wm) / eta;
+            return transmission * d.visible_d(wo, wm) * wi.dot(wm).abs() / (denom * denom);
+        }
+        let wh = (wo + wi).norm();
+        diffuse * wi.2 / PI
+            + specular * d.visible_d(wo, wh) / (4. * wo.dot(wh))
+            + clearcoat * gtr1(wh.2, self.clearcoat_alpha()) * wh.2 / (4. * wo.dot(wh))
+    }
+}
+
+impl Material for Principled {
+    fn sample(&self, s: &Surface, wo: Tup, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let (frame, eta) = self.frame(s, wo);
+        let wo_l = frame.to_local(wo);
+        if wo_l.2 == 0. {
+            return None;
+        }
+        let mut uc = uc;
+        let mut lobe = Lobe::Transmission;
+        for (l, p) in LOBES.into_iter().zip(self.probabilities(wo_l, eta)) {
+            if uc < p {
+                lobe = l;
+                break;
+            }
+            uc -= p;
+        }
+        let wi_l = match lobe {
+            Lobe::Diffuse => sample_cosine(Tup(0., 0., 1.), u),
+            Lobe::Specular => reflect(wo_l, self.distribution().sample_visible(wo_l, u)),
+            Lobe::Clearcoat => reflect(wo_l, sample_gtr1(self.clearcoat_alpha(), u)),
+            Lobe::Transmission => {
+                let wm = self.distribution().sample_visible(wo_l, u);
+                refract(wo_l, wm, eta)?.0
+            }
+        };
+        let pdf = self.pdf_local(wo_l, wi_l, eta);
+        if pdf == 0. {
+            return None;
+        }
+        let f = self.eval_local(s.color, wo_l, wi_l, eta);
+        Some(BsdfSample {
+            wi: frame.from_local(wi_l),
+            weight: f * (wi_l.2.abs() / pdf),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn eval(&self, s: &Surface, wo: Tup, wi: Tup) -> Tup {
+        let (frame, eta) = self.frame(s, wo);
+        self.eval_local(s.color, frame.to_local(wo), frame.to_local(wi), eta)
+    }
+
+    fn pdf(&self, s: &Surface, wo: Tup, wi: Tup) -> f64 {
+        let (frame, eta) = self.frame(s, wo);
+        self.pdf_local(frame.to_local(wo), frame.to_local(wi), eta)
+    }
+}
+
+/// Schlick's approximation of how the Fresnel reflectance grows towards
+/// grazing angles.
+fn schlick_weight(cos: f64) -> f64 {
+    (1. - cos).clamp(0., 1.).powi(5)
+}
+
+/// The masking of the clearcoat layer, which Disney fixes at a roughness of
+/// 0.5.
+fn clearcoat_masking() -> Ggx {
+    Ggx::from_roughness((0.5, 0.5))
+}
+
+/// The generalized Trowbridge-Reitz distribution with an exponent of 1, which
+/// has a longer tail than GGX, of the normal at `cos` to the surface's.
+fn gtr1(cos: f64, alpha: f64) -> f64 {
+    if cos <= 0. {
+        return 0.;
+    }
+    let a2 = alpha * alpha;
+    (a2 - 1.) / (PI * a2.ln() * (1. + (a2 - 1.) * cos * cos))
+}
+
+/// A normal distributed by `gtr1` times its cosine to the surface's.
+fn sample_gtr1(alpha: f64, u: (f64, f64)) -> Tup {
+    let a2 = alpha * alpha;
+    let cos2 = (1. - a2.powf(1. - u.0)) / (1. - a2);
+    let (cos, sin) = (cos2.sqrt(), (1. - cos2).max(0.).sqrt());
+    let phi = 2. * PI * u.1;
+    Tup(sin * phi.cos(), sin * phi.sin(), cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chi2::assert_sampling_matches_pdf;
+
+    fn surface(color: Tup) -> Surface {
+        Surface {
+            n: Tup(0., 0., 1.),
+            n1: Tup(0., 0., 1.),
+            color,
+            point: Tup::zeros(),
+            uv: (0., 0.),
+        }
+    }
+
+    #[test]
+    fn principled_passes_the_chi_square_test() {
+        let plastic = Principled {
+            roughness: 0.4,
+            sheen: 0.5,
+            ..Default::default()
+        };
+        let car_paint = Principled {
+            metallic: 0.6,
+            roughness: 0.5,
+            clearcoat: 1.,
+            clearcoat_gloss: 0.7,
+            ..Default::default()
+        };
+        let frosted = Principled {
+            roughness: 0.3,
+            transmission: 0.8,
+            ..Default::default()
+        };
+        let red = Tup(0.8, 0.2, 0.1);
+        for (i, (material, wo)) in [
+            (plastic, Tup(0., 0., 1.)),
+            (plastic, Tup(0.96, 0., 0.28)),
+            (car_paint, Tup(0.6, 0., 0.8)),
+            (frosted, Tup(0.48, 0.36, 0.8)),
+            (frosted, Tup(0.6, 0., -0.8)),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            assert_sampling_matches_pdf(&material, &surface(red), wo, i as u64);
+        }
+    }
+
+    #[test]
+    fn metals_reflect_in_their_base_color() {
+        let metal = Principled {
+            metallic: 1.,
+            roughness: 0.2,
+            ..Default::default()
+        };
+        let gold = Tup(1., 0.8, 0.3);
+        let wo = Tup(0., 0., 1.);
+        for i in 0..100 {
+            let u = ((i as f64 + 0.5) / 100., (i as f64 * 0.618034).fract());
+            let Some(b) = metal.sample(&surface(gold), wo, 0.5, u) else {
+                continue;
+            };
+            // Everything is reflected, all of it in the base color.
+            assert!(b.wi.2 > 0.);
+            let ratio = b.weight * (1. / b.weight.0);
+            assert!((ratio - gold).dot(ratio - gold) < 1e-6, "{:?}", b.weight);
+        }
+    }
+
+    /// The mean weight of samples leaving towards `wo`, with that of refracted
+    /// light scaled back by the radiance it gains or loses crossing `eta`.
+    fn albedo(material: &Principled, wo: Tup, eta: f64) -> f64 {
+        let s = surface(Tup::ones());
+        let n = 10_000;
+        let mut sum = 0.;
+        for i in 0..n {
+            let u = ((i as f64 + 0.5) / n as f64, (i as f64 * 0.618034).fract());
+            let uc = (i as f64 * 0.414214).fract();
+            if let Some(b) = material.sample(&s, wo, uc, u) {
+                let scale = if b.wi.2 * wo.2 < 0. { eta * eta } else { 1. };
+                sum += b.weight.0 * scale;
+            }
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn transmission_replaces_the_diffuse_lobe() {
+        let glass = Principled {
+            roughness: 0.2,
+            transmission: 1.,
+            ..Default::default()
+        };
+        // Whatever is not reflected is refracted, from either side.
+        let outside = albedo(&glass, Tup(0., 0., 1.), glass.ior);
+        assert!(outside > 0.95 && outside < 1.01, "{}", outside);
+        let inside = albedo(&glass, Tup(0., 0., -1.), 1. / glass.ior);
+        assert!(inside > 0.95 && inside < 1.01, "{}", inside);
+        // Nothing is reflected diffusely.
+        let s = surface(Tup::ones());
+        let grazing = Tup(0.8, 0., 0.6);
+        assert!(glass.eval(&s, Tup(0., 0., 1.), grazing).0 < 1e-3);
+    }
+
+    #[test]
+    fn light_beyond_the_critical_angle_is_reflected() {
+        let glass = Principled {
+            roughness: 0.05,
+            transmission: 1.,
+            ..Default::default()
+        };
+        let wo = Tup(0.8, 0., -0.6);
+        let s = surface(Tup::ones());
+        let wi = reflect(wo, Tup(0., 0., -1.));
+        let f = glass.eval(&s, wo, wi).0 * wi.2.abs() / glass.pdf(&s, wo, wi);
+        assert!(f > 0.95, "{}", f);
+        let inside = albedo(&glass, wo, 1. / glass.ior);
+        assert!(inside > 0.95 && inside < 1.01, "{}", inside);
+    }
+}
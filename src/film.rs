@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+
+use super::image::Image;
+use super::integrator::FirstHit;
+use super::tup::Tup;
+
+/// What one camera sample found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraSample {
+    pub radiance: Tup,
+    pub first_hit: Option<FirstHit>,
+}
+
+/// Everything a film keeps for one pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FilmPixel {
+    /// Filter-weighted sums of radiance, albedo and normal, and of the weights.
+    pub sum: Tup,
+    pub albedo: Tup,
+    pub normal: Tup,
+    pub weight: f64,
+    /// Unweighted statistics of the samples taken for this pixel.
+    pub samples: u32,
+    pub radiance_sum: Tup,
+    pub radiance_sq_sum: Tup,
+    /// Depth and object of the sample closest to the pixel centre, at distance
+    /// `nearest` from it.
+    pub depth: f64,
+    pub object: Option<usize>,
+    pub nearest: f64,
+}
+
+impl Default for FilmPixel {
+    fn default() -> Self {
+        FilmPixel {
+            sum: Tup::zeros(),
+            albedo: Tup::zeros(),
+            normal: Tup::zeros(),
+            weight: 0.,
+            samples: 0,
+            radiance_sum: Tup::zeros(),
+            radiance_sq_sum: Tup::zeros(),
+            depth: f64::INFINITY,
+            object: None,
+            nearest: f64::INFINITY,
+        }
+    }
+}
+
+/// Absolute term added to a pixel's mean when computing its relative error,
+/// so that nearly black pixels are not sampled forever.
+const ERROR_FLOOR: f64 = 0.01;
+
+impl FilmPixel {
+    /// Variance of the mean radiance, estimated from the samples.
+    pub fn variance(&self) -> Tup {
+        if self.samples < 2 {
+            return Tup::zeros();
+        }
+        let n = self.samples as f64;
+        let mean = self.radiance_sum * (1. / n);
+        let v = (self.radiance_sq_sum - mean * self.radiance_sum) * (1. / (n - 1.));
+        Tup(v.0.max(0.), v.1.max(0.), v.2.max(0.)) * (1. / n)
+    }
+
+    /// Standard error of the mean radiance relative to the mean, for the
+    /// channel where it is largest. Infinite with fewer than two samples.
+    pub fn relative_error(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+        let mean = self.radiance_sum * (1. / self.samples as f64);
+        let v = self.variance();
+        [(v.0, mean.0), (v.1, mean.1), (v.2, mean.2)]
+            .into_iter()
+            .map(|(v, m)| v.sqrt() / (m.abs() + ERROR_FLOOR))
+            .fold(0., f64::max)
+    }
+}
+
+/// Accumulates filtered samples, stored row by row from the top-left corner
+/// like [`Image`]. A pixel's value is its weighted sum over its total weight.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![FilmPixel::default(); width * height],
+        }
+    }
+
+    /// Adds `sample` with filter weight `weight` to pixel (`x`, `y`).
+    pub fn add(&mut self, x: usize, y: usize, sample: &CameraSample, weight: f64) {
+        let p = &mut self.pixels[y * self.width + x];
+        p.sum += sample.radiance * weight;
+        p.weight += weight;
+        if let Some(hit) = sample.first_hit {
+            p.albedo += hit.albedo * weight;
+            p.normal += hit.normal * weight;
+        }
+    }
+
+    /// Records `sample` as taken for pixel (`x`, `y`), at distance `distance`
+    /// from its centre. Each sample must be recorded once, for its own pixel.
+    pub fn record(&mut self, x: usize, y: usize, sample: &CameraSample, distance: f64) {
+        let p = &mut self.pixels[y * self.width + x];
+        let l = sample.radiance;
+        p.samples += 1;
+        p.radiance_sum += l;
+        p.radiance_sq_sum += l * l;
+        if distance < p.nearest {
+            p.nearest = distance;
+            p.depth = sample.first_hit.map_or(f64::INFINITY, |h| h.depth);
+            p.object = sample.first_hit.map(|h| h.object);
+        }
+    }
+
+    /// Adds the rows of `other`, which has the same width, starting at row
+    /// `first_row` of this film.
+    pub fn merge(&mut self, other: &Film, first_row: usize) {
+        let start = first_row * self.width;
+        for (p, q) in self.pixels[start..start + other.pixels.len()]
+            .iter_mut()
+            .zip(&other.pixels)
+        {
+            p.sum += q.sum;
+            p.albedo += q.albedo;
+            p.normal += q.normal;
+            p.weight += q.weight;
+            p.samples += q.samples;
+            p.radiance_sum += q.radiance_sum;
+            p.radiance_sq_sum += q.radiance_sq_sum;
+            if q.nearest < p.nearest {
+                p.nearest = q.nearest;
+                p.depth = q.depth;
+                p.object = q.object;
+            }
+        }
+    }
+
+    fn resolve(&self, f: impl Fn(&FilmPixel) -> Tup) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(f).collect(),
+        }
+    }
+
+    /// Resolves the film. Pixels without any weight are black.
+    pub fn image(&self) -> Image {
+        self.resolve(|p| normalize(p.sum, p.weight))
+    }
+
+    pub fn albedo(&self) -> Image {
+        self.resolve(|p| normalize(p.albedo, p.weight))
+    }
+
+    /// Filtered normals; they are shorter than unit length where a pixel
+    /// covers several surfaces.
+    pub fn normal(&self) -> Image {
+        self.resolve(|p| normalize(p.normal, p.weight))
+    }
+
+    /// Variance of each pixel's mean radiance, estimated from its samples.
+    pub fn variance(&self) -> Image {
+        self.resolve(FilmPixel::variance)
+    }
+
+    /// Number of samples taken per pixel as a heatmap, from black for none
+    /// through blue, red and yellow to white for the most.
+    pub fn sample_heatmap(&self) -> Image {
+        let max = self.pixels.iter().map(|p| p.samples).max().unwrap_or(0);
+        self.resolve(|p| heat(p.samples as f64 / max.max(1) as f64))
+    }
+}
+
+/// Colour of `t` in [0, 1] on a black, blue, red, yellow, white ramp.
+fn heat(t: f64) -> Tup {
+    const STOPS: [Tup; 5] = [
+        Tup(0., 0., 0.),
+        Tup(0., 0., 1.),
+        Tup(1., 0., 0.),
+        Tup(1., 1., 0.),
+        Tup(1., 1., 1.),
+    ];
+    let x = t.clamp(0., 1.) * (STOPS.len() - 1) as f64;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f64;
+    STOPS[i] * (1. - f) + STOPS[i + 1] * f
+}
+
+fn normalize(sum: Tup, weight: f64) -> Tup {
+    if weight != 0. {
+        sum * (1. / weight)
+    } else {
+        Tup::zeros()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(l: Tup) -> CameraSample {
+        CameraSample {
+            radiance: l,
+            first_hit: None,
+        }
+    }
+
+    #[test]
+    fn merges_rows_and_normalizes_weights() {
+        let mut band = Film::new(2, 2);
+        band.add(1, 0, &sample(Tup(1., 2., 3.)), 2.);
+        band.add(1, 0, &sample(Tup(4., 5., 6.)), -1.);
+        band.add(0, 1, &sample(Tup(1., 1., 1.)), 0.5);
+        let mut film = Film::new(2, 3);
+        film.merge(&band, 1);
+        let image = film.image();
+        assert_eq!(image.get(0, 0), Tup::zeros());
+        assert_eq!(image.get(1, 1), Tup(-2., -1., 0.));
+        assert_eq!(image.get(0, 2), Tup(1., 1., 1.));
+    }
+
+    #[test]
+    fn records_statistics_and_the_nearest_hit() {
+        let mut film = Film::new(1, 1);
+        let hit = |object, depth| {
+            Some(FirstHit {
+                object,
+                depth,
+                normal: Tup(0., 0., 1.),
+                albedo: Tup::ones(),
+            })
+        };
+        for (l, d, h) in [(1., 0.4, hit(3, 7.)), (3., 0.1, hit(5, 2.)), (5., 0.2, None)] {
+            let s = CameraSample {
+                radiance: Tup(l, 0., 0.),
+                first_hit: h,
+            };
+            film.record(0, 0, &s, d);
+        }
+        // Sample variance 4, so the mean has variance 4/3.
+        assert!((film.variance().pixels[0].0 - 4. / 3.).abs() < 1e-12);
+        assert_eq!(film.pixels[0].object, Some(5));
+        assert_eq!(film.pixels[0].depth, 2.);
+        // Standard error sqrt(4/3) over a mean of 3 (plus the floor).
+        let expected = (4f64 / 3.).sqrt() / (3. + ERROR_FLOOR);
+        assert!((film.pixels[0].relative_error() - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn heatmap_spans_the_ramp() {
+        let mut film = Film::new(3, 1);
+        for (x, n) in [(1, 1), (2, 4)] {
+            for _ in 0..n {
+                film.record(x, 0, &sample(Tup::ones()), 0.);
+            }
+        }
+        let map = film.sample_heatmap();
+        assert_eq!(map.pixels, vec![Tup::zeros(), Tup(0., 0., 1.), Tup::ones()]);
+        assert_eq!(film.pixels[0].relative_error(), f64::INFINITY);
+    }
+}
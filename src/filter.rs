@@ -1,11 +1,579 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::sampler::Sampler;
 
-pub fn tent_filter(sampler: &mut Sampler) -> (f64, f64) {
+/// A pixel reconstruction filter, centred on the pixel centre with offsets in
+/// pixels. All filters here are separable.
+pub trait Filter: Send + Sync {
+    /// Half-width of the support.
+    fn radius(&self) -> f64;
+
+    fn eval(&self, x: f64, y: f64) -> f64;
+
+    /// Draws an offset from the pixel centre, distributed roughly like the
+    /// absolute value of the filter, and the weight the sample gets. Weights
+    /// are only meaningful relative to other samples of the same filter.
+    fn sample(&self, u: (f64, f64)) -> FilterSample;
+}
+
+/// smallpt's offset from a subpixel centre, in subpixels.
+#[deprecated(note = "use a `Filter`, such as the one built by `FilterType::Tent`")]
+pub fn tent_filter(sampler: &mut dyn Sampler) -> (f64, f64) {
     let (r1, r2) = sampler.next_2d();
 
     let dx = if r1 < 0.5 { -1.0 } else { 1.0 } * (2.0 - 2.0 * r1).sqrt();
     let dy = if r2 < 0.5 { -1.0 } else { 1.0 } * (2.0 - 2.0 * r2).sqrt();
 
     (dx, dy)
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterSample {
+    pub offset: (f64, f64),
+    pub weight: f64,
+}
+
+/// Filter choice as written in scene files, e.g.
+/// `{ "type": "gaussian", "radius": 1.5, "sigma": 0.5 }`. Parameters left out
+/// take the defaults below, which are also used when a filter is named on the
+/// command line.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum FilterType {
+    Box {
+        #[serde(default = "half", deserialize_with = "positive")]
+        radius: f64,
+    },
+    Tent {
+        #[serde(default = "one", deserialize_with = "positive")]
+        radius: f64,
+    },
+    Gaussian {
+        #[serde(default = "one_and_a_half", deserialize_with = "positive")]
+        radius: f64,
+        #[serde(default = "half", deserialize_with = "positive")]
+        sigma: f64,
+    },
+    /// Mitchell-Netravali cubic with parameters `b` and `c`.
+    Mitchell {
+        #[serde(default = "two", deserialize_with = "positive")]
+        radius: f64,
+        #[serde(default = "one_third")]
+        b: f64,
+        #[serde(default = "one_third")]
+        c: f64,
+    },
+    /// Sinc windowed by a sinc stretched to `tau` lobes.
+    Lanczos {
+        #[serde(default = "two", deserialize_with = "positive")]
+        radius: f64,
+        #[serde(default = "two", deserialize_with = "positive")]
+        tau: f64,
+    },
+}
+
+fn half() -> f64 {
+    0.5
+}
+
+fn one() -> f64 {
+    1.
+}
+
+fn one_and_a_half() -> f64 {
+    1.5
+}
+
+fn two() -> f64 {
+    2.
+}
+
+fn one_third() -> f64 {
+    1. / 3.
+}
+
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let v = f64::deserialize(deserializer)?;
+    if v > 0.0 && v.is_finite() {
+        Ok(v)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "filter parameters must be positive, got {}",
+            v
+        )))
+    }
+}
+
+impl Default for FilterType {
+    fn default() -> Self {
+        FilterType::Tent { radius: 1. }
+    }
+}
+
+impl FilterType {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            FilterType::Box { radius }
+            | FilterType::Tent { radius }
+            | FilterType::Gaussian { radius, .. }
+            | FilterType::Mitchell { radius, .. }
+            | FilterType::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    /// The same filter with its support changed to `radius`.
+    pub fn with_radius(mut self, r: f64) -> Self {
+        match &mut self {
+            FilterType::Box { radius }
+            | FilterType::Tent { radius }
+            | FilterType::Gaussian { radius, .. }
+            | FilterType::Mitchell { radius, .. }
+            | FilterType::Lanczos { radius, .. } => *radius = r,
+        }
+        self
+    }
+
+    pub fn build(self) -> Box<dyn Filter> {
+        match self {
+            FilterType::Box { radius } => Box::new(BoxFilter { radius }),
+            FilterType::Tent { radius } => Box::new(TentFilter { radius }),
+            FilterType::Gaussian { radius, sigma } => Box::new(GaussianFilter::new(radius, sigma)),
+            FilterType::Mitchell { radius, b, c } => Box::new(MitchellFilter::new(radius, b, c)),
+            FilterType::Lanczos { radius, tau } => Box::new(LanczosFilter::new(radius, tau)),
+        }
+    }
+}
+
+impl FromStr for FilterType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "box" => Ok(FilterType::Box { radius: half() }),
+            "tent" => Ok(FilterType::Tent { radius: one() }),
+            "gaussian" => Ok(FilterType::Gaussian {
+                radius: one_and_a_half(),
+                sigma: half(),
+            }),
+            "mitchell" => Ok(FilterType::Mitchell {
+                radius: two(),
+                b: one_third(),
+                c: one_third(),
+            }),
+            "lanczos" => Ok(FilterType::Lanczos {
+                radius: two(),
+                tau: two(),
+            }),
+            _ => Err(format!(
+                "unknown filter '{}' (expected 'box', 'tent', 'gaussian', 'mitchell' or 'lanczos')",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FilterType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FilterType::Box { .. } => "box",
+            FilterType::Tent { .. } => "tent",
+            FilterType::Gaussian { .. } => "gaussian",
+            FilterType::Mitchell { .. } => "mitchell",
+            FilterType::Lanczos { .. } => "lanczos",
+        })
+    }
+}
+
+/// How camera samples are turned into pixel values.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMethod {
+    /// Each sample is placed around its own pixel's centre according to the
+    /// filter and only counts towards that pixel, weighted by `f / pdf`.
+    #[default]
+    Importance,
+    /// Each sample is placed uniformly in its pixel and added to every pixel
+    /// whose filter covers it, weighted by the filter value.
+    Splat,
+}
+
+impl FromStr for FilterMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "importance" => Ok(FilterMethod::Importance),
+            "splat" => Ok(FilterMethod::Splat),
+            _ => Err(format!(
+                "unknown filter method '{}' (expected 'importance' or 'splat')",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FilterMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FilterMethod::Importance => "importance",
+            FilterMethod::Splat => "splat",
+        })
+    }
+}
+
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        if x.abs() <= self.radius && y.abs() <= self.radius {
+            1.
+        } else {
+            0.
+        }
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        FilterSample {
+            offset: ((2. * u.0 - 1.) * self.radius, (2. * u.1 - 1.) * self.radius),
+            weight: 1.,
+        }
+    }
+}
+
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl TentFilter {
+    /// Inverts the CDF of the tent on [-1, 1].
+    fn sample_1d(u: f64) -> f64 {
+        if u < 0.5 {
+            (2. * u).sqrt() - 1.
+        } else {
+            1. - (2. - 2. * u).sqrt()
+        }
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.) * (self.radius - y.abs()).max(0.)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        FilterSample {
+            offset: (
+                TentFilter::sample_1d(u.0) * self.radius,
+                TentFilter::sample_1d(u.1) * self.radius,
+            ),
+            weight: 1.,
+        }
+    }
+}
+
+/// Gaussian with the value at the radius subtracted, so it falls to zero there.
+pub struct GaussianFilter {
+    radius: f64,
+    sigma: f64,
+    table: Table,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, sigma: f64) -> Self {
+        let f = |x: f64| gaussian(x, radius, sigma);
+        GaussianFilter {
+            radius,
+            sigma,
+            table: Table::new(radius, f),
+        }
+    }
+}
+
+fn gaussian(x: f64, radius: f64, sigma: f64) -> f64 {
+    let g = |x: f64| (-x * x / (2. * sigma * sigma)).exp();
+    (g(x) - g(radius)).max(0.)
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        gaussian(x, self.radius, self.sigma) * gaussian(y, self.radius, self.sigma)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        self.table.sample(u, |x, y| self.eval(x, y))
+    }
+}
+
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+    table: Table,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> Self {
+        MitchellFilter {
+            radius,
+            b,
+            c,
+            table: Table::new(radius, |x| mitchell(2. * x / radius, b, c)),
+        }
+    }
+}
+
+/// The Mitchell-Netravali cubic, whose support is [-2, 2].
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x <= 1. {
+        ((12. - 9. * b - 6. * c) * x * x * x + (-18. + 12. * b + 6. * c) * x * x + (6. - 2. * b))
+            / 6.
+    } else if x <= 2. {
+        ((-b - 6. * c) * x * x * x
+            + (6. * b + 30. * c) * x * x
+            + (-12. * b - 48. * c) * x
+            + (8. * b + 24. * c))
+            / 6.
+    } else {
+        0.
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        let m = |v: f64| mitchell(2. * v / self.radius, self.b, self.c);
+        m(x) * m(y)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        self.table.sample(u, |x, y| self.eval(x, y))
+    }
+}
+
+pub struct LanczosFilter {
+    radius: f64,
+    tau: f64,
+    table: Table,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64, tau: f64) -> Self {
+        LanczosFilter {
+            radius,
+            tau,
+            table: Table::new(radius, |x| windowed_sinc(x, radius, tau)),
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn windowed_sinc(x: f64, radius: f64, tau: f64) -> f64 {
+    if x.abs() > radius {
+        0.
+    } else {
+        sinc(x) * sinc(x / tau)
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        windowed_sinc(x, self.radius, self.tau) * windowed_sinc(y, self.radius, self.tau)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        self.table.sample(u, |x, y| self.eval(x, y))
+    }
+}
+
+const TABLE_SIZE: usize = 256;
+
+/// Piecewise-constant approximation of the absolute value of a 1D filter
+/// profile, used to sample filters that cannot be inverted analytically.
+/// Samples are weighted by the exact filter value over the table's pdf, so
+/// negative lobes come out with negative weights.
+struct Table {
+    radius: f64,
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Table {
+    fn new(radius: f64, f: impl Fn(f64) -> f64) -> Self {
+        let dx = 2. * radius / TABLE_SIZE as f64;
+        // Averaging over each bin keeps bins that contain a zero crossing from
+        // getting almost no probability, and their samples huge weights.
+        let func: Vec<f64> = (0..TABLE_SIZE)
+            .map(|i| {
+                (0..8)
+                    .map(|j| f(-radius + (i as f64 + (j as f64 + 0.5) / 8.) * dx).abs())
+                    .sum::<f64>()
+                    / 8.
+            })
+            .collect();
+        let mut cdf = Vec::with_capacity(TABLE_SIZE + 1);
+        cdf.push(0.);
+        for v in &func {
+            cdf.push(cdf.last().unwrap() + v * dx);
+        }
+        let integral = cdf[TABLE_SIZE];
+        Table {
+            radius,
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Returns a position and its pdf.
+    fn sample_1d(&self, u: f64) -> (f64, f64) {
+        let target = u * self.integral;
+        let i = (self.cdf.partition_point(|&c| c <= target) - 1).min(TABLE_SIZE - 1);
+        let du = (target - self.cdf[i]) / (self.cdf[i + 1] - self.cdf[i]);
+        let dx = 2. * self.radius / TABLE_SIZE as f64;
+        let x = -self.radius + (i as f64 + du.clamp(0., 1.)) * dx;
+        (x, self.func[i] / self.integral)
+    }
+
+    fn sample(&self, u: (f64, f64), eval: impl Fn(f64, f64) -> f64) -> FilterSample {
+        let (x, pdf_x) = self.sample_1d(u.0);
+        let (y, pdf_y) = self.sample_1d(u.1);
+        FilterSample {
+            offset: (x, y),
+            weight: eval(x, y) / (pdf_x * pdf_y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_filters() -> Vec<FilterType> {
+        ["box", "tent", "gaussian", "mitchell", "lanczos"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect()
+    }
+
+    /// Integral of `g(x) * f(x, y)` over the support by the midpoint rule.
+    fn integrate(filter: &dyn Filter, g: impl Fn(f64) -> f64) -> f64 {
+        let n = 400;
+        let r = filter.radius();
+        let dx = 2. * r / n as f64;
+        let mut sum = 0.;
+        for i in 0..n {
+            for j in 0..n {
+                let x = -r + (i as f64 + 0.5) * dx;
+                let y = -r + (j as f64 + 0.5) * dx;
+                sum += g(x) * filter.eval(x, y) * dx * dx;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn samples_reproduce_the_filter() {
+        // The weighted mean of x^2 over the samples should match that of the
+        // filter itself.
+        for t in all_filters() {
+            let filter = t.build();
+            let expected =
+                integrate(filter.as_ref(), |x| x * x) / integrate(filter.as_ref(), |_| 1.);
+            // Stratified in x and a golden ratio sequence in y; the few samples
+            // that fall in negative lobes need a fine stratification.
+            let n = 4096;
+            let (mut sum, mut weights) = (0., 0.);
+            for i in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (i as f64 * 0.618034).fract());
+                let s = filter.sample(u);
+                assert!(s.offset.0.abs() <= t.radius() && s.offset.1.abs() <= t.radius());
+                sum += s.weight * s.offset.0 * s.offset.0;
+                weights += s.weight;
+            }
+            let mean = sum / weights;
+            assert!(
+                (mean - expected).abs() < 1e-3 * t.radius(),
+                "{} {} {}",
+                t,
+                mean,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn filters_peak_at_the_centre() {
+        for t in all_filters() {
+            let filter = t.build();
+            let centre = filter.eval(0., 0.);
+            assert!(centre > 0., "{}", t);
+            assert!(filter.eval(0.3, -0.2) <= centre, "{}", t);
+            assert_eq!(filter.eval(t.radius() + 0.1, 0.), 0., "{}", t);
+        }
+    }
+
+    #[test]
+    fn mitchell_has_negative_lobes() {
+        let filter = MitchellFilter::new(2., 1. / 3., 1. / 3.);
+        assert!(filter.eval(1.5, 0.) < 0.);
+        let s = filter.sample((0.01, 0.5));
+        assert!(s.weight < 0., "{:?}", s);
+    }
+
+    #[test]
+    fn parses_scene_file_filters() {
+        let f: FilterType =
+            serde_json::from_str(r#"{ "type": "gaussian", "sigma": 0.3 }"#).unwrap();
+        assert_eq!(
+            f,
+            FilterType::Gaussian {
+                radius: 1.5,
+                sigma: 0.3
+            }
+        );
+        assert!(serde_json::from_str::<FilterType>(r#"{ "type": "box", "radius": -1 }"#).is_err());
+        assert!(serde_json::from_str::<FilterType>(r#"{ "type": "box", "sigma": 1 }"#).is_err());
+        for t in all_filters() {
+            assert_eq!(t.to_string().parse::<FilterType>(), Ok(t));
+        }
+        assert_eq!(
+            "tent".parse::<FilterType>().unwrap().with_radius(2.),
+            FilterType::Tent { radius: 2. }
+        );
+        assert!("sinc".parse::<FilterType>().is_err());
+    }
+}
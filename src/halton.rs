@@ -0,0 +1,130 @@
+use std::sync::OnceLock;
+
+use super::sampler::{hash, hash_float, mix_bits, permutation_element, Sampler, ONE_MINUS_EPSILON};
+
+/// Dimensions beyond this many fall back to independent random numbers.
+const PRIME_COUNT: usize = 1000;
+
+/// Digits below those needed to tell 2^16 samples apart are not permuted
+/// explicitly; a random value fills them in instead.
+const INDEX_BITS: f64 = 16.;
+
+fn primes() -> &'static [u32] {
+    static PRIMES: OnceLock<Vec<u32>> = OnceLock::new();
+    PRIMES.get_or_init(|| {
+        let mut primes = Vec::with_capacity(PRIME_COUNT);
+        let mut n = 2;
+        while primes.len() < PRIME_COUNT {
+            if primes
+                .iter()
+                .take_while(|&&p| p * p <= n)
+                .all(|&p| n % p != 0)
+            {
+                primes.push(n);
+            }
+            n += 1;
+        }
+        primes
+    })
+}
+
+/// The Halton sequence, with dimension `d` using the `d`-th prime as its base.
+/// Each digit of the radical inverse goes through a random permutation chosen
+/// per pixel, dimension and digit position, which decorrelates pixels and
+/// breaks up the patterns between higher dimensions.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u64,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        HaltonSampler {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn sample(&mut self) -> f64 {
+        let dimension = self.dimension as usize;
+        self.dimension += 1;
+        if dimension >= PRIME_COUNT {
+            return hash_float(&[self.seed, self.pixel, self.index, dimension as u64]);
+        }
+        let digit_seed = hash(&[self.seed, self.pixel, dimension as u64]);
+        permuted_radical_inverse(primes()[dimension], self.index, digit_seed)
+    }
+}
+
+fn permuted_radical_inverse(base: u32, mut a: u64, seed: u64) -> f64 {
+    let b = u64::from(base);
+    let inv_base = 1. / base as f64;
+    let min_digits = (INDEX_BITS / (base as f64).log2()).ceil() as u64;
+    let mut inv_base_m = 1.;
+    let mut result = 0.;
+    let mut digit_index = 0;
+    while a > 0 || digit_index < min_digits {
+        let digit = (a % b) as u32;
+        a /= b;
+        let p = mix_bits(seed ^ digit_index) as u32;
+        inv_base_m *= inv_base;
+        result += permutation_element(digit, base, p) as f64 * inv_base_m;
+        digit_index += 1;
+    }
+    // The remaining digits all permute zero, so together they are a random
+    // offset within the last interval.
+    result += hash_float(&[seed, digit_index]) * inv_base_m;
+    result.min(ONE_MINUS_EPSILON)
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn next(&mut self) -> f64 {
+        self.sample()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.sample(), self.sample())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::tests::points;
+
+    #[test]
+    fn first_primes() {
+        assert_eq!(&primes()[..6], &[2, 3, 5, 7, 11, 13]);
+        assert_eq!(primes().len(), PRIME_COUNT);
+    }
+
+    #[test]
+    fn first_dimensions_are_stratified() {
+        // The first 36 points of bases 2 and 3 put one point in each cell of
+        // a 4 x 9 grid, and the first 18 in each cell of a 2 x 9 grid.
+        let mut sampler = HaltonSampler::new(8);
+        for (nx, ny) in [(2., 9.), (4., 9.)] {
+            let pts = points(&mut sampler, nx as u64 * 9, 0);
+            let mut cells: Vec<u32> = pts
+                .iter()
+                .map(|&(x, y)| ((y * ny) as u32) * nx as u32 + (x * nx) as u32)
+                .collect();
+            cells.sort();
+            assert_eq!(cells, (0..pts.len() as u32).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::input;
+use super::output::{self, OutputOptions};
+use super::tup::Tup;
+
+/// A rendered image or a texture, stored row by row from the top-left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Tup>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![Tup::zeros(); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Tup {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Reads a PPM, PNG or PFM image, chosen by the extension of `path`. See
+    /// [`input::load`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        input::load(path)
+    }
+
+    /// Writes the image in the format given by the extension of `path`, with
+    /// the default output options. See [`output::save`].
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        output::save(self, path, OutputOptions::default())
+    }
+
+    /// Writes the image as a binary (P6) PPM with the default output options.
+    #[deprecated(note = "use `output::write_ppm`")]
+    pub fn write_ppm(&self, out: impl Write) -> io::Result<()> {
+        let options = OutputOptions::default();
+        output::write_ppm(&options.tone_mapping.apply(self), out, options.bit_depth)
+    }
+
+    /// Writes the image to `path` as a binary (P6) PPM, whatever its extension.
+    #[deprecated(note = "use `Image::save` with a `.ppm` path")]
+    pub fn save_ppm(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        #[allow(deprecated)]
+        self.write_ppm(&mut out)?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(deprecated)]
+    fn writes_ppm() {
+        let mut image = Image::new(2, 1);
+        image.pixels[1] = Tup(1., 0.5, 2.);
+        let mut out = vec![];
+        image.write_ppm(&mut out).unwrap();
+        assert_eq!(out, b"P6\n2 1\n255\n\0\0\0\xff\xbc\xff");
+    }
+}
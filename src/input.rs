@@ -0,0 +1,296 @@
+use std::fs;
+use std::io::{self, BufRead, Read};
+use std::path::Path;
+
+use super::image::Image;
+use super::tonemap::srgb_decode;
+use super::tup::Tup;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// The next whitespace-separated word of a PPM or PFM header, skipping
+/// comments. The single whitespace character after it is consumed too, so
+/// binary data starts right after the last word.
+fn word(input: &mut impl BufRead) -> io::Result<String> {
+    let mut word = String::new();
+    let mut byte = [0];
+    loop {
+        if input.read(&mut byte)? == 0 {
+            if word.is_empty() {
+                return Err(invalid("unexpected end of header"));
+            }
+            return Ok(word);
+        }
+        match byte[0] {
+            b'#' if word.is_empty() => {
+                input.read_line(&mut String::new())?;
+            }
+            b if b.is_ascii_whitespace() => {
+                if !word.is_empty() {
+                    return Ok(word);
+                }
+            }
+            b => word.push(b as char),
+        }
+    }
+}
+
+/// The most pixels a PPM or PFM may have, enough for an 8K by 4K environment
+/// map, so that a corrupt header cannot ask for any amount of memory.
+const MAX_PIXELS: usize = 1 << 25;
+
+/// The number of values in an image of `width` by `height` pixels with
+/// `channels` each, if it is not too large to read.
+fn value_count(width: usize, height: usize, channels: usize) -> io::Result<usize> {
+    width
+        .checked_mul(height)
+        .filter(|&pixels| pixels <= MAX_PIXELS)
+        .map(|pixels| pixels * channels)
+        .ok_or_else(|| invalid(format!("image of {}x{} pixels is too large", width, height)))
+}
+
+fn number<T: std::str::FromStr>(input: &mut impl BufRead, what: &str) -> io::Result<T> {
+    let w = word(input)?;
+    w.parse()
+        .map_err(|_| invalid(format!("invalid {} '{}'", what, w)))
+}
+
+/// Reads a binary (P6) or plain (P3) PPM. Values are taken to be sRGB
+/// encoded and converted to linear.
+pub fn read_ppm(mut input: impl BufRead) -> io::Result<Image> {
+    let magic = word(&mut input)?;
+    if magic != "P6" && magic != "P3" {
+        return Err(invalid(format!("not a PPM file (magic '{}')", magic)));
+    }
+    let width: usize = number(&mut input, "width")?;
+    let height: usize = number(&mut input, "height")?;
+    let max: u32 = number(&mut input, "maximum value")?;
+    if max == 0 || max > 65535 {
+        return Err(invalid(format!("invalid maximum value {}", max)));
+    }
+    let count = value_count(width, height, 3)?;
+    let values: Vec<u32> = if magic == "P3" {
+        (0..count)
+            .map(|_| number(&mut input, "value"))
+            .collect::<io::Result<_>>()?
+    } else {
+        let size = if max < 256 { 1 } else { 2 };
+        let mut data = vec![0; count * size];
+        input.read_exact(&mut data)?;
+        data.chunks(size)
+            .map(|c| c.iter().fold(0, |v, &b| v << 8 | u32::from(b)))
+            .collect()
+    };
+    let pixels = values
+        .chunks(3)
+        .map(|c| {
+            let v = |x: u32| srgb_decode(f64::from(x.min(max)) / f64::from(max));
+            Tup(v(c[0]), v(c[1]), v(c[2]))
+        })
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Reads a PNG of any color type, dropping alpha. Values are taken to be
+/// sRGB encoded and converted to linear.
+pub fn read_png(input: impl Read) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(input);
+    // Palettes and bit depths below 8 become plain 8-bit samples.
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(invalid_png)?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(invalid_png)?;
+    let channels = info.color_type.samples();
+    let (size, max) = match info.bit_depth {
+        png::BitDepth::Sixteen => (2, 65535.),
+        _ => (1, 255.),
+    };
+    let samples: Vec<f64> = data[..info.buffer_size()]
+        .chunks(size)
+        .map(|c| srgb_decode(c.iter().fold(0, |v, &b| v << 8 | u32::from(b)) as f64 / max))
+        .collect();
+    let pixels = samples
+        .chunks(channels)
+        .map(|c| match c.len() {
+            1 | 2 => Tup(c[0], c[0], c[0]),
+            _ => Tup(c[0], c[1], c[2]),
+        })
+        .collect();
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        pixels,
+    })
+}
+
+fn invalid_png(error: png::DecodingError) -> io::Error {
+    match error {
+        png::DecodingError::IoError(e) => e,
+        e => invalid(e.to_string()),
+    }
+}
+
+/// Reads a color (`PF`) or grayscale (`Pf`) portable float map as linear
+/// values. Rows are stored bottom to top, little-endian if the scale is
+/// negative.
+pub fn read_pfm(mut input: impl BufRead) -> io::Result<Image> {
+    let magic = word(&mut input)?;
+    let channels = match magic.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid(format!("not a PFM file (magic '{}')", magic))),
+    };
+    let width: usize = number(&mut input, "width")?;
+    let height: usize = number(&mut input, "height")?;
+    let scale: f64 = number(&mut input, "scale")?;
+    let mut data = vec![0; value_count(width, height, channels)? * 4];
+    input.read_exact(&mut data)?;
+    let samples: Vec<f64> = data
+        .chunks(4)
+        .map(|c| {
+            let bytes = [c[0], c[1], c[2], c[3]];
+            f64::from(if scale < 0. {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            })
+        })
+        .collect();
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in samples.chunks(width.max(1) * channels).rev() {
+        pixels.extend(row.chunks(channels).map(|c| match c.len() {
+            1 => Tup(c[0], c[0], c[0]),
+            _ => Tup(c[0], c[1], c[2]),
+        }));
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Reads the image at `path` in the format given by its extension: `.ppm`,
+/// `.png` or `.pfm`.
+pub fn load(path: impl AsRef<Path>) -> io::Result<Image> {
+    let path = path.as_ref();
+    read(path, &fs::read(path)?)
+}
+
+/// Decodes `data`, the contents of the image file at `path`.
+pub fn read(path: &Path, data: &[u8]) -> io::Result<Image> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("ppm") => read_ppm(data),
+        Some("png") => read_png(data),
+        Some("pfm") => read_pfm(data),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unsupported image format (expected '.ppm', '.png' or '.pfm')",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{write_pfm, write_png, write_ppm, BitDepth};
+
+    fn test_image() -> Image {
+        Image {
+            width: 3,
+            height: 2,
+            pixels: vec![
+                Tup(0., 0.5, 1.),
+                Tup(0.25, 0.75, 0.1),
+                Tup(1., 1., 1.),
+                Tup(0.01, 0.2, 0.3),
+                Tup(0.6, 0., 0.9),
+                Tup(0.4, 0.4, 0.05),
+            ],
+        }
+    }
+
+    fn assert_close(a: &Image, b: &Image, tolerance: f64) {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        for (p, q) in a.pixels.iter().zip(&b.pixels) {
+            let d = *p - *q;
+            assert!(
+                d.0.abs().max(d.1.abs()).max(d.2.abs()) <= tolerance,
+                "{:?} {:?}",
+                p,
+                q
+            );
+        }
+    }
+
+    #[test]
+    fn reads_back_what_is_written() {
+        for depth in [BitDepth::Eight, BitDepth::Sixteen] {
+            // Quantization errors are largest in the brightest values.
+            let tolerance = if depth == BitDepth::Eight { 6e-3 } else { 3e-5 };
+            let mut out = vec![];
+            write_ppm(&test_image(), &mut out, depth).unwrap();
+            assert_close(&read_ppm(out.as_slice()).unwrap(), &test_image(), tolerance);
+            let mut out = vec![];
+            write_png(&test_image(), &mut out, depth).unwrap();
+            assert_close(&read_png(out.as_slice()).unwrap(), &test_image(), tolerance);
+        }
+        let mut out = vec![];
+        write_pfm(&test_image(), &mut out).unwrap();
+        assert_close(&read_pfm(out.as_slice()).unwrap(), &test_image(), 1e-7);
+    }
+
+    #[test]
+    fn reads_plain_ppm_with_comments() {
+        let image =
+            read_ppm(b"P3\n# a comment\n2 1\n# another\n255\n255 0 0  0 0 255\n".as_slice())
+                .unwrap();
+        assert_eq!(image.pixels, [Tup(1., 0., 0.), Tup(0., 0., 1.)]);
+    }
+
+    #[test]
+    fn reads_big_endian_grayscale_pfm() {
+        let mut data = b"Pf\n1 2\n1.0\n".to_vec();
+        data.extend_from_slice(&0.5f32.to_be_bytes());
+        data.extend_from_slice(&2f32.to_be_bytes());
+        let image = read_pfm(data.as_slice()).unwrap();
+        // The bottom row comes first.
+        assert_eq!(image.pixels, [Tup(2., 2., 2.), Tup(0.5, 0.5, 0.5)]);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(read_ppm(b"P5\n1 1\n255\n\0".as_slice()).is_err());
+        assert!(read_ppm(b"P6\n2 2\n255\n\0\0\0".as_slice()).is_err());
+        assert!(read_pfm(b"PF\n1 x\n-1\n".as_slice()).is_err());
+        for header in [
+            b"P6\n18446744073709551615 2\n255\n".as_slice(),
+            b"P3\n100000 100000\n255\n",
+            b"PF\n4294967296 4294967296\n-1\n",
+        ] {
+            let decode = if header[1] == b'F' {
+                read_pfm
+            } else {
+                read_ppm
+            };
+            let err = decode(header).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains("too large"), "{}", err);
+        }
+        assert!(read_png(b"not a png".as_slice()).is_err());
+        assert_eq!(
+            read(Path::new("texture.jpg"), b"").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
@@ -1,296 +1,828 @@
-use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
 use recursive::recursive;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    material::{BsdfSample, Material, Surface},
     ray::Ray,
-    sphere::{RflType, Sphere},
+    sampler::Sampler,
+    shape::Hit,
     tup::Tup,
-    world::World,
-    sampler::Sampler
+    world::{Object, World},
 };
 
-#[derive(Default)]
+/// Written in scene files and on the command line as `iterative`, `recursive`,
+/// `nee`, `mis` (power heuristic), `mis-power` or `mis-balance`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum IntegrationType {
     #[default]
     Iterative,
-    #[allow(dead_code)]
     Recursive,
+    /// Iterative path tracing with explicit light sampling on diffuse surfaces.
+    NextEvent,
+    /// Light sampling and BSDF sampling combined with multiple importance sampling.
+    Mis(MisHeuristic),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MisHeuristic {
+    Balance,
+    #[default]
+    Power,
+}
+
+impl MisHeuristic {
+    /// Weight of a sample taken with pdf `a` when another strategy would have
+    /// produced it with pdf `b`.
+    pub fn weight(self, a: f64, b: f64) -> f64 {
+        match self {
+            MisHeuristic::Balance => a / (a + b),
+            MisHeuristic::Power => a * a / (a * a + b * b),
+        }
+    }
+}
+
+impl FromStr for IntegrationType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "iterative" => Ok(IntegrationType::Iterative),
+            "recursive" => Ok(IntegrationType::Recursive),
+            "nee" => Ok(IntegrationType::NextEvent),
+            "mis" | "mis-power" => Ok(IntegrationType::Mis(MisHeuristic::Power)),
+            "mis-balance" => Ok(IntegrationType::Mis(MisHeuristic::Balance)),
+            _ => Err(format!(
+                "unknown integrator '{}' (expected 'iterative', 'recursive', 'nee', \
+                 'mis', 'mis-power' or 'mis-balance')",
+                s
+            )),
+        }
+    }
 }
 
+impl fmt::Display for IntegrationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IntegrationType::Iterative => "iterative",
+            IntegrationType::Recursive => "recursive",
+            IntegrationType::NextEvent => "nee",
+            IntegrationType::Mis(MisHeuristic::Power) => "mis-power",
+            IntegrationType::Mis(MisHeuristic::Balance) => "mis-balance",
+        })
+    }
+}
+
+impl TryFrom<String> for IntegrationType {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<IntegrationType> for String {
+    fn from(t: IntegrationType) -> Self {
+        t.to_string()
+    }
+}
+
+/// Dimensions of each sample used before the path starts: two for the film
+/// position and two for the lens of cameras that have one.
+pub const CAMERA_DIMENSIONS: u32 = 4;
+
+/// Dimensions used by a bounce before light sampling: one for Russian roulette
+/// and three for the BSDF.
+const BOUNCE_DIMENSIONS: u32 = 4;
+
+/// Moves the sampler to the dimensions of bounce `depth`, so each decision on
+/// a path always uses the same dimension of the sample. A bounce takes the
+/// Russian roulette dimension, then the BSDF dimensions, then two dimensions
+/// per light.
+fn start_bounce(world: &World, sampler: &mut dyn Sampler, depth: i32) {
+    let stride = BOUNCE_DIMENSIONS + 2 * world.lights.len() as u32;
+    let dimension = (depth.max(0) as u32)
+        .saturating_mul(stride)
+        .saturating_add(CAMERA_DIMENSIONS);
+    sampler.set_dimension(dimension.min(u32::MAX / 2));
+}
+
+/// Surface data at the first intersection of a camera path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FirstHit {
+    /// Index into `World::objects`.
+    pub object: usize,
+    /// Distance along the camera ray.
+    pub depth: f64,
+    /// Outward surface normal.
+    pub normal: Tup,
+    pub albedo: Tup,
+}
+
+/// Fills in `first_hit` unless an earlier vertex of the path already did.
+fn record_first_hit(first_hit: &mut Option<FirstHit>, hit: &Hit, obj: &Object) {
+    if first_hit.is_none() {
+        *first_hit = Some(FirstHit {
+            object: hit.object,
+            depth: hit.t,
+            normal: hit.normal,
+            albedo: obj.color_at(hit),
+        });
+    }
+}
+
+/// Estimates the radiance arriving along `ray`. Paths are cut off after
+/// `max_depth` bounces when it is set, otherwise only Russian roulette ends them.
+/// If `first_hit` is `None` it is set to the first surface the path hits.
 pub fn integrate(
     world: &World,
     ray: Ray,
     depth: i32,
-    sampler: &mut Sampler,
+    max_depth: Option<i32>,
+    sampler: &mut dyn Sampler,
     int_type: IntegrationType,
+    first_hit: &mut Option<FirstHit>,
 ) -> Tup {
     match int_type {
-        IntegrationType::Iterative => radiance_iter(world, ray, depth, sampler),
-        IntegrationType::Recursive => radiance(world, &ray, depth, sampler),
+        IntegrationType::Iterative => {
+            radiance_iter(world, ray, depth, max_depth, sampler, first_hit)
+        }
+        IntegrationType::Recursive => radiance(world, &ray, depth, max_depth, sampler, first_hit),
+        IntegrationType::NextEvent => {
+            radiance_nee(world, ray, depth, max_depth, sampler, first_hit)
+        }
+        IntegrationType::Mis(heuristic) => {
+            radiance_mis(world, ray, depth, max_depth, sampler, heuristic, first_hit)
+        }
     }
 }
 
 #[recursive]
-pub fn radiance(world: &World, ray: &Ray, mut depth: i32, mut sampler: &mut Sampler) -> Tup {
-    let mut t = f64::INFINITY;
-    let mut id: usize = 0;
-    if !world.intersect(&ray, &mut t, &mut id) {
+pub fn radiance(
+    world: &World,
+    ray: &Ray,
+    mut depth: i32,
+    max_depth: Option<i32>,
+    sampler: &mut dyn Sampler,
+    first_hit: &mut Option<FirstHit>,
+) -> Tup {
+    start_bounce(world, sampler, depth);
+    let Some(hit) = world.intersect(ray) else {
         return Tup(0., 0., 0.);
-    }
-    let obj: &Sphere = &world.spheres[id];
-    let x = ray.o + (ray.d * t);
-    let n = (x - obj.p).norm();
-    let n1 = if n.dot(ray.d) < 0.0 { n } else { n * -1.0 };
+    };
+    let obj = &world.objects[hit.object];
+    let f = obj.color_at(&hit);
+    let emission = obj.emission_at(&hit);
+    let s = Surface::new(ray, &hit, f);
+    record_first_hit(first_hit, &hit, obj);
+    let absorbed = interior_transmittance(&s, obj, &hit);
 
-    let mut f = obj.c;
     let p = f.0.max(f.1.max(f.2));
     depth += 1;
+    if max_depth.is_some_and(|max| depth > max) {
+        return absorbed * emission;
+    }
+    let rr = sampler.next();
+    let mut scale = 1.;
     if depth > 5 {
-        if sampler.next() < p {
-            f = f * (1.0 / p);
+        if rr < p {
+            scale = 1.0 / p;
         } else {
-            return obj.e;
+            return absorbed * emission;
         }
     }
 
-    match obj.rfl {
-        RflType::DIFF => {
-            let r1 = 2. * PI * sampler.next();
-            let r2: f64 = sampler.next();
-            let r2s = r2.sqrt();
-            let w: Tup = n1;
-            let u: Tup = if w.0.abs() > 0.1 {
-                Tup(0., 1., 0.).cross(w).norm()
-            } else {
-                Tup(1., 0., 0.).cross(w).norm()
-            };
-
-            let v = w.cross(u);
-            let d: Tup =
-                (u * f64::cos(r1) * r2s + v * f64::sin(r1) * r2s + w * ((1. - r2).sqrt())).norm();
-            return obj.e + f * radiance(world, &Ray { o: x, d }, depth, &mut sampler);
+    let wo = ray.d * -1.;
+    // Near the camera every lobe of a specular material is followed, as
+    // smallpt does for glass.
+    let lobes = if depth > 2 {
+        Vec::new()
+    } else {
+        obj.material.lobes(&s, wo)
+    };
+    let mut follow = |b: &BsdfSample, sampler: &mut dyn Sampler| {
+        let next = Ray {
+            o: hit.point,
+            d: b.wi,
+        };
+        b.weight * scale * radiance(world, &next, depth, max_depth, sampler, first_hit)
+    };
+    let l = if lobes.is_empty() {
+        match obj
+            .material
+            .sample(&s, wo, sampler.next(), sampler.next_2d())
+        {
+            Some(b) => emission + follow(&b, sampler),
+            None => emission,
         }
-        RflType::SPEC => {
-            return obj.e
-                + f * radiance(
-                    world,
-                    &Ray {
-                        o: x,
-                        d: ray.d - n * 2. * n.dot(ray.d),
-                    },
-                    depth,
-                    &mut sampler,
-                );
+    } else {
+        lobes.iter().fold(emission, |l, b| l + follow(b, sampler))
+    };
+    absorbed * l
+}
+
+pub fn radiance_iter(
+    world: &World,
+    mut ray: Ray,
+    mut depth: i32,
+    max_depth: Option<i32>,
+    sampler: &mut dyn Sampler,
+    first_hit: &mut Option<FirstHit>,
+) -> Tup {
+    let mut result = Tup::zeros();
+    let mut throughput = Tup::ones();
+
+    loop {
+        start_bounce(world, sampler, depth);
+        let Some(hit) = world.intersect(&ray) else {
+            return result;
+        };
+
+        let obj = &world.objects[hit.object];
+        let f = obj.color_at(&hit);
+        let emission = obj.emission_at(&hit);
+        let s = Surface::new(&ray, &hit, f);
+        record_first_hit(first_hit, &hit, obj);
+        throughput = throughput * interior_transmittance(&s, obj, &hit);
+
+        let p = f.0.max(f.1.max(f.2));
+        depth += 1;
+
+        if max_depth.is_some_and(|max| depth > max) {
+            result += throughput * emission;
+            break;
         }
-        RflType::REFR => {
-            let rfl_ray = Ray {
-                o: x,
-                d: ray.d - n * 2. * n.dot(ray.d),
-            };
-            let into = n.dot(n1) > 0.;
-            let nc: f64 = 1.;
-            let nt: f64 = 1.5;
-            let nnt = if into { nc / nt } else { nt / nc };
-            let ddn = ray.d.dot(n1);
-            let cos2t = 1. - nnt * nnt * (1. - ddn * ddn);
-            if cos2t < 0. {
-                return obj.e + f * radiance(world, &rfl_ray, depth, &mut sampler);
+
+        let rr = sampler.next();
+        let mut scale = 1.;
+        if depth > 5 {
+            if rr < p {
+                scale = 1.0 / p;
+            } else {
+                result += throughput * emission;
+                break;
             }
-            let tdir =
-                (ray.d * nnt - n * if into { 1. } else { -1. } * (ddn * nnt + cos2t.sqrt())).norm();
-            let a = nt - nc;
-            let b = nt + nc;
-            let r0 = a * a / (b * b);
-            let c = 1. - if into { -ddn } else { tdir.dot(n) };
-            let re = r0 + (1. - r0) * c * c * c * c * c;
-            let tr = 1. - re;
-            let p = 0.25 + 0.5 * re;
-            let rp = re / p;
-            let tp = tr / (1. - p);
-
-            obj.e
-                + f * (if depth > 2 {
-                    if sampler.next() < p {
-                        radiance(world, &rfl_ray, depth, &mut sampler) * rp
-                    } else {
-                        radiance(world, &Ray { o: x, d: tdir }, depth, &mut sampler) * tp
-                    }
-                } else {
-                    radiance(world, &rfl_ray, depth, &mut sampler) * re
-                        + radiance(world, &Ray { o: x, d: tdir }, depth, &mut sampler) * tr
-                })
         }
+
+        result += throughput * emission;
+        throughput = throughput * scale;
+
+        let Some(b) = obj
+            .material
+            .sample(&s, ray.d * -1., sampler.next(), sampler.next_2d())
+        else {
+            break;
+        };
+        throughput = throughput * b.weight;
+        ray = Ray {
+            o: hit.point,
+            d: b.wi,
+        };
     }
+    result
+}
+
+/// Path tracing with next event estimation. Every hit on a material that is
+/// not specular samples each emissive object and casts a shadow ray towards
+/// it. Emission reached by the following bounce is skipped, since the light
+/// samples already account for it. Lights whose shape cannot be sampled are
+/// only found by BSDF sampling.
+pub fn radiance_nee(
+    world: &World,
+    ray: Ray,
+    depth: i32,
+    max_depth: Option<i32>,
+    sampler: &mut dyn Sampler,
+    first_hit: &mut Option<FirstHit>,
+) -> Tup {
+    radiance_light_sampling(world, ray, depth, max_depth, sampler, None, first_hit)
+}
+
+/// Like `radiance_nee`, but emission reached by a bounce off a material that
+/// is not specular is kept, and both it and the light samples are weighted
+/// with `heuristic`. Light sampling handles small lights well and BSDF
+/// sampling large ones, so the combination copes with both.
+pub fn radiance_mis(
+    world: &World,
+    ray: Ray,
+    depth: i32,
+    max_depth: Option<i32>,
+    sampler: &mut dyn Sampler,
+    heuristic: MisHeuristic,
+    first_hit: &mut Option<FirstHit>,
+) -> Tup {
+    radiance_light_sampling(
+        world,
+        ray,
+        depth,
+        max_depth,
+        sampler,
+        Some(heuristic),
+        first_hit,
+    )
 }
 
-pub fn radiance_iter(world: &World, mut ray: Ray, mut depth: i32, sampler: &mut Sampler) -> Tup {
+fn radiance_light_sampling(
+    world: &World,
+    mut ray: Ray,
+    mut depth: i32,
+    max_depth: Option<i32>,
+    sampler: &mut dyn Sampler,
+    heuristic: Option<MisHeuristic>,
+    first_hit: &mut Option<FirstHit>,
+) -> Tup {
     let mut result = Tup::zeros();
     let mut throughput = Tup::ones();
+    // The last vertex lights were sampled at, its object and the pdf of the
+    // direction the material sampled there.
+    let mut prev: Option<(Tup, usize, f64)> = None;
 
     loop {
-        let mut t = f64::INFINITY;
-        let mut id: usize = 0;
-        if !world.intersect(&ray, &mut t, &mut id) {
+        start_bounce(world, sampler, depth);
+        let Some(hit) = world.intersect(&ray) else {
             return result;
-        }
+        };
 
-        let obj: &Sphere = &world.spheres[id];
-        let x = ray.o + (ray.d * t);
-        let n = (x - obj.p).norm();
-        let n1 = if n.dot(ray.d) < 0.0 { n } else { n * -1.0 };
+        let obj = &world.objects[hit.object];
+        let f = obj.color_at(&hit);
+        let s = Surface::new(&ray, &hit, f);
+        record_first_hit(first_hit, &hit, obj);
+        throughput = throughput * interior_transmittance(&s, obj, &hit);
+
+        let e = match (prev, heuristic) {
+            (Some((px, pid, pdf)), Some(h)) if light_sampled(world, hit.object, px, pid) => {
+                obj.emission_at(&hit) * h.weight(pdf, obj.shape.pdf_toward(px, ray.d))
+            }
+            (Some((px, pid, _)), None) if light_sampled(world, hit.object, px, pid) => Tup::zeros(),
+            _ => obj.emission_at(&hit),
+        };
 
-        let mut f = obj.c;
         let p = f.0.max(f.1.max(f.2));
         depth += 1;
 
+        if max_depth.is_some_and(|max| depth > max) {
+            result += throughput * e;
+            break;
+        }
+
+        let rr = sampler.next();
+        let mut scale = 1.;
         if depth > 5 {
-            if sampler.next() < p {
-                f = f * (1.0 / p);
+            if rr < p {
+                scale = 1.0 / p;
             } else {
-                result += throughput * obj.e;
+                result += throughput * e;
                 break;
             }
         }
 
-        result += throughput * obj.e;
-        throughput = throughput * f;
-
-        match obj.rfl {
-            RflType::DIFF => {
-                let r1 = 2. * PI * sampler.next();
-                let r2: f64 = sampler.next();
-                let r2s = r2.sqrt();
-                let w: Tup = n1;
-                let u: Tup = if w.0.abs() > 0.1 {
-                    Tup(0., 1., 0.).cross(w).norm()
-                } else {
-                    Tup(1., 0., 0.).cross(w).norm()
-                };
-                let v = w.cross(u);
-                let d: Tup =
-                    (u * f64::cos(r1) * r2s + v * f64::sin(r1) * r2s + w * ((1. - r2).sqrt()))
-                        .norm();
-
-                ray = Ray { o: x, d };
-            }
-            RflType::SPEC => {
-                ray = Ray {
-                    o: x,
-                    d: ray.d - n * 2. * n.dot(ray.d),
-                };
-            }
-            RflType::REFR => {
-                let into = n.dot(n1) > 0.;
-                let nc: f64 = 1.;
-                let nt: f64 = 1.5;
-                let nnt = if into { nc / nt } else { nt / nc };
-                let ddn = ray.d.dot(n1);
-                let cos2t = 1. - nnt * nnt * (1. - ddn * ddn);
-
-                if cos2t < 0. {
-                    ray = Ray {
-                        o: x,
-                        d: ray.d - n * 2. * n.dot(ray.d),
-                    };
-                    continue;
-                }
-
-                let tdir = (ray.d * nnt
-                    - n * if into { 1. } else { -1. } * (ddn * nnt + cos2t.sqrt()))
-                .norm();
-
-                let a = nt - nc;
-                let b = nt + nc;
-                let r0 = (a * a) / (b * b);
-                let c = 1. - if into { -ddn } else { tdir.dot(n) };
-                let re = r0 + (1. - r0) * c * c * c * c * c;
-                let tr = 1. - re;
-                let p = 0.25 + 0.5 * re;
-                let rp = re / p;
-                let tp = tr / (1. - p);
-
-                if sampler.next() < p {
-                    ray = Ray {
-                        o: x,
-                        d: ray.d - n * 2. * n.dot(ray.d),
-                    };
-                    throughput = throughput * rp;
-                } else {
-                    ray = Ray { o: x, d: tdir };
-                    throughput = throughput * tp;
-                }
-
-                // if depth > 2 {
-                //     if rng.gen::<f64>() < p {
-                //         ray = Ray {
-                //             o: x,
-                //             d: ray.d - n * 2. * n.dot(ray.d),
-                //         };
-                //         throughput = throughput * rp;
-                //     } else {
-                //         ray = Ray { o: x, d: tdir };
-                //         throughput = throughput * tp;
-                //     }
-                // } else {
-                //     let reflected_dir = ray.d - n * 2. * n.dot(ray.d);
-                //     let reflected_part = throughput * re;
-                //     let refracted_part = throughput * tr;
-
-                //     result += reflected_part
-                //         * radiance(
-                //             world,
-                //             &Ray {
-                //                 o: x,
-                //                 d: reflected_dir,
-                //             },
-                //             depth,
-                //             rng,
-                //         );
-                //     result +=
-                //         refracted_part * radiance(world, &Ray { o: x, d: tdir }, depth, rng);
-                //     break;
-                // }
-            }
+        result += throughput * e;
+        throughput = throughput * scale;
+
+        let material = obj.material.as_ref();
+        let wo = ray.d * -1.;
+        let sample = material.sample(&s, wo, sampler.next(), sampler.next_2d());
+        if !material.is_specular() {
+            let l = direct_light(
+                world, hit.point, &s, wo, material, hit.object, sampler, heuristic,
+            );
+            result += throughput * l;
         }
+        let Some(b) = sample else {
+            break;
+        };
+        prev = (!material.is_specular() && !b.specular).then_some((hit.point, hit.object, b.pdf));
+        throughput = throughput * b.weight;
+        ray = Ray {
+            o: hit.point,
+            d: b.wi,
+        };
     }
     result
 }
 
+/// Absorption along the ray that led to `hit`, for rays that travelled
+/// through the inside of the object.
+fn interior_transmittance(s: &Surface, obj: &Object, hit: &Hit) -> Tup {
+    if s.entering() {
+        Tup::ones()
+    } else {
+        obj.material.transmittance(s, hit.t)
+    }
+}
+
+/// Whether `direct_light` sampled `light` from point `x` on object `id`.
+fn light_sampled(world: &World, light: usize, x: Tup, id: usize) -> bool {
+    light != id && world.lights.contains(&light) && world.objects[light].shape.samples_from(x)
+}
+
+/// Light from all emissive objects that can be sampled, reflected towards
+/// `wo` at point `x` of object `id`. With a heuristic each sample is weighted
+/// against sampling the material.
+#[allow(clippy::too_many_arguments)]
+fn direct_light(
+    world: &World,
+    x: Tup,
+    s: &Surface,
+    wo: Tup,
+    material: &dyn Material,
+    id: usize,
+    sampler: &mut dyn Sampler,
+    heuristic: Option<MisHeuristic>,
+) -> Tup {
+    let mut l = Tup::zeros();
+    for &light in &world.lights {
+        let u = sampler.next_2d();
+        if !light_sampled(world, light, x, id) {
+            continue;
+        }
+        let Some((d, pdf)) = world.objects[light].shape.sample_toward(x, u) else {
+            continue;
+        };
+        let f = material.eval(s, wo, d);
+        if f == Tup::zeros() {
+            continue;
+        }
+        let ray = Ray { o: x, d };
+        match world.first_object(&ray) {
+            Some((hit, i)) if hit == light => {
+                let obj = &world.objects[light];
+                let emission = obj.emission_at(&obj.shape.hit(&ray, &i));
+                let weight = heuristic.map_or(1., |h| h.weight(pdf, material.pdf(s, wo, d)));
+                let cos = d.dot(s.n1).abs();
+                l += emission * f * (weight * cos / pdf);
+            }
+            _ => {}
+        }
+    }
+    l
+}
+
 #[cfg(test)]
 mod tests {
+    use std::f64::consts::PI;
+
     use super::*;
+    use crate::material::{Diffuse, Glass};
+    use crate::mesh::Mesh;
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::Sphere;
+    use crate::texture::{Axis, Gradient};
+
     #[test]
     fn ray_intesects_empty_world() {
         let ray = Ray {
             o: Tup(0., 0., 0.),  // Origin
             d: Tup(0., 0., -1.), // Direction pointing away from any spheres
         };
-        let world = World { spheres: vec![] };
-        let mut sampler = Sampler::new();
+        let world = World::from_objects(vec![]);
+        let mut sampler = IndependentSampler::new(0);
 
-        let result = radiance(&world, &ray, 0, &mut sampler);
+        let result = radiance(&world, &ray, 0, None, &mut sampler, &mut None);
         assert_eq!(result, Tup(0., 0., 0.));
     }
 
     #[test]
     fn ray_intesects_single_sphere_world() {
-        let sphere = Sphere::new(
-            1.0,
-            Tup(0., 0., -5.),
+        let sphere = Object::new(
+            Sphere::new(1.0, Tup(0., 0., -5.)),
             Tup(1., 0., 0.),
             Tup(0., 0., 0.),
-            RflType::DIFF,
+            Diffuse,
         );
-        let world = World {
-            spheres: vec![sphere],
-        };
+        let world = World::from_objects(vec![sphere]);
         let ray = Ray {
             o: Tup(0., 0., 0.),  // Origin
             d: Tup(0., 0., -1.), // Direction pointing away from any spheres
         };
-        let mut sampler = Sampler::new();
+        let mut sampler = IndependentSampler::new(0);
 
-        let result = radiance(&world, &ray, 0, &mut sampler);
+        let result = radiance(&world, &ray, 0, None, &mut sampler, &mut None);
         assert_eq!(result, Tup(1., 0., 0.));
     }
+
+    /// A diffuse floor with albedo 0.5 under a small spherical light. Seen from
+    /// straight above, the floor point below the light reflects
+    /// `albedo * e * r^2 / d^2`, here 0.5 * 10 / 25 = 0.2.
+    fn lit_floor() -> (World, Ray) {
+        let floor = Object::new(
+            Sphere::new(1e4, Tup(0., -1e4, 0.)),
+            Tup::zeros(),
+            Tup(0.5, 0.5, 0.5),
+            Diffuse,
+        );
+        let light = Object::new(
+            Sphere::new(1., Tup(0., 5., 0.)),
+            Tup(10., 10., 10.),
+            Tup::zeros(),
+            Diffuse,
+        );
+        let ray = Ray {
+            o: Tup(0., 2., 0.),
+            d: Tup(0., -1., 0.),
+        };
+        (World::from_objects(vec![floor, light]), ray)
+    }
+
+    fn mean_radiance(int_type: IntegrationType, n: usize) -> Tup {
+        let (world, ray) = lit_floor();
+        mean_radiance_in(&world, &ray, int_type, n)
+    }
+
+    fn mean_radiance_in(world: &World, ray: &Ray, int_type: IntegrationType, n: usize) -> Tup {
+        let mut sampler = IndependentSampler::new(11);
+        let mut sum = Tup::zeros();
+        for i in 0..n {
+            sampler.start_pixel_sample(0, i as u64);
+            let r = Ray { o: ray.o, d: ray.d };
+            sum += integrate(world, r, 0, None, &mut sampler, int_type, &mut None);
+        }
+        sum * (1. / n as f64)
+    }
+
+    #[test]
+    fn path_tracing_matches_analytic_direct_lighting() {
+        let l = mean_radiance(IntegrationType::Iterative, 100_000);
+        assert!((l.0 - 0.2).abs() < 0.01, "{:?}", l);
+    }
+
+    #[test]
+    fn nee_matches_analytic_direct_lighting() {
+        let l = mean_radiance(IntegrationType::NextEvent, 10_000);
+        assert!((l.0 - 0.2).abs() < 0.002, "{:?}", l);
+    }
+
+    /// Lambertian reflection sampled uniformly over the hemisphere, so its
+    /// pdf differs from that of `Diffuse`.
+    #[derive(Debug)]
+    struct UniformDiffuse;
+
+    impl Material for UniformDiffuse {
+        fn sample(&self, s: &Surface, wo: Tup, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+            let cos = u.0;
+            let sin = (1. - cos * cos).sqrt();
+            let (a, b) = s.n1.basis();
+            let phi = 2. * PI * u.1;
+            let wi = a * (sin * phi.cos()) + b * (sin * phi.sin()) + s.n1 * cos;
+            let pdf = self.pdf(s, wo, wi);
+            Some(BsdfSample {
+                wi,
+                weight: self.eval(s, wo, wi) * (cos / pdf),
+                pdf,
+                specular: false,
+            })
+        }
+
+        fn eval(&self, s: &Surface, wo: Tup, wi: Tup) -> Tup {
+            Diffuse.eval(s, wo, wi)
+        }
+
+        fn pdf(&self, s: &Surface, _wo: Tup, wi: Tup) -> f64 {
+            if wi.dot(s.n1) > 0. {
+                1. / (2. * PI)
+            } else {
+                0.
+            }
+        }
+    }
+
+    #[test]
+    fn integrators_only_rely_on_the_material_trait() {
+        let (mut world, ray) = lit_floor();
+        world.objects[0].material = Box::new(UniformDiffuse);
+        for (t, n, tolerance) in [
+            (IntegrationType::Iterative, 100_000, 0.01),
+            (IntegrationType::Recursive, 100_000, 0.01),
+            (IntegrationType::NextEvent, 10_000, 0.002),
+            (IntegrationType::Mis(MisHeuristic::Power), 10_000, 0.004),
+        ] {
+            let l = mean_radiance_in(&world, &ray, t, n);
+            assert!((l.0 - 0.2).abs() < tolerance, "{} {:?}", t, l);
+        }
+    }
+
+    /// Light seen straight through an absorbing sphere of glass that does
+    /// not bend it is dimmed by `exp(-absorption * diameter)`.
+    #[test]
+    fn glass_absorbs_light_inside_it() {
+        let glass = Object::new(
+            Sphere::new(1., Tup(0., 0., -5.)),
+            Tup::zeros(),
+            Tup::ones(),
+            Glass::new(1.).with_absorption(Tup(0.5, 0.25, 0.)),
+        );
+        let light = Object::new(
+            Sphere::new(1e3, Tup(0., 0., -1010.)),
+            Tup::ones(),
+            Tup::zeros(),
+            Diffuse,
+        );
+        let world = World::from_objects(vec![glass, light]);
+        let ray = Ray {
+            o: Tup::zeros(),
+            d: Tup(0., 0., -1.),
+        };
+        let expected = Tup((-1f64).exp(), (-0.5f64).exp(), 1.);
+        for t in [
+            IntegrationType::Iterative,
+            IntegrationType::Recursive,
+            IntegrationType::NextEvent,
+            IntegrationType::Mis(MisHeuristic::Power),
+        ] {
+            let l = mean_radiance_in(&world, &ray, t, 16_000);
+            let error = l - expected;
+            assert!(
+                [error.0, error.1, error.2].iter().all(|e| e.abs() < 0.03),
+                "{} {:?}",
+                t,
+                l
+            );
+        }
+    }
+
+    /// Light sampling has to look up textured emission where the sampled
+    /// direction meets the light, here a sphere bright at the bottom and dark
+    /// at the top.
+    #[test]
+    fn integrators_agree_on_textured_lights() {
+        let floor = Object::new(
+            Sphere::new(1e4, Tup(0., -1e4, 0.)),
+            Tup::zeros(),
+            Tup(0.5, 0.5, 0.5),
+            Diffuse,
+        );
+        let light = Object::new(
+            Sphere::new(1., Tup(0., 3., 0.)),
+            Gradient {
+                from: Tup(8., 8., 8.),
+                to: Tup::zeros(),
+                axis: Axis::V,
+            },
+            Tup::zeros(),
+            Diffuse,
+        );
+        let world = World::from_objects(vec![floor, light]);
+        let ray = Ray {
+            o: Tup(0., 1., 5.),
+            d: Tup(0., -1., -5.).norm(),
+        };
+        let reference = mean_radiance_in(&world, &ray, IntegrationType::Iterative, 40_000);
+        for t in [
+            IntegrationType::Recursive,
+            IntegrationType::NextEvent,
+            IntegrationType::Mis(MisHeuristic::Power),
+        ] {
+            let l = mean_radiance_in(&world, &ray, t, 40_000);
+            assert!(
+                (l.0 - reference.0).abs() < 0.05 * reference.0,
+                "{} {:?} {:?}",
+                t,
+                l,
+                reference
+            );
+        }
+    }
+
+    /// With one bounce allowed the floor still reflects the light; with none
+    /// only emission seen directly is left.
+    #[test]
+    fn max_depth_counts_bounces() {
+        let (world, ray) = lit_floor();
+        for t in [
+            IntegrationType::Iterative,
+            IntegrationType::Recursive,
+            IntegrationType::NextEvent,
+            IntegrationType::Mis(MisHeuristic::Power),
+        ] {
+            let mut sampler = IndependentSampler::new(5);
+            let mut sum = Tup::zeros();
+            let n = 20_000;
+            for i in 0..n {
+                sampler.start_pixel_sample(0, i);
+                let r = Ray { o: ray.o, d: ray.d };
+                sum += integrate(&world, r, 0, Some(1), &mut sampler, t, &mut None);
+            }
+            let l = sum * (1. / n as f64);
+            assert!((l.0 - 0.2).abs() < 0.02, "{} {:?}", t, l);
+
+            let r = Ray { o: ray.o, d: ray.d };
+            let l = integrate(&world, r, 0, Some(0), &mut sampler, t, &mut None);
+            assert_eq!(l, Tup::zeros(), "{}", t);
+        }
+    }
+
+    #[test]
+    fn nee_counts_lights_seen_directly() {
+        let light = Object::new(
+            Sphere::new(1., Tup(0., 0., -5.)),
+            Tup(2., 3., 4.),
+            Tup::zeros(),
+            Diffuse,
+        );
+        let world = World::from_objects(vec![light]);
+        let ray = Ray {
+            o: Tup(0., 0., 0.),
+            d: Tup(0., 0., -1.),
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let result = integrate(
+            &world,
+            ray,
+            0,
+            None,
+            &mut sampler,
+            IntegrationType::NextEvent,
+            &mut None,
+        );
+        assert_eq!(result, Tup(2., 3., 4.));
+    }
+
+    #[test]
+    fn mis_matches_analytic_direct_lighting() {
+        for h in [MisHeuristic::Balance, MisHeuristic::Power] {
+            let l = mean_radiance(IntegrationType::Mis(h), 10_000);
+            assert!((l.0 - 0.2).abs() < 0.004, "{:?} {:?}", h, l);
+        }
+    }
+
+    /// A square light that cannot be sampled reaches diffuse surfaces through
+    /// BSDF sampling alone. Its form factor from the point below its centre
+    /// is 4 times that of a 1 by 1 rectangle at height 5 seen from a corner.
+    #[test]
+    fn unsampled_mesh_lights_are_found_by_bsdf_sampling() {
+        let (floor_world, ray) = lit_floor();
+        let floor = floor_world.objects.into_iter().next().unwrap();
+        let light = Mesh::new(
+            vec![
+                Tup(-1., 5., -1.),
+                Tup(1., 5., -1.),
+                Tup(1., 5., 1.),
+                Tup(-1., 5., 1.),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+        .unwrap();
+        let light = Object::new(light, Tup(10., 10., 10.), Tup::zeros(), Diffuse);
+        let world = World::from_objects(vec![floor, light]);
+        let a = 1. / 26f64.sqrt();
+        let expected = 0.5 * 10. * 4. * 2. * a * a.atan() / (2. * PI);
+        for t in [
+            IntegrationType::NextEvent,
+            IntegrationType::Mis(MisHeuristic::Power),
+        ] {
+            let l = mean_radiance_in(&world, &ray, t, 20_000);
+            assert!((l.0 - expected).abs() < 0.03, "{} {:?} {}", t, l, expected);
+        }
+    }
+
+    #[test]
+    fn records_the_first_hit() {
+        let (world, ray) = lit_floor();
+        for t in [
+            IntegrationType::Iterative,
+            IntegrationType::Recursive,
+            IntegrationType::NextEvent,
+            IntegrationType::Mis(MisHeuristic::Power),
+        ] {
+            let mut sampler = IndependentSampler::new(3);
+            let mut first_hit = None;
+            let r = Ray { o: ray.o, d: ray.d };
+            integrate(&world, r, 0, None, &mut sampler, t, &mut first_hit);
+            let hit = first_hit.unwrap();
+            assert_eq!(hit.object, 0, "{}", t);
+            assert!((hit.depth - 2.).abs() < 1e-6, "{} {}", t, hit.depth);
+            assert!((hit.normal.1 - 1.).abs() < 1e-9, "{} {:?}", t, hit.normal);
+            assert_eq!(hit.albedo, Tup(0.5, 0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn mis_weights_sum_to_one() {
+        for h in [MisHeuristic::Balance, MisHeuristic::Power] {
+            for (a, b) in [(1., 1.), (0.3, 7.), (100., 0.01)] {
+                assert!((h.weight(a, b) + h.weight(b, a) - 1.).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn integration_type_names_round_trip() {
+        for t in [
+            IntegrationType::Iterative,
+            IntegrationType::Recursive,
+            IntegrationType::NextEvent,
+            IntegrationType::Mis(MisHeuristic::Balance),
+            IntegrationType::Mis(MisHeuristic::Power),
+        ] {
+            assert_eq!(t.to_string().parse::<IntegrationType>(), Ok(t));
+        }
+        assert_eq!(
+            "mis".parse::<IntegrationType>(),
+            Ok(IntegrationType::Mis(MisHeuristic::Power))
+        );
+        assert!("bdpt".parse::<IntegrationType>().is_err());
+    }
 }
@@ -0,0 +1,51 @@
+//! A Rust port of smallpt, Kevin Beason's 99-line path tracer.
+//!
+//! [`render`] turns a [`Scene`] into an [`Image`] using a set of
+//! [`RenderSettings`]. The building blocks it uses, such as
+//! [`integrator::integrate`] and [`World::intersect`], are public as well.
+
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+#[cfg(test)]
+mod chi2;
+pub mod conductor;
+pub mod cuboid;
+pub mod dielectric;
+pub mod disk;
+pub mod disney;
+pub mod film;
+pub mod filter;
+pub mod halton;
+pub mod image;
+pub mod input;
+pub mod integrator;
+pub mod material;
+pub mod mesh;
+pub mod microfacet;
+pub mod obj;
+pub mod output;
+pub mod plane;
+pub mod quad;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod shape;
+pub mod sobol;
+pub mod sphere;
+pub mod stratified;
+pub mod texture;
+pub mod textured;
+pub mod tonemap;
+pub mod triangle;
+pub mod tup;
+pub mod world;
+
+pub use camera::Camera;
+pub use image::Image;
+pub use integrator::IntegrationType;
+pub use render::{render, render_with_progress, RenderSettings};
+pub use scene::{Scene, SceneError};
+pub use tup::Tup;
+pub use world::World;
@@ -1,104 +1,65 @@
-mod integrator;
-mod ray;
-mod sphere;
-mod tup;
-mod world;
-mod sampler;
-mod filter;
-
-use std::fs::File;
+mod cli;
+
 use std::io::Write;
-use std::sync::atomic::AtomicUsize;
-use std::sync::atomic::Ordering;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 
-use integrator::integrate;
-use integrator::IntegrationType;
-use rayon::prelude::*;
-
-use ray::Ray;
-use tup::Tup;
-use world::World;
-use sampler::Sampler;
-use filter::tent_filter;
-
-fn clamp(x: f64) -> f64 {
-    if x < 0. {
-        return 0.;
-    } else if x > 1. {
-        return 1.;
-    }
-    x
-}
+use clap::Parser;
 
-fn to_int(x: f64) -> i32 {
-    (clamp(x).powf(1. / 2.2) * 255. + 0.5) as i32
-}
+use cli::Args;
+use smallpt::checkpoint::Checkpoint;
+use smallpt::film::Film;
+use smallpt::render::{render_film, ProgressiveRender};
+use smallpt::{output, RenderSettings, Scene};
 
 fn main() {
-    let w = 640;
-    let h = 480;
-    let num_samples: isize = 50; // will be evaluated to num_samples * 4
-    let cam = Ray {
-        o: Tup(50., 52., 295.6),
-        d: Tup(0., -0.046, -1.).norm(),
-    };
-
-    let cx = Tup(w as f64 * 0.5135 / h as f64, 0.0, 0.0);
-    let cy = (cx.cross(cam.d)).norm() * 0.5135;
-    let mut data: Vec<(usize, usize, Tup)> = vec![];
-    for i in (0..h).rev() {
-        for j in 0..w {
-            data.push((i, j, Tup(0.,0.,0.)));
+    let args = Args::parse();
+
+    if let Some(threads) = args.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()
+        {
+            eprintln!("error: could not start {} worker threads: {}", threads, e);
+            process::exit(1);
         }
     }
 
-    let world = World::new();
-    
-    let now = Instant::now();
-
-    let progress_counter = AtomicUsize::new(0);
-    let total_pixels = h * w;
-
-    data.par_chunks_mut(100).for_each(|slice| {
-        let mut sampler = Sampler::new();
-        slice.into_iter().for_each(|p| {
-            let y = p.0;
-            let x = p.1;
-            for sy in 0..2 {
-                for sx in 0..2 {
-                    let mut rad = Tup(0., 0., 0.);
-                    rad = (0..num_samples).into_iter().fold(rad,|acc, _| {
-                        let (dx, dy) = tent_filter(&mut sampler);
-
-                        let d = cx * (((sx as f64 + 0.5 + dx) / 2. + x as f64) / w as f64 - 0.5)
-                            + cy * (((sy as f64 + 0.5 + dy) / 2. + y as f64) / h as f64 - 0.5)
-                            + cam.d;
-
-                        acc + integrate(
-                            &world,
-                            Ray {
-                                o: cam.o + d * 140.,
-                                d: d.norm(),
-                            },
-                            0,
-                            &mut sampler,
-                            IntegrationType::default(),
-                        ) * (1. / num_samples as f64)
-                    });
-
-                    p.2 = p.2 + Tup(clamp(rad.0), clamp(rad.1), clamp(rad.2)) * 0.25;
-                }
-            }
+    let scene = match &args.scene {
+        Some(path) => Scene::load(path).unwrap_or_else(|e| {
+            eprintln!("error: could not load scene {}: {}", path.display(), e);
+            process::exit(1);
+        }),
+        None => Scene::cornell_box(),
+    };
+    let settings = args
+        .apply(
+            RenderSettings::default().with_scene_settings(&scene.settings),
+            scene.camera.projection,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
         });
 
-        // Increment progress
-        let prev_count = progress_counter.fetch_add(1, Ordering::SeqCst);
+    let now = Instant::now();
 
-        // Print progress
-        let progress = 10000. * prev_count as f64 / (total_pixels as f64);
-        print!("\rRendering {0} spp {1:.2}%", num_samples, progress);
+    let spp = settings.samples_per_pixel();
+    // Checkpoints are taken between passes; without a pass size of its own a
+    // checkpointed render takes one sample per subpixel at a time.
+    let pass_samples = args.pass_samples.or_else(|| {
+        args.checkpoint
+            .as_ref()
+            .map(|_| settings.subpixels * settings.subpixels)
     });
+    let film = match pass_samples {
+        Some(pass_samples) => render_progressive(&scene, &settings, &args, pass_samples),
+        None => render_film(&scene, &settings, |done, total| {
+            print!("\rRendering {0} spp {1:.2}%", spp, 100. * done as f64 / total as f64);
+            let _ = std::io::stdout().flush();
+        }),
+    };
 
     let elapsed_time = now.elapsed();
     println!(
@@ -106,16 +67,145 @@ fn main() {
         elapsed_time.as_secs(),
     );
 
-    let mut f = File::create("image.ppm").unwrap();
-    writeln!(f, "P3\n{} {}\n255", w, h).unwrap();
-    for i in 0..w * h {
-        writeln!(
-            f,
-            "{} {} {}",
-            to_int(data[i].2.0),
-            to_int(data[i].2.1),
-            to_int(data[i].2.2)
-        )
-        .unwrap();
+    save(&film, &args);
+    // A finished render leaves nothing to resume.
+    if let Some(path) = &args.checkpoint {
+        if let Err(e) = Checkpoint::remove(path) {
+            eprintln!("error: could not remove checkpoint {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+    if let Some(path) = &args.spp_map {
+        if let Err(e) = film.sample_heatmap().save(path) {
+            eprintln!("error: could not write {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Set by Ctrl-C while a checkpointed render runs.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Renders in passes, writing the image whenever a snapshot is due and the
+/// render state whenever a checkpoint is.
+fn render_progressive(
+    scene: &Scene,
+    settings: &RenderSettings,
+    args: &Args,
+    pass_samples: u32,
+) -> Film {
+    let spp = settings.samples_per_pixel();
+    let mut render = if args.resume {
+        let path = args
+            .checkpoint
+            .as_ref()
+            .expect("--resume requires --checkpoint");
+        let checkpoint = Checkpoint::load(path).unwrap_or_else(|e| {
+            eprintln!("error: could not load checkpoint {}: {}", path.display(), e);
+            process::exit(1);
+        });
+        if let Err(e) = checkpoint.matches(scene, settings, pass_samples) {
+            eprintln!("error: cannot resume from {}: {}", path.display(), e);
+            process::exit(1);
+        }
+        checkpoint.render
+    } else {
+        ProgressiveRender::new(settings)
+    };
+    if args.checkpoint.is_some() {
+        // The first Ctrl-C stops the render at a pass boundary; a second one
+        // exits at once.
+        if let Err(e) = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                process::exit(130);
+            }
+        }) {
+            eprintln!("error: could not handle Ctrl-C: {}", e);
+            process::exit(1);
+        }
+    }
+
+    let mut passes = 0;
+    let mut last_snapshot = Instant::now();
+    let mut last_checkpoint = Instant::now();
+    while !render.is_done(settings) {
+        let start = render.samples;
+        let end = start.saturating_add(pass_samples).min(spp);
+        let finished = render.interruptible_pass(
+            scene,
+            settings,
+            pass_samples,
+            &INTERRUPTED,
+            |done, total| {
+                let samples = start as f64 + (end - start) as f64 * done as f64 / total as f64;
+                print!(
+                    "\rRendering {0} spp {1:.2}%",
+                    spp,
+                    100. * samples / spp as f64
+                );
+                let _ = std::io::stdout().flush();
+            },
+        );
+        if !finished {
+            save_checkpoint(scene, settings, args, pass_samples, &render);
+            save(&render.film, args);
+            println!(
+                "\nInterrupted after {} spp; continue with --resume.",
+                render.samples
+            );
+            process::exit(130);
+        }
+        passes += 1;
+
+        if args.checkpoint.is_some()
+            && !render.is_done(settings)
+            && last_checkpoint.elapsed().as_secs_f64() >= args.checkpoint_seconds
+        {
+            save_checkpoint(scene, settings, args, pass_samples, &render);
+            last_checkpoint = Instant::now();
+        }
+
+        let due = match (args.snapshot_passes, args.snapshot_seconds) {
+            _ if args.pass_samples.is_none() => false,
+            (None, None) => true,
+            (k, t) => {
+                k.is_some_and(|k| passes % k == 0)
+                    || t.is_some_and(|t| last_snapshot.elapsed().as_secs_f64() >= t)
+            }
+        };
+        // The final image is written by the caller.
+        if due && !render.is_done(settings) {
+            save(&render.film, args);
+            last_snapshot = Instant::now();
+        }
+    }
+    render.film
+}
+
+fn save_checkpoint(
+    scene: &Scene,
+    settings: &RenderSettings,
+    args: &Args,
+    pass_samples: u32,
+    render: &ProgressiveRender,
+) {
+    let Some(path) = &args.checkpoint else {
+        return;
+    };
+    let checkpoint = Checkpoint::new(scene, settings, pass_samples, render.clone());
+    if let Err(e) = checkpoint.save(path) {
+        eprintln!(
+            "error: could not write checkpoint {}: {}",
+            path.display(),
+            e
+        );
+        process::exit(1);
+    }
+}
+
+fn save(film: &Film, args: &Args) {
+    if let Err(e) = output::save_film(film, &args.output, args.output_options()) {
+        eprintln!("error: could not write {}: {}", args.output.display(), e);
+        process::exit(1);
     }
 }
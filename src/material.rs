@@ -0,0 +1,396 @@
+use std::f64::consts::PI;
+use std::fmt::Debug;
+
+use super::ray::Ray;
+use super::shape::Hit;
+use super::tup::Tup;
+
+/// The local surface a material scatters light at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surface {
+    /// Shading normal.
+    pub n: Tup,
+    /// Shading normal turned towards the side of the surface the path came
+    /// from, as judged by the geometric normal.
+    pub n1: Tup,
+    /// Color of the object at the hit.
+    pub color: Tup,
+    /// Where the hit is, and its texture coordinates, for materials whose
+    /// parameters are textures.
+    pub point: Tup,
+    pub uv: (f64, f64),
+}
+
+impl Surface {
+    pub fn new(ray: &Ray, hit: &Hit, color: Tup) -> Self {
+        let n = hit.shading_normal;
+        let n1 = if hit.normal.dot(ray.d) < 0.0 {
+            n
+        } else {
+            n * -1.0
+        };
+        Surface {
+            n,
+            n1,
+            color,
+            point: hit.point,
+            uv: hit.uv,
+        }
+    }
+
+    /// Whether the path arrived from the side the shading normal points to.
+    pub fn entering(&self) -> bool {
+        self.n.dot(self.n1) > 0.
+    }
+}
+
+/// A direction chosen by `Material::sample`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BsdfSample {
+    /// Direction the path continues in.
+    pub wi: Tup,
+    /// BSDF times cosine over pdf; what the path throughput is multiplied by.
+    pub weight: Tup,
+    /// Solid angle pdf of `wi`, or the probability of the chosen direction
+    /// for specular samples.
+    pub pdf: f64,
+    /// Whether `wi` is one of finitely many directions, which light
+    /// sampling can never produce.
+    pub specular: bool,
+}
+
+/// How a surface scatters light. Directions point away from the surface:
+/// `wo` back along the incoming path and `wi` along the continued one.
+pub trait Material: Debug + Send + Sync {
+    /// Picks a direction to continue the path in from the uniform numbers `uc`,
+    /// which chooses between lobes, and `u`. `None` means the path is absorbed.
+    fn sample(&self, s: &Surface, wo: Tup, uc: f64, u: (f64, f64)) -> Option<BsdfSample>;
+
+    /// The BSDF for light arriving from `wi`, without the cosine. Always zero
+    /// for specular materials.
+    fn eval(&self, s: &Surface, wo: Tup, wi: Tup) -> Tup;
+
+    /// Solid angle pdf of `sample` choosing `wi`.
+    fn pdf(&self, s: &Surface, wo: Tup, wi: Tup) -> f64;
+
+    /// Whether the material only scatters into finitely many directions, so
+    /// sampling lights at it is pointless.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// Fraction of light that survives travelling `distance` through the
+    /// inside of the material. Paths that reach a surface from behind have
+    /// just done so, and `s` is where they left it.
+    fn transmittance(&self, _s: &Surface, _distance: f64) -> Tup {
+        Tup::ones()
+    }
+
+    /// Every direction a specular material scatters into, weighted so they
+    /// add up to its full response. The recursive integrator follows them all
+    /// near the camera, as smallpt does for glass, and falls back on `sample`
+    /// when this is empty, as it is by default.
+    fn lobes(&self, _s: &Surface, _wo: Tup) -> Vec<BsdfSample> {
+        Vec::new()
+    }
+}
+
+impl<M: Material + 'static> From<M> for Box<dyn Material> {
+    fn from(m: M) -> Self {
+        Box::new(m)
+    }
+}
+
+/// Lambertian reflection, smallpt's `DIFF`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diffuse;
+
+impl Material for Diffuse {
+    fn sample(&self, s: &Surface, _wo: Tup, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let wi = sample_cosine(s.n1, u);
+        Some(BsdfSample {
+            wi,
+            weight: s.color,
+            pdf: wi.dot(s.n1) / PI,
+            specular: false,
+        })
+    }
+
+    fn eval(&self, s: &Surface, _wo: Tup, wi: Tup) -> Tup {
+        if wi.dot(s.n1) > 0. {
+            s.color * (1. / PI)
+        } else {
+            Tup::zeros()
+        }
+    }
+
+    fn pdf(&self, s: &Surface, _wo: Tup, wi: Tup) -> f64 {
+        wi.dot(s.n1).max(0.) / PI
+    }
+}
+
+/// A perfect mirror, smallpt's `SPEC`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mirror;
+
+impl Material for Mirror {
+    fn sample(&self, s: &Surface, wo: Tup, _uc: f64, _u: (f64, f64)) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            wi: reflect(wo, s.n),
+            weight: s.color,
+            pdf: 1.,
+            specular: true,
+        })
+    }
+
+    fn eval(&self, _s: &Surface, _wo: Tup, _wi: Tup) -> Tup {
+        Tup::zeros()
+    }
+
+    fn pdf(&self, _s: &Surface, _wo: Tup, _wi: Tup) -> f64 {
+        0.
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+/// Smooth glass with Schlick's Fresnel approximation, smallpt's `REFR`. Light
+/// inside it is absorbed following the Beer-Lambert law, by `absorption` per
+/// unit of distance in each channel. The outside is taken to be vacuum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glass {
+    pub ior: f64,
+    pub absorption: Tup,
+}
+
+impl Default for Glass {
+    fn default() -> Self {
+        Glass::new(1.5)
+    }
+}
+
+impl Glass {
+    pub fn new(ior: f64) -> Self {
+        Glass {
+            ior,
+            absorption: Tup::zeros(),
+        }
+    }
+
+    pub fn with_absorption(self, absorption: Tup) -> Self {
+        Glass { absorption, ..self }
+    }
+
+    /// The index of refraction of a named substance: `water`, `ice`,
+    /// `glass`, `sapphire` or `diamond`.
+    pub fn preset_ior(name: &str) -> Option<f64> {
+        match name {
+            "water" => Some(1.333),
+            "ice" => Some(1.309),
+            "glass" => Some(1.5),
+            "sapphire" => Some(1.77),
+            "diamond" => Some(2.417),
+            _ => None,
+        }
+    }
+
+    /// The reflected and, unless it is totally reflected, the refracted
+    /// direction with their Fresnel weights.
+    fn split(&self, s: &Surface, wo: Tup) -> (Tup, Option<(Tup, f64, f64)>) {
+        let d = wo * -1.;
+        let reflected = reflect(wo, s.n);
+        let into = s.entering();
+        let nc: f64 = 1.;
+        let nt: f64 = self.ior;
+        let nnt = if into { nc / nt } else { nt / nc };
+        let ddn = d.dot(s.n1);
+        let cos2t = 1. - nnt * nnt * (1. - ddn * ddn);
+        if cos2t < 0. {
+            return (reflected, None);
+        }
+
+        let tdir =
+            (d * nnt - s.n * if into { 1. } else { -1. } * (ddn * nnt + cos2t.sqrt())).norm();
+        let a = nt - nc;
+        let b = nt + nc;
+        let r0 = (a * a) / (b * b);
+        let c = 1. - if into { -ddn } else { tdir.dot(s.n) };
+        let re = r0 + (1. - r0) * c * c * c * c * c;
+        (reflected, Some((tdir, re, 1. - re)))
+    }
+}
+
+impl Material for Glass {
+    /// Chooses between reflection and refraction with a probability based
+    /// on the Fresnel term, weighting the choice to compensate.
+    fn sample(&self, s: &Surface, wo: Tup, uc: f64, _u: (f64, f64)) -> Option<BsdfSample> {
+        let (reflected, refracted) = self.split(s, wo);
+        let Some((tdir, re, tr)) = refracted else {
+            return Some(specular(reflected, s.color, 1.));
+        };
+        let p = 0.25 + 0.5 * re;
+        Some(if uc < p {
+            BsdfSample {
+                pdf: p,
+                ..specular(reflected, s.color * (re / p), 1.)
+            }
+        } else {
+            BsdfSample {
+                pdf: 1. - p,
+                ..specular(tdir, s.color * (tr / (1. - p)), 1.)
+            }
+        })
+    }
+
+    fn eval(&self, _s: &Surface, _wo: Tup, _wi: Tup) -> Tup {
+        Tup::zeros()
+    }
+
+    fn pdf(&self, _s: &Surface, _wo: Tup, _wi: Tup) -> f64 {
+        0.
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    fn transmittance(&self, _s: &Surface, distance: f64) -> Tup {
+        beer_lambert(self.absorption, distance)
+    }
+
+    fn lobes(&self, s: &Surface, wo: Tup) -> Vec<BsdfSample> {
+        match self.split(s, wo) {
+            (reflected, None) => vec![specular(reflected, s.color, 1.)],
+            (reflected, Some((tdir, re, tr))) => vec![
+                specular(reflected, s.color * re, 1.),
+                specular(tdir, s.color * tr, 1.),
+            ],
+        }
+    }
+}
+
+pub(crate) fn specular(wi: Tup, weight: Tup, pdf: f64) -> BsdfSample {
+    BsdfSample {
+        wi,
+        weight,
+        pdf,
+        specular: true,
+    }
+}
+
+/// Fraction of light left after `distance` in a medium with the absorption
+/// coefficient `absorption`.
+pub fn beer_lambert(absorption: Tup, distance: f64) -> Tup {
+    let a = absorption * -distance;
+    Tup(a.0.exp(), a.1.exp(), a.2.exp())
+}
+
+/// Mirrors the outgoing direction `wo` about the normal `n`.
+pub fn reflect(wo: Tup, n: Tup) -> Tup {
+    n * 2. * n.dot(wo) - wo
+}
+
+/// Cosine-weighted direction on the hemisphere around `n`.
+pub fn sample_cosine(n: Tup, u: (f64, f64)) -> Tup {
+    let r1 = 2. * PI * u.0;
+    let r2s = u.1.sqrt();
+    let (a, b) = n.basis();
+    (a * f64::cos(r1) * r2s + b * f64::sin(r1) * r2s + n * ((1. - u.1).sqrt())).norm()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface(color: Tup) -> Surface {
+        Surface {
+            n: Tup(0., 0., 1.),
+            n1: Tup(0., 0., 1.),
+            color,
+            point: Tup::zeros(),
+            uv: (0., 0.),
+        }
+    }
+
+    #[test]
+    fn diffuse_samples_agree_with_eval_and_pdf() {
+        let s = surface(Tup(0.5, 0.25, 1.));
+        let wo = Tup(0., 0.6, 0.8);
+        for u in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.99)] {
+            let b = Diffuse.sample(&s, wo, 0.5, u).unwrap();
+            assert!(b.wi.dot(s.n1) > 0.);
+            assert!((Diffuse.pdf(&s, wo, b.wi) - b.pdf).abs() < 1e-12);
+            let weight = Diffuse.eval(&s, wo, b.wi) * (b.wi.dot(s.n1) / b.pdf);
+            assert!((weight - b.weight).dot(Tup::ones()).abs() < 1e-12);
+        }
+        assert_eq!(Diffuse.eval(&s, wo, Tup(0., 0., -1.)), Tup::zeros());
+        assert_eq!(Diffuse.pdf(&s, wo, Tup(0., 0., -1.)), 0.);
+    }
+
+    #[test]
+    fn mirror_reflects_about_the_normal() {
+        let s = surface(Tup::ones());
+        let b = Mirror
+            .sample(&s, Tup(0., 0.6, 0.8), 0.5, (0.5, 0.5))
+            .unwrap();
+        assert_eq!(b.wi, Tup(0., -0.6, 0.8));
+        assert!(b.specular);
+        assert_eq!(Mirror.eval(&s, Tup(0., 0.6, 0.8), b.wi), Tup::zeros());
+    }
+
+    #[test]
+    fn glass_lobes_conserve_energy() {
+        let wo = Tup(0., 0.6, 0.8);
+        for s in [
+            surface(Tup::ones()),
+            // Leaving the glass.
+            Surface {
+                n1: Tup(0., 0., -1.),
+                ..surface(Tup::ones())
+            },
+        ] {
+            let wo = if s.entering() { wo } else { wo * -1. };
+            let lobes = Glass::default().lobes(&s, wo);
+            let total: f64 = lobes.iter().map(|b| b.weight.0).sum();
+            assert!((total - 1.).abs() < 1e-12, "{:?}", lobes);
+            // Sampling reaches the same directions with the same mean weight.
+            let mean: f64 = [0.1, 0.9]
+                .iter()
+                .map(|&u| {
+                    let b = Glass::default().sample(&s, wo, u, (0.5, 0.5)).unwrap();
+                    assert!(lobes.iter().any(|l| l.wi == b.wi));
+                    b.weight.0 * b.pdf
+                })
+                .sum();
+            assert!((mean - 1.).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn glass_refracts_towards_the_normal() {
+        let s = surface(Tup::ones());
+        let wo = Tup(0., 0.6, 0.8);
+        for name in ["water", "glass", "diamond"] {
+            let ior = Glass::preset_ior(name).unwrap();
+            let lobes = Glass::new(ior).lobes(&s, wo);
+            let t = lobes[1].wi;
+            assert!(t.2 < 0.);
+            // Snell's law.
+            assert!((t.1.abs() * ior - 0.6).abs() < 1e-12, "{}", name);
+        }
+        assert_eq!(Glass::preset_ior("jelly"), None);
+    }
+
+    #[test]
+    fn glass_absorbs_with_distance() {
+        let glass = Glass::default().with_absorption(Tup(0., 0.5, 1.));
+        let s = surface(Tup::ones());
+        let t = glass.transmittance(&s, 2.);
+        assert_eq!(t.0, 1.);
+        assert!((t.1 - (-1f64).exp()).abs() < 1e-12);
+        assert!((t.2 - (-2f64).exp()).abs() < 1e-12);
+        assert_eq!(Diffuse.transmittance(&s, 2.), Tup::ones());
+    }
+}
@@ -0,0 +1,252 @@
+use super::bvh::{Aabb, Bvh};
+use super::ray::Ray;
+use super::shape::{Hit, Intersection, Shape};
+use super::triangle::{intersect_triangle, triangle_bounds, triangle_hit};
+use super::tup::Tup;
+
+/// An indexed triangle mesh. Vertex normals and uvs are optional; when present
+/// there is one per position and the triangles index all three alike.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub positions: Vec<Tup>,
+    pub normals: Vec<Tup>,
+    pub uvs: Vec<(f64, f64)>,
+    pub triangles: Vec<[usize; 3]>,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Tup>, triangles: Vec<[usize; 3]>) -> Result<Self, String> {
+        if triangles.is_empty() {
+            return Err("a mesh needs at least one triangle".to_string());
+        }
+        if let Some(i) = triangles.iter().flatten().find(|&&i| i >= positions.len()) {
+            return Err(format!(
+                "vertex index {} is out of range for {} positions",
+                i,
+                positions.len()
+            ));
+        }
+        let bounds: Vec<_> = triangles
+            .iter()
+            .map(|t| triangle_bounds(&t.map(|i| positions[i])))
+            .collect();
+        Ok(Mesh {
+            bvh: Bvh::build(&bounds),
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            triangles,
+        })
+    }
+
+    /// Adds vertex normals for smooth shading.
+    pub fn with_normals(self, normals: Vec<Tup>) -> Result<Self, String> {
+        if normals.len() != self.positions.len() {
+            return Err(format!(
+                "expected {} normals, one per position, got {}",
+                self.positions.len(),
+                normals.len()
+            ));
+        }
+        if normals.iter().any(|n| n.dot(*n) == 0.) {
+            return Err("normals must not be zero".to_string());
+        }
+        Ok(Mesh {
+            normals: normals.into_iter().map(Tup::norm).collect(),
+            ..self
+        })
+    }
+
+    pub fn with_uvs(self, uvs: Vec<(f64, f64)>) -> Result<Self, String> {
+        if uvs.len() != self.positions.len() {
+            return Err(format!(
+                "expected {} uvs, one per position, got {}",
+                self.positions.len(),
+                uvs.len()
+            ));
+        }
+        Ok(Mesh { uvs, ..self })
+    }
+
+    /// Scales the mesh about the origin by a positive `scale`, then moves it
+    /// by `offset`.
+    pub fn place(self, scale: f64, offset: Tup) -> Self {
+        let positions = self.positions.iter().map(|&p| p * scale + offset).collect();
+        Mesh {
+            normals: self.normals,
+            uvs: self.uvs,
+            ..Mesh::new(positions, self.triangles).expect("indices were checked")
+        }
+    }
+
+    fn vertices(&self, triangle: usize) -> [Tup; 3] {
+        self.triangles[triangle].map(|i| self.positions[i])
+    }
+}
+
+impl Shape for Mesh {
+    fn bounds(&self) -> Aabb {
+        self.triangles
+            .iter()
+            .flatten()
+            .fold(Aabb::empty(), |b, &i| b.grow(self.positions[i]))
+    }
+
+    fn intersect(&self, ray: &Ray, t_max: f64) -> Option<Intersection> {
+        let mut closest: Option<Intersection> = None;
+        self.bvh.traverse(ray, t_max, |triangle| {
+            let t_max = closest.map_or(t_max, |c| c.t);
+            if let Some((t, b)) = intersect_triangle(ray, &self.vertices(triangle), t_max) {
+                closest = Some(Intersection {
+                    t,
+                    primitive: triangle,
+                    b,
+                });
+                return t;
+            }
+            t_max
+        });
+        closest
+    }
+
+    fn hit(&self, ray: &Ray, i: &Intersection) -> Hit {
+        let triangle = self.triangles[i.primitive];
+        let normals = (!self.normals.is_empty()).then(|| triangle.map(|v| self.normals[v]));
+        let uvs = (!self.uvs.is_empty()).then(|| triangle.map(|v| self.uvs[v]));
+        triangle_hit(
+            ray,
+            i,
+            &self.vertices(i.primitive),
+            normals.as_ref(),
+            uvs.as_ref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triangle::Triangle;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// A unit quad in the xy plane made of two triangles.
+    fn quad() -> Mesh {
+        Mesh::new(
+            vec![
+                Tup(0., 0., 0.),
+                Tup(1., 0., 0.),
+                Tup(1., 1., 0.),
+                Tup(0., 1., 0.),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn ray_hits_the_right_triangle() {
+        let mesh = quad()
+            .with_uvs(vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)])
+            .unwrap();
+        let ray = Ray {
+            o: Tup(0.25, 0.75, 1.),
+            d: Tup(0., 0., -1.),
+        };
+        let i = mesh.intersect(&ray, f64::INFINITY).unwrap();
+        assert_eq!(i.primitive, 1);
+        let hit = mesh.hit(&ray, &i);
+        assert_eq!(hit.primitive, 1);
+        assert_eq!(hit.normal, Tup(0., 0., 1.));
+        assert!((hit.uv.0 - 0.25).abs() < 1e-12 && (hit.uv.1 - 0.75).abs() < 1e-12);
+        assert!(mesh
+            .intersect(
+                &Ray {
+                    o: Tup(1.5, 0.5, 1.),
+                    d: Tup(0., 0., -1.)
+                },
+                f64::INFINITY
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn smooth_normals_are_interpolated() {
+        let mesh = quad()
+            .with_normals(vec![
+                Tup(-1., 0., 1.),
+                Tup(1., 0., 1.),
+                Tup(1., 0., 1.),
+                Tup(-1., 0., 1.),
+            ])
+            .unwrap();
+        let ray = Ray {
+            o: Tup(0.5, 0.3, 1.),
+            d: Tup(0., 0., -1.),
+        };
+        let hit = mesh.hit(&ray, &mesh.intersect(&ray, f64::INFINITY).unwrap());
+        assert!((hit.shading_normal.0).abs() < 1e-12);
+        assert!((hit.shading_normal.2 - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn placed_meshes_move_their_hits() {
+        let mesh = quad().place(2., Tup(0., 0., -3.));
+        let ray = Ray {
+            o: Tup(1.5, 1.5, 1.),
+            d: Tup(0., 0., -1.),
+        };
+        let i = mesh.intersect(&ray, f64::INFINITY).unwrap();
+        assert_eq!((i.t, i.primitive), (4., 0));
+        assert_eq!(mesh.bounds().max, Tup(2., 2., -3.));
+    }
+
+    #[test]
+    fn rejects_bad_indices_and_attributes() {
+        assert!(Mesh::new(vec![Tup::zeros()], vec![]).is_err());
+        let err = Mesh::new(vec![Tup::zeros(); 3], vec![[0, 1, 3]]).unwrap_err();
+        assert!(err.contains("vertex index 3"), "{}", err);
+        assert!(quad().with_normals(vec![Tup(0., 0., 1.)]).is_err());
+        assert!(quad().with_normals(vec![Tup::zeros(); 4]).is_err());
+        assert!(quad().with_uvs(vec![(0., 0.); 3]).is_err());
+    }
+
+    #[test]
+    fn matches_its_triangles() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut point = || {
+            Tup(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+        };
+        let positions: Vec<_> = (0..300).map(|_| point()).collect();
+        let triangles: Vec<_> = (0..100).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+        let mesh = Mesh::new(positions, triangles).unwrap();
+        let singles: Vec<_> = (0..100)
+            .map(|i| {
+                Triangle::new(
+                    mesh.positions[3 * i],
+                    mesh.positions[3 * i + 1],
+                    mesh.positions[3 * i + 2],
+                )
+            })
+            .collect();
+        for _ in 0..1000 {
+            let ray = Ray {
+                o: point() * 3.,
+                d: point().norm(),
+            };
+            let expected = singles
+                .iter()
+                .enumerate()
+                .filter_map(|(i, s)| s.intersect(&ray, f64::INFINITY).map(|x| (x.t, i)))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let got = mesh
+                .intersect(&ray, f64::INFINITY)
+                .map(|x| (x.t, x.primitive));
+            assert_eq!(got, expected);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::f64::consts::PI;
+
+use super::tup::Tup;
+
+/// Roughness below which a microfacet material is treated as perfectly smooth.
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// An orthonormal frame around a normal. The first tangent follows the scene's
+/// x axis projected onto the surface, or its z axis where the surface faces
+/// along x, so that anisotropic roughness has a predictable direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub t: Tup,
+    pub b: Tup,
+    pub n: Tup,
+}
+
+impl Frame {
+    pub fn new(n: Tup) -> Self {
+        let axis = if n.0.abs() < 0.9 {
+            Tup(1., 0., 0.)
+        } else {
+            Tup(0., 0., 1.)
+        };
+        let t = (axis - n * n.dot(axis)).norm();
+        Frame {
+            t,
+            b: n.cross(t),
+            n,
+        }
+    }
+
+    pub fn to_local(&self, v: Tup) -> Tup {
+        Tup(v.dot(self.t), v.dot(self.b), v.dot(self.n))
+    }
+
+    pub fn from_local(&self, v: Tup) -> Tup {
+        self.t * v.0 + self.b * v.1 + self.n * v.2
+    }
+}
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals, with
+/// separate widths along the two tangents of a [`Frame`]. Directions are in
+/// that frame, with the macro normal along z.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    /// The distribution for a perceptual roughness between 0 and 1 along each
+    /// tangent. The width is its square, as in Disney's BRDF.
+    pub fn from_roughness(roughness: (f64, f64)) -> Self {
+        Ggx {
+            alpha_x: roughness.0 * roughness.0,
+            alpha_y: roughness.1 * roughness.1,
+        }
+    }
+
+    /// Whether the distribution is so narrow that the surface is best treated
+    /// as a perfect mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals `wm` per projected area.
+    pub fn d(&self, wm: Tup) -> f64 {
+        if wm.2 <= 0. {
+            return 0.;
+        }
+        let (ax, ay) = self.alphas();
+        let x = wm.0 / ax;
+        let y = wm.1 / ay;
+        let s = x * x + y * y + wm.2 * wm.2;
+        1. / (PI * ax * ay * s * s)
+    }
+
+    /// Smith's auxiliary function, the ratio of hidden to visible microfacet
+    /// area seen from `w`.
+    pub fn lambda(&self, w: Tup) -> f64 {
+        if w.2 == 0. {
+            return f64::INFINITY;
+        }
+        let (ax, ay) = self.alphas();
+        let tan2 = (ax * ax * w.0 * w.0 + ay * ay * w.1 * w.1) / (w.2 * w.2);
+        ((1. + tan2).sqrt() - 1.) / 2.
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: Tup) -> f64 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both `wo` and `wi`, with
+    /// height-correlated masking and shadowing.
+    pub fn g(&self, wo: Tup, wi: Tup) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `w`; the pdf of `sample_visible`.
+    /// Like it, this looks at the surface from the upper side.
+    pub fn visible_d(&self, w: Tup, wm: Tup) -> f64 {
+        if w.2 == 0. {
+            return 0.;
+        }
+        let w = if w.2 < 0. { w * -1. } else { w };
+        self.g1(w) / w.2 * self.d(wm) * w.dot(wm).max(0.)
+    }
+
+    /// Samples a microfacet normal visible from `w`, following Heitz,
+    /// "Sampling the GGX Distribution of Visible Normals" (2018). Directions
+    /// below the surface are treated like their mirror image above it.
+    pub fn sample_visible(&self, w: Tup, u: (f64, f64)) -> Tup {
+        let (ax, ay) = self.alphas();
+        // Stretch the view direction to the configuration of a hemisphere.
+        let mut wh = Tup(ax * w.0, ay * w.1, w.2).norm();
+        if wh.2 < 0. {
+            wh = wh * -1.;
+        }
+        let len2 = wh.0 * wh.0 + wh.1 * wh.1;
+        let t1 = if len2 > 0. {
+            Tup(-wh.1, wh.0, 0.) * (1. / len2.sqrt())
+        } else {
+            Tup(1., 0., 0.)
+        };
+        let t2 = wh.cross(t1);
+
+        // A uniform point on the disk, warped to the projection of the part
+        // of the hemisphere visible from `wh`.
+        let r = u.0.sqrt();
+        let phi = 2. * PI * u.1;
+        let p1 = r * phi.cos();
+        let mut p2 = r * phi.sin();
+        let s = 0.5 * (1. + wh.2);
+        p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * p2;
+        let nh = t1 * p1 + t2 * p2 + wh * (1. - p1 * p1 - p2 * p2).max(0.).sqrt();
+
+        // Unstretch.
+        Tup(ax * nh.0, ay * nh.1, nh.2.max(1e-6)).norm()
+    }
+
+    /// The widths, kept away from zero where they would make the density
+    /// singular.
+    fn alphas(&self) -> (f64, f64) {
+        (
+            self.alpha_x.max(SMOOTH_ALPHA),
+            self.alpha_y.max(SMOOTH_ALPHA),
+        )
+    }
+}
+
+/// Refracts `w` through a surface with normal `n`, going from the side `w` is
+/// on into a medium with relative index of refraction `eta` if `w` is above
+/// the surface, or `1 / eta` if it is below. Returns the refracted direction
+/// and the relative index it entered, or `None` on total internal reflection.
+pub fn refract(w: Tup, n: Tup, eta: f64) -> Option<(Tup, f64)> {
+    let mut cos_i = n.dot(w);
+    let (mut n, mut eta) = (n, eta);
+    if cos_i < 0. {
+        eta = 1. / eta;
+        cos_i = -cos_i;
+        n = n * -1.;
+    }
+    let sin2_i = (1. - cos_i * cos_i).max(0.);
+    let sin2_t = sin2_i / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    Some((w * (-1. / eta) + n * (cos_i / eta - cos_t), eta))
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface with relative
+/// index of refraction `eta`, for light arriving at `cos_i` to its normal.
+/// Negative cosines come from inside.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let mut cos_i = cos_i.clamp(-1., 1.);
+    let mut eta = eta;
+    if cos_i < 0. {
+        eta = 1. / eta;
+        cos_i = -cos_i;
+    }
+    let sin2_i = 1. - cos_i * cos_i;
+    let sin2_t = sin2_i / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).max(0.).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.
+}
+
+/// Unpolarized Fresnel reflectance of a conductor with complex index of
+/// refraction `eta + i k`, per color channel.
+pub fn fresnel_conductor(cos_i: f64, eta: Tup, k: Tup) -> Tup {
+    let f = |eta: f64, k: f64| {
+        let cos_i = cos_i.clamp(0., 1.);
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1. - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+        let t2 = 2. * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Tup(f(eta.0, k.0), f(eta.1, k.1), f(eta.2, k.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn sphere_direction(rng: &mut StdRng) -> Tup {
+        let z: f64 = rng.gen_range(-1. ..1.);
+        let phi = rng.gen_range(0. ..2. * PI);
+        let r = (1. - z * z).sqrt();
+        Tup(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    #[test]
+    fn frames_are_orthonormal() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for n in (0..100)
+            .map(|_| sphere_direction(&mut rng))
+            .chain([Tup(1., 0., 0.), Tup(0., -1., 0.)])
+        {
+            let f = Frame::new(n);
+            for (a, b) in [(f.t, f.b), (f.b, f.n), (f.n, f.t)] {
+                assert!(a.dot(b).abs() < 1e-12);
+            }
+            let v = Tup(0.3, -0.2, 0.9);
+            let back = f.from_local(f.to_local(v));
+            assert!((back - v).dot(back - v) < 1e-20);
+        }
+    }
+
+    /// The projected area of the microfacets is that of the surface, and
+    /// seen from any direction the visible ones add up to the projected area
+    /// of the surface.
+    #[test]
+    fn ggx_is_normalized() {
+        let ggx = Ggx::from_roughness((0.5, 0.8));
+        let mut rng = StdRng::seed_from_u64(2);
+        let n = 200_000;
+        let w = Tup(0.5, 0.2, 0.6).norm();
+        let (mut projected, mut visible) = (0., 0.);
+        for _ in 0..n {
+            let wm = sphere_direction(&mut rng);
+            projected += ggx.d(wm) * wm.2.max(0.);
+            visible += ggx.visible_d(w, wm);
+        }
+        let area = 4. * PI / n as f64;
+        assert!((projected * area - 1.).abs() < 0.02, "{}", projected * area);
+        assert!((visible * area - 1.).abs() < 0.02, "{}", visible * area);
+    }
+
+    #[test]
+    fn visible_normals_face_the_viewer() {
+        let ggx = Ggx::from_roughness((0.7, 0.3));
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..1000 {
+            let w = sphere_direction(&mut rng);
+            let wm = ggx.sample_visible(w, (rng.gen(), rng.gen()));
+            assert!(wm.2 > 0.);
+            assert!((wm.dot(wm) - 1.).abs() < 1e-9);
+            assert!(wm.dot(w) * w.2.signum() >= -1e-9, "{:?} {:?}", w, wm);
+        }
+    }
+
+    #[test]
+    fn refraction_follows_snell() {
+        let w = Tup(0.6, 0., 0.8);
+        let (t, eta) = refract(w, Tup(0., 0., 1.), 1.5).unwrap();
+        assert_eq!(eta, 1.5);
+        assert!((t.0 * -1.5 - 0.6).abs() < 1e-12 && t.2 < 0.);
+        // And back out again.
+        let (back, _) = refract(t, Tup(0., 0., 1.), 1.5).unwrap();
+        assert!((back - w).dot(back - w) < 1e-20);
+        assert!(refract(Tup(0.9, 0., -(0.19f64).sqrt()), Tup(0., 0., 1.), 1.5).is_none());
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        // (n - 1)^2 / (n + 1)^2 at normal incidence.
+        assert!((fresnel_dielectric(1., 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(-1., 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(-0.2, 1.5), 1.);
+        assert!((fresnel_dielectric(1e-9, 1.5) - 1.).abs() < 1e-6);
+        // A conductor without absorption is a dielectric.
+        let f = fresnel_conductor(0.7, Tup(1.5, 1.5, 1.5), Tup::zeros());
+        assert!((f.0 - fresnel_dielectric(0.7, 1.5)).abs() < 1e-12);
+        let f = fresnel_conductor(1., Tup(0.2, 0.2, 0.2), Tup(3., 3., 3.));
+        let expected = (0.8 * 0.8 + 9.) / (1.2 * 1.2 + 9.);
+        assert!((f.0 - expected).abs() < 1e-12);
+    }
+}
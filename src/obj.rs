@@ -0,0 +1,699 @@
+//! Wavefront OBJ and MTL import.
+//!
+//! Faces become triangle meshes, one per group or object and material.
+//! Statements the renderer has no use for, such as smoothing groups, lines
+//! or texture maps, are skipped.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::material::{Diffuse, Glass, Material, Mirror};
+use super::mesh::Mesh;
+use super::tup::Tup;
+use super::world::Object;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}, line {}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// An MTL material.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjMaterial {
+    /// `Kd`
+    pub diffuse: Tup,
+    /// `Ks`
+    pub specular: Tup,
+    /// `Ke`
+    pub emission: Tup,
+    /// `Ni`
+    pub ior: f64,
+    pub illum: u32,
+}
+
+impl Default for ObjMaterial {
+    fn default() -> Self {
+        ObjMaterial {
+            diffuse: Tup(0.75, 0.75, 0.75),
+            specular: Tup::zeros(),
+            emission: Tup::zeros(),
+            ior: 1.,
+            illum: 2,
+        }
+    }
+}
+
+impl ObjMaterial {
+    /// The closest of the renderer's materials, with its color. Illumination
+    /// models with ray traced refraction (4, 6, 7 and 9) become glass and
+    /// those with ray traced reflection (3, 5 and 8) mirrors, both tinted by
+    /// `Ks`; everything else is diffuse with `Kd`. Glass takes its index of
+    /// refraction from `Ni`, except that the default of 1, which exporters
+    /// often leave in place, stands for the usual 1.5.
+    pub fn surface(&self) -> (Tup, Box<dyn Material>) {
+        match self.illum {
+            4 | 6 | 7 | 9 => {
+                let color = if self.specular == Tup::zeros() {
+                    Tup::ones() * 0.999
+                } else {
+                    self.specular
+                };
+                let glass = if self.ior > 1. {
+                    Glass::new(self.ior)
+                } else {
+                    Glass::default()
+                };
+                (color, glass.into())
+            }
+            3 | 5 | 8 => (self.specular, Mirror.into()),
+            _ => (self.diffuse, Diffuse.into()),
+        }
+    }
+}
+
+/// A mesh of an OBJ file, named after its group or object.
+#[derive(Debug)]
+pub struct Part {
+    pub name: String,
+    pub mesh: Mesh,
+    pub material: ObjMaterial,
+}
+
+impl Part {
+    pub fn into_object(self) -> Object {
+        let (color, material) = self.material.surface();
+        Object::new(self.mesh, self.material.emission, color, material)
+    }
+}
+
+/// Loads an OBJ file along with the MTL files it refers to.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Part>, ObjError> {
+    let path = path.as_ref();
+    let mut read = |path: &Path| fs::read_to_string(path);
+    let source = read(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    parse(&source, path, &mut read)
+}
+
+/// Parses OBJ `source`. `path` names the file in errors and locates its MTL
+/// files, which are fetched with `read`.
+pub fn parse(
+    source: &str,
+    path: &Path,
+    read: &mut dyn FnMut(&Path) -> io::Result<String>,
+) -> Result<Vec<Part>, ObjError> {
+    let mut parser = Parser::default();
+    for (i, line) in source.lines().enumerate() {
+        let error = |message| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: i + 1,
+            message,
+        };
+        let mut words = statement(line);
+        if words.next() == Some("mtllib") {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            for file in words {
+                let mtl = dir.join(file);
+                let source = read(&mtl)
+                    .map_err(|e| error(format!("cannot read '{}': {}", mtl.display(), e)))?;
+                parser.materials.extend(parse_mtl(&source, &mtl)?);
+            }
+            continue;
+        }
+        parser.statement(line).map_err(error)?;
+    }
+    parser.finish().map_err(|message| ObjError::Parse {
+        path: path.to_path_buf(),
+        line: source.lines().count(),
+        message,
+    })
+}
+
+/// Parses MTL `source` into named materials.
+pub fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, ObjMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, ObjMaterial)> = None;
+    for (i, line) in source.lines().enumerate() {
+        mtl_statement(line, &mut current, &mut materials).map_err(|message| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: i + 1,
+            message,
+        })?;
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+fn mtl_statement(
+    line: &str,
+    current: &mut Option<(String, ObjMaterial)>,
+    materials: &mut HashMap<String, ObjMaterial>,
+) -> Result<(), String> {
+    let mut words = statement(line);
+    let Some(keyword) = words.next() else {
+        return Ok(());
+    };
+    if keyword == "newmtl" {
+        let name = name(words, "newmtl")?;
+        if let Some((name, material)) = current.replace((name, ObjMaterial::default())) {
+            materials.insert(name, material);
+        }
+        return Ok(());
+    }
+    let field = match keyword {
+        "Kd" | "Ks" | "Ke" | "Ni" | "illum" => keyword,
+        _ => return Ok(()),
+    };
+    let Some((_, material)) = current else {
+        return Err(format!("'{}' before any 'newmtl'", field));
+    };
+    match field {
+        "Kd" => material.diffuse = color(words)?,
+        "Ks" => material.specular = color(words)?,
+        "Ke" => material.emission = color(words)?,
+        "Ni" => {
+            let [ior] = numbers(words, "Ni")?;
+            if ior <= 0. {
+                return Err(format!("'Ni' must be positive, got {}", ior));
+            }
+            material.ior = ior;
+        }
+        _ => {
+            let [illum] = numbers(words, "illum")?;
+            if illum < 0. || illum.fract() != 0. {
+                return Err(format!("'illum' must be a whole number, got {}", illum));
+            }
+            material.illum = illum as u32;
+        }
+    }
+    Ok(())
+}
+
+/// The words of a line, without its comment.
+fn statement(line: &str) -> std::str::SplitWhitespace<'_> {
+    line.split('#').next().unwrap_or("").split_whitespace()
+}
+
+fn name<'a>(words: impl Iterator<Item = &'a str>, keyword: &str) -> Result<String, String> {
+    let name = words.collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Err(format!("'{}' needs a name", keyword));
+    }
+    Ok(name)
+}
+
+fn number(word: &str) -> Result<f64, String> {
+    match word.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(format!("expected a number, got '{}'", word)),
+    }
+}
+
+/// Reads `N` numbers, ignoring any that follow.
+fn numbers<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+    keyword: &str,
+) -> Result<[f64; N], String> {
+    let mut values = [0.; N];
+    for value in &mut values {
+        let word = words.next().ok_or_else(|| match N {
+            1 => format!("'{}' needs a number", keyword),
+            _ => format!("'{}' needs {} numbers", keyword, N),
+        })?;
+        *value = number(word)?;
+    }
+    Ok(values)
+}
+
+/// An RGB color; a single value stands for a grey.
+fn color<'a>(words: impl Iterator<Item = &'a str>) -> Result<Tup, String> {
+    let values = words.map(number).collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [v] => Ok(Tup(v, v, v)),
+        [r, g, b, ..] => Ok(Tup(r, g, b)),
+        _ => Err("expected a color".to_string()),
+    }
+}
+
+/// Vertex indices of a face corner: position, then optional uv and normal.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct Parser {
+    positions: Vec<Tup>,
+    uvs: Vec<(f64, f64)>,
+    normals: Vec<Tup>,
+    materials: HashMap<String, ObjMaterial>,
+    object: Option<String>,
+    group: Option<String>,
+    material: Option<String>,
+    builders: Vec<Builder>,
+    /// Index into `builders` by name and material.
+    by_key: HashMap<(String, Option<String>), usize>,
+}
+
+/// Faces of one part, reindexed so that each distinct corner is a vertex.
+struct Builder {
+    name: String,
+    material: Option<String>,
+    corners: HashMap<Corner, usize>,
+    vertices: Vec<Corner>,
+    triangles: Vec<[usize; 3]>,
+}
+
+impl Parser {
+    fn statement(&mut self, line: &str) -> Result<(), String> {
+        let mut words = statement(line);
+        let Some(keyword) = words.next() else {
+            return Ok(());
+        };
+        match keyword {
+            "v" => {
+                let [x, y, z] = numbers(words, "v")?;
+                self.positions.push(Tup(x, y, z));
+            }
+            "vt" => {
+                let u = number(words.next().ok_or("'vt' needs a number")?)?;
+                let v = words.next().map(number).transpose()?.unwrap_or(0.);
+                self.uvs.push((u, v));
+            }
+            "vn" => {
+                let [x, y, z] = numbers(words, "vn")?;
+                self.normals.push(Tup(x, y, z));
+            }
+            "f" => self.face(words)?,
+            "o" => {
+                self.object = Some(name(words, "o")?);
+                self.group = None;
+            }
+            "g" => {
+                // A bare `g` returns to the default group.
+                let name = words.collect::<Vec<_>>().join(" ");
+                self.group = (!name.is_empty()).then_some(name);
+            }
+            "usemtl" => {
+                let material = name(words, "usemtl")?;
+                if !self.materials.contains_key(&material) {
+                    return Err(format!("unknown material '{}'", material));
+                }
+                self.material = Some(material);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn face<'a>(&mut self, words: impl Iterator<Item = &'a str>) -> Result<(), String> {
+        let corners = words
+            .map(|word| self.corner(word))
+            .collect::<Result<Vec<_>, _>>()?;
+        if corners.len() < 3 {
+            return Err(format!(
+                "a face needs at least 3 vertices, got {}",
+                corners.len()
+            ));
+        }
+        let points: Vec<_> = corners.iter().map(|c| self.positions[c.0]).collect();
+        let triangles = triangulate(&points);
+
+        let name = self
+            .group
+            .clone()
+            .or_else(|| self.object.clone())
+            .unwrap_or_default();
+        let key = (name, self.material.clone());
+        let index = *self.by_key.entry(key.clone()).or_insert_with(|| {
+            self.builders.push(Builder {
+                name: key.0,
+                material: key.1,
+                corners: HashMap::new(),
+                vertices: Vec::new(),
+                triangles: Vec::new(),
+            });
+            self.builders.len() - 1
+        });
+        let builder = &mut self.builders[index];
+        let vertices: Vec<_> = corners
+            .iter()
+            .map(|&corner| {
+                *builder.corners.entry(corner).or_insert_with(|| {
+                    builder.vertices.push(corner);
+                    builder.vertices.len() - 1
+                })
+            })
+            .collect();
+        builder
+            .triangles
+            .extend(triangles.iter().map(|t| t.map(|i| vertices[i])));
+        Ok(())
+    }
+
+    /// Parses a face corner such as `3`, `3/1`, `3//2` or `3/1/2`.
+    fn corner(&self, word: &str) -> Result<Corner, String> {
+        let mut parts = word.split('/');
+        let position = parts.next().unwrap_or("");
+        let uv = parts.next().filter(|s| !s.is_empty());
+        let normal = parts.next().filter(|s| !s.is_empty());
+        if parts.next().is_some() {
+            return Err(format!("malformed face vertex '{}'", word));
+        }
+        Ok((
+            index(position, self.positions.len(), "vertex")?,
+            uv.map(|s| index(s, self.uvs.len(), "texture coordinate"))
+                .transpose()?,
+            normal
+                .map(|s| index(s, self.normals.len(), "normal"))
+                .transpose()?,
+        ))
+    }
+
+    fn finish(self) -> Result<Vec<Part>, String> {
+        if self.builders.is_empty() {
+            return Err("the file has no faces".to_string());
+        }
+        self.builders
+            .into_iter()
+            .map(|b| {
+                let positions = b.vertices.iter().map(|c| self.positions[c.0]).collect();
+                let mut mesh = Mesh::new(positions, b.triangles)?;
+                // Meshes take normals and uvs for all vertices or none.
+                if let Some(normals) = b.vertices.iter().map(|c| c.2).collect::<Option<Vec<_>>>() {
+                    let normals: Vec<_> = normals.into_iter().map(|n| self.normals[n]).collect();
+                    if normals.iter().all(|n| n.dot(*n) > 0.) {
+                        mesh = mesh.with_normals(normals)?;
+                    }
+                }
+                if let Some(uvs) = b.vertices.iter().map(|c| c.1).collect::<Option<Vec<_>>>() {
+                    mesh = mesh.with_uvs(uvs.into_iter().map(|t| self.uvs[t]).collect())?;
+                }
+                let material = match &b.material {
+                    Some(name) => self.materials[name].clone(),
+                    None => ObjMaterial::default(),
+                };
+                Ok(Part {
+                    name: b.name,
+                    mesh,
+                    material,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Resolves a one-based OBJ index, or a negative one counting back from the
+/// last element defined so far.
+fn index(word: &str, count: usize, what: &str) -> Result<usize, String> {
+    let i: i64 = word
+        .parse()
+        .map_err(|_| format!("expected a {} index, got '{}'", what, word))?;
+    let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+    if i == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} is out of range, {} defined so far",
+            what, i, count
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+/// Splits a planar polygon into triangles by ear clipping, keeping its
+/// winding. Falls back to a fan when the polygon is too degenerate to clip.
+fn triangulate(points: &[Tup]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    let fan = || (1..n - 1).map(|i| [0, i, i + 1]).collect();
+    if n == 3 {
+        return fan();
+    }
+    // Newell's normal, then drop its largest axis to work in 2D. Keeping the
+    // other two in cyclic order makes counterclockwise polygons positive.
+    let normal = (0..n).fold(Tup::zeros(), |acc, i| {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        acc + Tup(
+            (a.1 - b.1) * (a.2 + b.2),
+            (a.2 - b.2) * (a.0 + b.0),
+            (a.0 - b.0) * (a.1 + b.1),
+        )
+    });
+    let (ax, ay, az) = (normal.0.abs(), normal.1.abs(), normal.2.abs());
+    let (flat, sign): (Vec<(f64, f64)>, f64) = if az >= ax && az >= ay {
+        (points.iter().map(|p| (p.0, p.1)).collect(), normal.2)
+    } else if ax >= ay {
+        (points.iter().map(|p| (p.1, p.2)).collect(), normal.0)
+    } else {
+        (points.iter().map(|p| (p.2, p.0)).collect(), normal.1)
+    };
+    let sign = if sign < 0. { -1. } else { 1. };
+    let cross = |o: usize, a: usize, b: usize| {
+        let (o, a, b) = (flat[o], flat[a], flat[b]);
+        sign * ((a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0))
+    };
+
+    let mut polygon: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while polygon.len() > 3 {
+        let m = polygon.len();
+        let ear = (0..m).find(|&i| {
+            let (a, b, c) = (polygon[(i + m - 1) % m], polygon[i], polygon[(i + 1) % m]);
+            cross(a, b, c) > 0.
+                && polygon.iter().all(|&p| {
+                    p == a
+                        || p == b
+                        || p == c
+                        || cross(a, b, p) <= 0.
+                        || cross(b, c, p) <= 0.
+                        || cross(c, a, p) <= 0.
+                })
+        });
+        let Some(i) = ear else {
+            return fan();
+        };
+        triangles.push([polygon[(i + m - 1) % m], polygon[i], polygon[(i + 1) % m]]);
+        polygon.remove(i);
+    }
+    triangles.push([polygon[0], polygon[1], polygon[2]]);
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::world::World;
+
+    fn parse_with(source: &str, mtl: &str) -> Result<Vec<Part>, ObjError> {
+        let mtl = mtl.to_string();
+        parse(source, Path::new("dir/model.obj"), &mut |path: &Path| {
+            if path == Path::new("dir/model.mtl") {
+                Ok(mtl.clone())
+            } else {
+                Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+            }
+        })
+    }
+
+    fn error(source: &str) -> String {
+        parse_with(source, "").unwrap_err().to_string()
+    }
+
+    const MTL: &str = "\
+newmtl red
+Kd 0.8 0.1 0.1
+newmtl lamp # a light
+Kd 0 0 0
+Ke 4 4 4
+newmtl glass
+Ks 1 1 1
+Ni 1.5
+illum 7
+newmtl mirror
+Ks 0.9
+illum 3
+";
+
+    #[test]
+    fn loads_faces_groups_and_materials() {
+        let parts = parse_with(
+            "mtllib model.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+o box
+usemtl red
+f 1/1/1 2/2/1 3/3/1 4/4/1
+v 0 0 -1
+v 1 0 -1
+v 1 1 -1
+g lid
+usemtl lamp
+f -3 -2 -1
+usemtl glass
+f 5//1 6//1 7//1
+g
+usemtl mirror
+f 5 6 7
+",
+            MTL,
+        )
+        .unwrap();
+        let names: Vec<_> = parts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["box", "lid", "lid", "box"]);
+
+        assert_eq!(parts[2].material.ior, 1.5);
+        let world = World::from_objects(parts.into_iter().map(Part::into_object).collect());
+        let red = &world.objects[0];
+        assert_eq!(red.color.value((0., 0.), Tup::zeros()), Tup(0.8, 0.1, 0.1));
+        assert_eq!(format!("{:?}", red.material), "Diffuse");
+        assert_eq!(
+            world.objects[1].emission.value((0., 0.), Tup::zeros()),
+            Tup(4., 4., 4.)
+        );
+        assert_eq!(
+            format!("{:?}", world.objects[2].material),
+            format!("{:?}", Glass::new(1.5))
+        );
+        assert_eq!(format!("{:?}", world.objects[3].material), "Mirror");
+        assert_eq!(
+            world.objects[3].color.value((0., 0.), Tup::zeros()),
+            Tup(0.9, 0.9, 0.9)
+        );
+
+        let ray = Ray {
+            o: Tup(0.25, 0.75, 1.),
+            d: Tup(0., 0., -1.),
+        };
+        let hit = world.intersect(&ray).unwrap();
+        assert_eq!(hit.object, 0);
+        assert!((hit.uv.0 - 0.25).abs() < 1e-12 && (hit.uv.1 - 0.75).abs() < 1e-12);
+        assert_eq!(hit.shading_normal, Tup(0., 0., 1.));
+    }
+
+    #[test]
+    fn faces_without_normals_are_flat() {
+        let parts = parse_with("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2 3\n", "").unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].name, "");
+        let ray = Ray {
+            o: Tup(0.2, 0.2, -1.),
+            d: Tup(0., 0., 1.),
+        };
+        let world = World::from_objects(parts.into_iter().map(Part::into_object).collect());
+        assert_eq!(world.intersect(&ray).unwrap().uv, (0.2, 0.2));
+        assert_eq!(
+            world.objects[0].color.value((0., 0.), Tup::zeros()),
+            Tup(0.75, 0.75, 0.75)
+        );
+    }
+
+    #[test]
+    fn triangulates_concave_polygons() {
+        // An L shape whose fan from the first vertex would leave the polygon.
+        let points = [
+            Tup(1., 1., 0.),
+            Tup(0., 1., 0.),
+            Tup(0., 0., 0.),
+            Tup(2., 0., 0.),
+            Tup(2., 2., 0.),
+            Tup(1., 2., 0.),
+        ];
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), 4);
+        let area: f64 = triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| points[i]);
+                (b - a).cross(c - a).2 / 2.
+            })
+            .sum();
+        assert!((area - 3.).abs() < 1e-12, "{:?}", triangles);
+        // Counterclockwise when seen from +z, like the polygon.
+        for t in &triangles {
+            let [a, b, c] = t.map(|i| points[i]);
+            assert!((b - a).cross(c - a).2 > 0., "{:?}", triangles);
+        }
+    }
+
+    #[test]
+    fn reports_malformed_files() {
+        for (source, expected) in [
+            ("v 0 0\n", "line 1: 'v' needs 3 numbers"),
+            ("v 0 x 0\n", "line 1: expected a number, got 'x'"),
+            (
+                "v 0 0 0\nv 1 0 0\nf 1 2\n",
+                "line 3: a face needs at least 3 vertices, got 2",
+            ),
+            (
+                "v 0 0 0\nf 1 2 3\n",
+                "line 2: vertex index 2 is out of range, 1 defined so far",
+            ),
+            ("v 0 0 0\nf 1 0 1\n", "vertex index 0 is out of range"),
+            (
+                "v 0 0 0\nf 1/1 1 1\n",
+                "texture coordinate index 1 is out of range",
+            ),
+            (
+                "v 0 0 0\nf 1/a 1 1\n",
+                "expected a texture coordinate index, got 'a'",
+            ),
+            (
+                "v 0 0 0\nf 1/1/1/1 1 1\n",
+                "malformed face vertex '1/1/1/1'",
+            ),
+            ("usemtl red\n", "line 1: unknown material 'red'"),
+            ("mtllib other.mtl\n", "cannot read 'dir/other.mtl'"),
+            ("v 0 0 0\n", "line 1: the file has no faces"),
+        ] {
+            let err = error(source);
+            assert!(err.starts_with("dir/model.obj, line "), "{}", err);
+            assert!(
+                err.contains(expected),
+                "{} does not contain {}",
+                err,
+                expected
+            );
+        }
+        let err = parse_with("mtllib model.mtl\n", "Kd 1 1 1\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "dir/model.mtl, line 1: 'Kd' before any 'newmtl'");
+        let err = parse_with("mtllib model.mtl\n", "newmtl a\nillum 2.5\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'illum' must be a whole number"), "{}", err);
+    }
+}
@@ -0,0 +1,492 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use ::exr::prelude as exr;
+use ::exr::prelude::WritableImage;
+
+use super::film::Film;
+use super::image::Image;
+use super::tonemap::{srgb_encode, ToneMapping};
+
+/// Image file formats, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Binary (P6) PPM.
+    Ppm,
+    Png,
+    /// Portable float map; keeps the unclamped radiance.
+    Pfm,
+    /// OpenEXR with the render's extra layers next to the radiance.
+    Exr,
+}
+
+impl Format {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, String> {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some(ext) => match ext.to_ascii_lowercase().as_str() {
+                "ppm" => Ok(Format::Ppm),
+                "png" => Ok(Format::Png),
+                "pfm" => Ok(Format::Pfm),
+                "exr" => Ok(Format::Exr),
+                _ => Err(format!(
+                    "unsupported output format '.{}' (expected '.ppm', '.png', '.pfm' or '.exr')",
+                    ext
+                )),
+            },
+            None => {
+                Err("output path needs a '.ppm', '.png', '.pfm' or '.exr' extension".to_string())
+            }
+        }
+    }
+}
+
+/// Bits per channel of the integer formats.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    fn max(self) -> f64 {
+        match self {
+            BitDepth::Eight => 255.,
+            BitDepth::Sixteen => 65535.,
+        }
+    }
+}
+
+impl FromStr for BitDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" => Ok(BitDepth::Eight),
+            "16" => Ok(BitDepth::Sixteen),
+            _ => Err(format!("unsupported bit depth '{}' (expected 8 or 16)", s)),
+        }
+    }
+}
+
+impl fmt::Display for BitDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BitDepth::Eight => "8",
+            BitDepth::Sixteen => "16",
+        })
+    }
+}
+
+fn clamp(x: f64) -> f64 {
+    if x < 0. {
+        return 0.;
+    } else if x > 1. {
+        return 1.;
+    }
+    x
+}
+
+/// Channel values encoded with the sRGB transfer function, clamped and
+/// quantized to `depth` as big-endian bytes, which is what both PPM and PNG
+/// expect. This is the only place where values are clamped.
+fn quantize(image: &Image, depth: BitDepth) -> Vec<u8> {
+    let max = depth.max();
+    let mut data = Vec::with_capacity(image.pixels.len() * 3 * 2);
+    for p in &image.pixels {
+        for c in [p.0, p.1, p.2] {
+            let v = (clamp(srgb_encode(c)) * max + 0.5) as u16;
+            match depth {
+                BitDepth::Eight => data.push(v as u8),
+                BitDepth::Sixteen => data.extend_from_slice(&v.to_be_bytes()),
+            }
+        }
+    }
+    data
+}
+
+pub fn write_ppm(image: &Image, mut out: impl Write, depth: BitDepth) -> io::Result<()> {
+    write!(
+        out,
+        "P6\n{} {}\n{}\n",
+        image.width,
+        image.height,
+        depth.max()
+    )?;
+    out.write_all(&quantize(image, depth))
+}
+
+pub fn write_png(image: &Image, out: impl Write, depth: BitDepth) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(match depth {
+        BitDepth::Eight => png::BitDepth::Eight,
+        BitDepth::Sixteen => png::BitDepth::Sixteen,
+    });
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(&quantize(image, depth))
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+/// Writes linear radiance as 32-bit floats. PFM stores rows bottom to top; a
+/// negative scale marks little-endian data.
+pub fn write_pfm(image: &Image, mut out: impl Write) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+    let mut data = Vec::with_capacity(image.pixels.len() * 3 * 4);
+    for row in image.pixels.chunks(image.width.max(1)).rev() {
+        for p in row {
+            for c in [p.0, p.1, p.2] {
+                data.extend_from_slice(&(c as f32).to_le_bytes());
+            }
+        }
+    }
+    out.write_all(&data)
+}
+
+/// Sample type of the colour layers of an EXR file. Depth is always stored as
+/// 32-bit floats and object ids as 32-bit integers.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Precision {
+    Half,
+    #[default]
+    Float,
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "half" => Ok(Precision::Half),
+            "float" => Ok(Precision::Float),
+            _ => Err(format!(
+                "unknown precision '{}' (expected 'half' or 'float')",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Precision::Half => "half",
+            Precision::Float => "float",
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    /// Zlib over blocks of 16 scanlines.
+    #[default]
+    Zip,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "zip" => Ok(Compression::Zip),
+            _ => Err(format!(
+                "unknown compression '{}' (expected 'none' or 'zip')",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "none",
+            Compression::Zip => "zip",
+        })
+    }
+}
+
+/// Object id stored for pixels whose samples hit nothing.
+pub const NO_OBJECT: u32 = u32::MAX;
+
+fn colour_channels(
+    channels: &mut Vec<exr::AnyChannel<exr::FlatSamples>>,
+    layer: &str,
+    names: [&str; 3],
+    image: &Image,
+    precision: Precision,
+) {
+    let components: [fn(&crate::tup::Tup) -> f64; 3] = [|p| p.0, |p| p.1, |p| p.2];
+    for (name, component) in names.into_iter().zip(components) {
+        let values = image.pixels.iter().map(|p| component(p) as f32);
+        let samples = match precision {
+            Precision::Half => exr::FlatSamples::F16(values.map(exr::f16::from_f32).collect()),
+            Precision::Float => exr::FlatSamples::F32(values.collect()),
+        };
+        let name = if layer.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", layer, name)
+        };
+        channels.push(exr::AnyChannel::new(name.as_str(), samples));
+    }
+}
+
+/// Writes a scanline OpenEXR file with the radiance in `R`, `G` and `B` and
+/// the layers `albedo`, `normal` (`X`, `Y`, `Z`), `variance`, `Z` (depth along
+/// the camera ray), `id` (index of the object, [`NO_OBJECT`] for none) and
+/// `spp` (samples taken for the pixel).
+pub fn write_exr(
+    film: &Film,
+    out: impl Write + Seek,
+    precision: Precision,
+    compression: Compression,
+) -> io::Result<()> {
+    let mut channels = Vec::new();
+    colour_channels(&mut channels, "", ["R", "G", "B"], &film.image(), precision);
+    colour_channels(
+        &mut channels,
+        "albedo",
+        ["R", "G", "B"],
+        &film.albedo(),
+        precision,
+    );
+    colour_channels(
+        &mut channels,
+        "normal",
+        ["X", "Y", "Z"],
+        &film.normal(),
+        precision,
+    );
+    colour_channels(
+        &mut channels,
+        "variance",
+        ["R", "G", "B"],
+        &film.variance(),
+        precision,
+    );
+    channels.push(exr::AnyChannel::new(
+        "Z",
+        exr::FlatSamples::F32(film.pixels.iter().map(|p| p.depth as f32).collect()),
+    ));
+    channels.push(exr::AnyChannel::new(
+        "id",
+        exr::FlatSamples::U32(
+            film.pixels
+                .iter()
+                .map(|p| p.object.map_or(NO_OBJECT, |o| o as u32))
+                .collect(),
+        ),
+    ));
+    channels.push(exr::AnyChannel::new(
+        "spp",
+        exr::FlatSamples::U32(film.pixels.iter().map(|p| p.samples).collect()),
+    ));
+
+    let encoding = exr::Encoding {
+        compression: match compression {
+            Compression::None => exr::Compression::Uncompressed,
+            Compression::Zip => exr::Compression::ZIP16,
+        },
+        blocks: exr::Blocks::ScanLines,
+        line_order: exr::LineOrder::Increasing,
+    };
+    let layer = exr::Layer::new(
+        (film.width, film.height),
+        exr::LayerAttributes::default(),
+        encoding,
+        exr::AnyChannels::sort(exr::SmallVec::from_vec(channels)),
+    );
+    exr::Image::from_layer(layer)
+        .write()
+        .to_buffered(out)
+        .map_err(io::Error::other)
+}
+
+/// Writes `image` in the format given by the extension of `path`. PPM and PNG
+/// files get the tone mapping and bit depth of `options`; PFM files keep the
+/// radiance as it is. EXR files need the extra layers of a film; write those
+/// with [`save_film`].
+pub fn save(image: &Image, path: impl AsRef<Path>, options: OutputOptions) -> io::Result<()> {
+    let format =
+        Format::from_path(&path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        Format::Ppm => write_ppm(
+            &options.tone_mapping.apply(image),
+            &mut out,
+            options.bit_depth,
+        )?,
+        Format::Png => write_png(
+            &options.tone_mapping.apply(image),
+            &mut out,
+            options.bit_depth,
+        )?,
+        Format::Pfm => write_pfm(image, &mut out)?,
+        Format::Exr => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "EXR output is written from a film",
+            ))
+        }
+    }
+    out.flush()
+}
+
+/// Output settings that only apply to some formats.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OutputOptions {
+    pub bit_depth: BitDepth,
+    pub precision: Precision,
+    pub compression: Compression,
+    pub tone_mapping: ToneMapping,
+}
+
+/// Writes the film in the format given by the extension of `path`: EXR files
+/// get all of its layers, the other formats its image.
+pub fn save_film(film: &Film, path: impl AsRef<Path>, options: OutputOptions) -> io::Result<()> {
+    if Format::from_path(&path) != Ok(Format::Exr) {
+        return save(&film.image(), path, options);
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    write_exr(film, &mut out, options.precision, options.compression)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tup::Tup;
+
+    fn test_image() -> Image {
+        let mut image = Image::new(2, 2);
+        image.pixels[1] = Tup(1., 0.5, 2.);
+        image.pixels[2] = Tup(-1., 0.25, 10.);
+        image
+    }
+
+    #[test]
+    fn formats_from_extension() {
+        assert_eq!(Format::from_path("a.ppm"), Ok(Format::Ppm));
+        assert_eq!(Format::from_path("dir/a.PNG"), Ok(Format::Png));
+        assert_eq!(Format::from_path("a.pfm"), Ok(Format::Pfm));
+        assert_eq!(Format::from_path("a.exr"), Ok(Format::Exr));
+        assert!(Format::from_path("a.jpg").is_err());
+        assert!(Format::from_path("a").is_err());
+    }
+
+    #[test]
+    fn writes_binary_ppm() {
+        let mut out = vec![];
+        write_ppm(&test_image(), &mut out, BitDepth::Eight).unwrap();
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 255, 188, 255, 0, 137, 255, 0, 0, 0]);
+        assert_eq!(out, expected);
+
+        let mut out = vec![];
+        write_ppm(&test_image(), &mut out, BitDepth::Sixteen).unwrap();
+        assert!(out.starts_with(b"P6\n2 2\n65535\n"));
+        assert_eq!(out.len(), 13 + 2 * 2 * 3 * 2);
+        assert_eq!(&out[13 + 6..13 + 12], &[255, 255, 0xbc, 0x40, 255, 255]);
+    }
+
+    #[test]
+    fn writes_png() {
+        for depth in [BitDepth::Eight, BitDepth::Sixteen] {
+            let mut out = vec![];
+            write_png(&test_image(), &mut out, depth).unwrap();
+            let decoder = png::Decoder::new(out.as_slice());
+            let mut reader = decoder.read_info().unwrap();
+            let mut data = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut data).unwrap();
+            assert_eq!((info.width, info.height), (2, 2));
+            assert_eq!(info.color_type, png::ColorType::Rgb);
+            assert_eq!(&data[..info.buffer_size()], quantize(&test_image(), depth));
+        }
+    }
+
+    #[test]
+    fn pfm_keeps_unclamped_radiance() {
+        let mut out = vec![];
+        write_pfm(&test_image(), &mut out).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert!(out.starts_with(header));
+        let values: Vec<f32> = out[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        // The bottom row comes first.
+        assert_eq!(
+            values,
+            vec![-1., 0.25, 10., 0., 0., 0., 0., 0., 0., 1., 0.5, 2.]
+        );
+    }
+
+    #[test]
+    fn writes_exr_layers() {
+        use crate::film::CameraSample;
+        use crate::integrator::FirstHit;
+        use ::exr::prelude::traits::*;
+
+        let mut film = Film::new(3, 2);
+        let hit = FirstHit {
+            object: 4,
+            depth: 12.5,
+            normal: Tup(0., 1., 0.),
+            albedo: Tup(0.25, 0.5, 0.75),
+        };
+        for (l, first_hit) in [(1., Some(hit)), (3., Some(hit))] {
+            let s = CameraSample {
+                radiance: Tup(l, 2. * l, 100.),
+                first_hit,
+            };
+            film.add(1, 0, &s, 1.);
+            film.record(1, 0, &s, 0.);
+        }
+
+        for precision in [Precision::Half, Precision::Float] {
+            for compression in [Compression::None, Compression::Zip] {
+                let mut out = io::Cursor::new(Vec::new());
+                write_exr(&film, &mut out, precision, compression).unwrap();
+                out.set_position(0);
+                let image = exr::read()
+                    .no_deep_data()
+                    .largest_resolution_level()
+                    .all_channels()
+                    .first_valid_layer()
+                    .all_attributes()
+                    .from_buffered(out)
+                    .unwrap();
+                let channels = &image.layer_data.channel_data.list;
+                let value = |name: &str, index: usize| {
+                    let c = channels
+                        .iter()
+                        .find(|c| c.name == *name)
+                        .unwrap_or_else(|| panic!("missing channel {}", name));
+                    c.sample_data.value_by_flat_index(index).to_f32()
+                };
+                assert_eq!(channels.len(), 15);
+                assert_eq!(value("R", 1), 2.);
+                assert_eq!(value("B", 1), 100.);
+                assert_eq!(value("R", 0), 0.);
+                assert_eq!(value("albedo.G", 1), 0.5);
+                assert_eq!(value("normal.Y", 1), 1.);
+                // Two samples with variance 2 give a mean with variance 1.
+                assert_eq!(value("variance.R", 1), 1.);
+                assert_eq!(value("Z", 1), 12.5);
+                assert_eq!(value("Z", 0), f32::INFINITY);
+                assert_eq!(value("id", 1), 4.);
+                assert_eq!(value("id", 0), NO_OBJECT as f32);
+                assert_eq!(value("spp", 1), 2.);
+                assert_eq!(value("spp", 0), 0.);
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::bvh::Aabb;
+use super::ray::Ray;
+use super::shape::{Hit, Intersection, Shape, EPS};
+use super::tup::Tup;
+
+/// An infinite plane through `point`, facing along `normal`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plane {
+    pub point: Tup,
+    pub normal: Tup,
+}
+
+impl Plane {
+    pub fn new(point: Tup, normal: Tup) -> Result<Self, String> {
+        Ok(Plane {
+            point,
+            normal: unit_normal(normal)?,
+        })
+    }
+}
+
+/// Normalizes a normal given in a scene, which must not be zero.
+pub(crate) fn unit_normal(normal: Tup) -> Result<Tup, String> {
+    if normal.dot(normal) > 0. {
+        Ok(normal.norm())
+    } else {
+        Err("normal must not be zero".to_string())
+    }
+}
+
+/// Distance along `ray` to the plane through `point` with unit `normal`.
+pub(crate) fn plane_distance(ray: &Ray, point: Tup, normal: Tup, t_max: f64) -> Option<f64> {
+    let t = normal.dot(point - ray.o) / normal.dot(ray.d);
+    (t > EPS && t < t_max).then_some(t)
+}
+
+impl Shape for Plane {
+    fn bounds(&self) -> Aabb {
+        Aabb::infinite()
+    }
+
+    fn intersect(&self, ray: &Ray, t_max: f64) -> Option<Intersection> {
+        plane_distance(ray, self.point, self.normal, t_max).map(|t| Intersection {
+            t,
+            primitive: 0,
+            b: (0., 0.),
+        })
+    }
+
+    /// The uv coordinates are distances from `point` along two directions in
+    /// the plane.
+    fn hit(&self, ray: &Ray, i: &Intersection) -> Hit {
+        let point = ray.o + ray.d * i.t;
+        let (u, v) = self.normal.basis();
+        let offset = point - self.point;
+        Hit {
+            t: i.t,
+            point,
+            normal: self.normal,
+            shading_normal: self.normal,
+            uv: (offset.dot(u), offset.dot(v)),
+            object: 0,
+            primitive: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_hits_plane_from_either_side() {
+        let plane = Plane::new(Tup(0., 2., 0.), Tup(0., 3., 0.)).unwrap();
+        assert_eq!(plane.normal, Tup(0., 1., 0.));
+        for (o, d, t) in [
+            (Tup(1., 5., 1.), Tup(0., -1., 0.), 3.),
+            (Tup(1., 0., 1.), Tup(0., 0.6, 0.8), 2. / 0.6),
+        ] {
+            let ray = Ray { o, d };
+            let i = plane.intersect(&ray, f64::INFINITY).unwrap();
+            assert!((i.t - t).abs() < 1e-12);
+            let hit = plane.hit(&ray, &i);
+            assert!((hit.point.1 - 2.).abs() < 1e-12);
+            assert_eq!(hit.normal, Tup(0., 1., 0.));
+        }
+    }
+
+    #[test]
+    fn ray_misses_plane() {
+        let plane = Plane::new(Tup::zeros(), Tup(0., 0., 1.)).unwrap();
+        for d in [Tup(0., 0., 1.), Tup(1., 0., 0.)] {
+            let ray = Ray {
+                o: Tup(0., 0., 1.),
+                d,
+            };
+            assert!(plane.intersect(&ray, f64::INFINITY).is_none());
+        }
+        let ray = Ray {
+            o: Tup(0., 0., 1.),
+            d: Tup(0., 0., -1.),
+        };
+        assert!(plane.intersect(&ray, 1.).is_none());
+        assert!(Plane::new(Tup::zeros(), Tup::zeros()).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use super::bvh::Aabb;
+use super::plane::plane_distance;
+use super::ray::Ray;
+use super::shape::{Hit, Intersection, Shape};
+use super::tup::Tup;
+
+/// A parallelogram spanned by `edge1` and `edge2` from `corner`. It faces along
+/// `edge1 × edge2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quad {
+    pub corner: Tup,
+    pub edge1: Tup,
+    pub edge2: Tup,
+    normal: Tup,
+    /// Maps offsets in the plane to edge coordinates.
+    w: Tup,
+}
+
+impl Quad {
+    pub fn new(corner: Tup, edge1: Tup, edge2: Tup) -> Result<Self, String> {
+        let n = edge1.cross(edge2);
+        let n2 = n.dot(n);
+        if n2 == 0. {
+            return Err("quad edges must not be parallel or zero".to_string());
+        }
+        Ok(Quad {
+            corner,
+            edge1,
+            edge2,
+            normal: n.norm(),
+            w: n * (1. / n2),
+        })
+    }
+
+    /// Coordinates of a point in the plane along the two edges.
+    fn coordinates(&self, point: Tup) -> (f64, f64) {
+        let offset = point - self.corner;
+        (
+            self.w.dot(offset.cross(self.edge2)),
+            self.w.dot(self.edge1.cross(offset)),
+        )
+    }
+}
+
+impl Shape for Quad {
+    fn bounds(&self) -> Aabb {
+        let c = self.corner;
+        Aabb::empty()
+            .grow(c)
+            .grow(c + self.edge1)
+            .grow(c + self.edge2)
+            .grow(c + self.edge1 + self.edge2)
+    }
+
+    fn intersect(&self, ray: &Ray, t_max: f64) -> Option<Intersection> {
+        let t = plane_distance(ray, self.corner, self.normal, t_max)?;
+        let b = self.coordinates(ray.o + ray.d * t);
+        ((0. ..=1.).contains(&b.0) && (0. ..=1.).contains(&b.1)).then_some(Intersection {
+            t,
+            primitive: 0,
+            b,
+        })
+    }
+
+    /// The uv coordinates run from 0 to 1 along each edge.
+    fn hit(&self, ray: &Ray, i: &Intersection) -> Hit {
+        Hit {
+            t: i.t,
+            point: ray.o + ray.d * i.t,
+            normal: self.normal,
+            shading_normal: self.normal,
+            uv: i.b,
+            object: 0,
+            primitive: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_hits_quad() {
+        let quad = Quad::new(Tup(1., 0., 0.), Tup(2., 0., 0.), Tup(1., 4., 0.)).unwrap();
+        let ray = Ray {
+            o: Tup(2.5, 2., 5.),
+            d: Tup(0., 0., -1.),
+        };
+        let i = quad.intersect(&ray, f64::INFINITY).unwrap();
+        assert_eq!(i.t, 5.);
+        let hit = quad.hit(&ray, &i);
+        assert_eq!(hit.normal, Tup(0., 0., 1.));
+        assert!((hit.uv.0 - 0.5).abs() < 1e-12 && (hit.uv.1 - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ray_misses_outside_the_edges() {
+        let quad = Quad::new(Tup(1., 0., 0.), Tup(2., 0., 0.), Tup(1., 4., 0.)).unwrap();
+        for (x, y) in [(1.2, 2.), (3.8, 2.), (2., -0.1), (2., 4.1)] {
+            let ray = Ray {
+                o: Tup(x, y, 5.),
+                d: Tup(0., 0., -1.),
+            };
+            assert!(quad.intersect(&ray, f64::INFINITY).is_none(), "{} {}", x, y);
+        }
+        assert!(Quad::new(Tup::zeros(), Tup(1., 0., 0.), Tup(2., 0., 0.)).is_err());
+    }
+}
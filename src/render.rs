@@ -0,0 +1,758 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::camera::{Camera, Projection};
+use super::film::{CameraSample, Film, FilmPixel};
+use super::filter::{Filter, FilterMethod, FilterType};
+use super::image::Image;
+use super::integrator::{integrate, IntegrationType};
+use super::sampler::{Sampler, SamplerType};
+use super::scene::{Scene, SceneSettings};
+use super::tup::Tup;
+
+/// Settings that control a render but not the contents of the scene.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    /// Samples per subpixel; each pixel receives `samples * subpixels^2` samples.
+    pub samples: u32,
+    /// Size of the subpixel grid each pixel is split into.
+    pub subpixels: u32,
+    pub max_depth: Option<i32>,
+    pub integrator: IntegrationType,
+    pub sampler: SamplerType,
+    pub filter: FilterType,
+    pub filter_method: FilterMethod,
+    /// Replaces the projection of the scene's camera.
+    pub projection: Option<Projection>,
+    /// Relative error at which a pixel stops taking samples; see
+    /// [`FilmPixel::relative_error`]. Without it every pixel takes all samples.
+    pub adaptive_threshold: Option<f64>,
+    /// Samples per pixel every pixel takes before adaptive sampling may stop it.
+    pub adaptive_min_samples: u32,
+    /// Seed for the samplers. Without one a random seed is drawn per render.
+    pub seed: Option<u64>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 640,
+            height: 480,
+            samples: 50,
+            subpixels: 2,
+            max_depth: None,
+            integrator: IntegrationType::default(),
+            sampler: SamplerType::default(),
+            filter: FilterType::default(),
+            filter_method: FilterMethod::default(),
+            projection: None,
+            adaptive_threshold: None,
+            adaptive_min_samples: 16,
+            seed: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Overrides these settings with any that the scene file provides.
+    pub fn with_scene_settings(mut self, settings: &SceneSettings) -> Self {
+        if let Some(width) = settings.width {
+            self.width = width.get() as usize;
+        }
+        if let Some(height) = settings.height {
+            self.height = height.get() as usize;
+        }
+        if let Some(samples) = settings.samples {
+            self.samples = samples.get();
+        }
+        if let Some(subpixels) = settings.subpixels {
+            self.subpixels = subpixels.get();
+        }
+        if let Some(max_depth) = settings.max_depth {
+            self.max_depth = Some(i32::try_from(max_depth.get()).unwrap_or(i32::MAX));
+        }
+        if let Some(integrator) = settings.integrator {
+            self.integrator = integrator;
+        }
+        if let Some(sampler) = settings.sampler {
+            self.sampler = sampler;
+        }
+        if let Some(filter) = settings.filter {
+            self.filter = filter;
+        }
+        if let Some(filter_method) = settings.filter_method {
+            self.filter_method = filter_method;
+        }
+        if settings.adaptive_threshold.is_some() {
+            self.adaptive_threshold = settings.adaptive_threshold;
+        }
+        if let Some(min_samples) = settings.adaptive_min_samples {
+            self.adaptive_min_samples = min_samples.get();
+        }
+        self
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples * self.subpixels * self.subpixels
+    }
+}
+
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Image {
+    render_with_progress(scene, settings, |_, _| {})
+}
+
+/// Renders `scene`, calling `progress` with the number of finished pixels and
+/// the total number of pixels as the work advances.
+pub fn render_with_progress(
+    scene: &Scene,
+    settings: &RenderSettings,
+    progress: impl Fn(usize, usize) + Sync,
+) -> Image {
+    render_film(scene, settings, progress).image()
+}
+
+/// Rows of the image handed to a worker at a time.
+const BAND_ROWS: usize = 8;
+
+/// Like [`render_with_progress`], but returns the film with the extra data it
+/// keeps besides the image.
+pub fn render_film(
+    scene: &Scene,
+    settings: &RenderSettings,
+    progress: impl Fn(usize, usize) + Sync,
+) -> Film {
+    if settings.adaptive_threshold.is_some() {
+        // Adaptive sampling decides between passes of one sample per subpixel.
+        let pass_samples = settings.subpixels * settings.subpixels;
+        let passes = settings.samples_per_pixel().div_ceil(pass_samples) as usize;
+        let mut render = ProgressiveRender::new(settings);
+        let mut pass = 0;
+        while !render.is_done(settings) {
+            render.pass(scene, settings, pass_samples, |done, total| {
+                progress(pass * total + done, passes * total)
+            });
+            pass += 1;
+        }
+        let total = settings.width * settings.height;
+        progress(passes * total, passes * total);
+        return render.film;
+    }
+    let seed = settings.seed.unwrap_or_else(rand::random);
+    let mut film = Film::new(settings.width, settings.height);
+    render_samples(
+        scene,
+        settings,
+        seed,
+        0..settings.samples_per_pixel(),
+        None,
+        &mut film,
+        &AtomicBool::new(false),
+        progress,
+    );
+    film
+}
+
+/// A render that takes its samples in passes over the whole image, so that the
+/// film can be looked at between passes. The samplers are stateless between
+/// samples, so the seed and the number of samples taken are all it takes to
+/// continue the render later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgressiveRender {
+    pub film: Film,
+    pub seed: u64,
+    /// Samples per pixel taken so far.
+    pub samples: u32,
+}
+
+impl ProgressiveRender {
+    pub fn new(settings: &RenderSettings) -> Self {
+        ProgressiveRender {
+            film: Film::new(settings.width, settings.height),
+            seed: settings.seed.unwrap_or_else(rand::random),
+            samples: 0,
+        }
+    }
+
+    /// Whether all samples per pixel of `settings` have been taken, or
+    /// adaptive sampling has stopped every pixel.
+    pub fn is_done(&self, settings: &RenderSettings) -> bool {
+        self.samples >= settings.samples_per_pixel()
+            || (settings.adaptive_threshold.is_some()
+                && !self.active_pixels(settings).contains(&true))
+    }
+
+    /// Which pixels the next pass samples. With adaptive sampling a pixel
+    /// stops for good once it has its minimum number of samples and neither
+    /// it nor any of its neighbours is above the error threshold.
+    pub fn active_pixels(&self, settings: &RenderSettings) -> Vec<bool> {
+        let Some(threshold) = settings.adaptive_threshold else {
+            return vec![true; self.film.pixels.len()];
+        };
+        let (w, h) = (self.film.width, self.film.height);
+        let error: Vec<f64> = self
+            .film
+            .pixels
+            .iter()
+            .map(FilmPixel::relative_error)
+            .collect();
+        (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                self.film.pixels[i].samples == self.samples
+                    && (self.samples < settings.adaptive_min_samples
+                        || (y.saturating_sub(1)..(y + 2).min(h)).any(|y| {
+                            (x.saturating_sub(1)..(x + 2).min(w))
+                                .any(|x| error[y * w + x] > threshold)
+                        }))
+            })
+            .collect()
+    }
+
+    /// Takes the next `samples` samples per pixel, or fewer if the render
+    /// needs fewer to finish, calling `progress` like [`render_with_progress`].
+    pub fn pass(
+        &mut self,
+        scene: &Scene,
+        settings: &RenderSettings,
+        samples: u32,
+        progress: impl Fn(usize, usize) + Sync,
+    ) {
+        self.interruptible_pass(scene, settings, samples, &AtomicBool::new(false), progress);
+    }
+
+    /// Like [`ProgressiveRender::pass`], but gives up on the pass as soon as
+    /// `interrupt` is set, leaving the render as it was before. Returns
+    /// whether the pass was completed.
+    pub fn interruptible_pass(
+        &mut self,
+        scene: &Scene,
+        settings: &RenderSettings,
+        samples: u32,
+        interrupt: &AtomicBool,
+        progress: impl Fn(usize, usize) + Sync,
+    ) -> bool {
+        let end = self
+            .samples
+            .saturating_add(samples)
+            .min(settings.samples_per_pixel());
+        let active = self.active_pixels(settings);
+        let done = render_samples(
+            scene,
+            settings,
+            self.seed,
+            self.samples..end,
+            Some(&active),
+            &mut self.film,
+            interrupt,
+            progress,
+        );
+        if done {
+            self.samples = self.samples.max(end);
+        }
+        done
+    }
+}
+
+/// Takes the samples with indices in `samples` for every pixel, or for those
+/// marked in `active`, and adds them to `film`. If `interrupt` is set before
+/// all of them are taken, `film` is left alone and `false` returned.
+#[allow(clippy::too_many_arguments)]
+fn render_samples(
+    scene: &Scene,
+    settings: &RenderSettings,
+    seed: u64,
+    samples: Range<u32>,
+    active: Option<&[bool]>,
+    film: &mut Film,
+    interrupt: &AtomicBool,
+    progress: impl Fn(usize, usize) + Sync,
+) -> bool {
+    let w = settings.width;
+    let h = settings.height;
+    let filter = settings.filter.build();
+    let mut camera = scene.camera.clone();
+    if let Some(projection) = settings.projection {
+        camera.projection = projection;
+    }
+    // Rows above and below a band that its samples can reach.
+    let margin = match settings.filter_method {
+        FilterMethod::Importance => 0,
+        FilterMethod::Splat => filter.radius().ceil() as usize,
+    };
+    let progress_counter = AtomicUsize::new(0);
+
+    // Each band is rendered into a film of its own, and the bands are added up
+    // in order afterwards, so the result does not depend on which thread
+    // rendered which band.
+    let bands: Option<Vec<(usize, Film)>> = (0..h)
+        .step_by(BAND_ROWS)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|first| {
+            let last = (first + BAND_ROWS).min(h);
+            let top = first.saturating_sub(margin);
+            let mut film = Film::new(w, (last + margin).min(h) - top);
+            let mut sampler = settings.sampler.build(seed, settings.samples_per_pixel());
+            for row in first..last {
+                if interrupt.load(Ordering::Relaxed) {
+                    return None;
+                }
+                for x in 0..w {
+                    if active.is_some_and(|active| !active[row * w + x]) {
+                        continue;
+                    }
+                    render_pixel(
+                        scene,
+                        &camera,
+                        settings,
+                        filter.as_ref(),
+                        sampler.as_mut(),
+                        (x, row),
+                        samples.clone(),
+                        &mut film,
+                        top,
+                    );
+                }
+            }
+            let pixels = (last - first) * w;
+            let done = progress_counter.fetch_add(pixels, Ordering::SeqCst) + pixels;
+            progress(done, w * h);
+            Some((top, film))
+        })
+        .collect();
+
+    let Some(bands) = bands else {
+        return false;
+    };
+    for (top, band) in &bands {
+        film.merge(band, *top);
+    }
+    true
+}
+
+/// Takes the samples with indices in `samples` of the pixel in column `x` and
+/// image row `row`, adding them to `film`, whose first row is image row `top`.
+/// Consecutive samples go to consecutive subpixels, so that any run of
+/// `subpixels^2` samples covers the whole pixel.
+#[allow(clippy::too_many_arguments)]
+fn render_pixel(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    filter: &dyn Filter,
+    sampler: &mut dyn Sampler,
+    (x, row): (usize, usize),
+    samples: Range<u32>,
+    film: &mut Film,
+    top: usize,
+) {
+    let w = settings.width;
+    let h = settings.height;
+    let n = settings.subpixels;
+    let index = row * w + x;
+    // The camera counts rows from the bottom.
+    let y = h - 1 - row;
+
+    for i in samples {
+        let subpixel = i % (n * n);
+        let (sx, sy) = (subpixel % n, subpixel / n);
+        sampler.start_pixel_sample(index as u64, i as u64);
+        let u = sampler.next_2d();
+        // Position of the sample within the subpixel grid, in [0, 1)^2.
+        let u = ((sx as f64 + u.0) / n as f64, (sy as f64 + u.1) / n as f64);
+        let (fx, fy, weight) = match settings.filter_method {
+            FilterMethod::Importance => {
+                let fs = filter.sample(u);
+                (
+                    x as f64 + 0.5 + fs.offset.0,
+                    y as f64 + 0.5 + fs.offset.1,
+                    fs.weight,
+                )
+            }
+            FilterMethod::Splat => (x as f64 + u.0, y as f64 + u.1, 1.),
+        };
+        // Pinhole cameras leave the lens dimensions unused; each bounce
+        // starts at dimensions of its own either way.
+        let lens = if camera.aperture > 0. {
+            sampler.next_2d()
+        } else {
+            (0.5, 0.5)
+        };
+        let mut first_hit = None;
+        let radiance = match camera.ray(w, h, fx, fy, lens) {
+            Some(ray) => integrate(
+                &scene.world,
+                ray,
+                0,
+                settings.max_depth,
+                sampler,
+                settings.integrator,
+                &mut first_hit,
+            ),
+            None => Tup::zeros(),
+        };
+        let sample = CameraSample {
+            radiance,
+            first_hit,
+        };
+        let (dx, dy) = (fx - x as f64 - 0.5, fy - y as f64 - 0.5);
+        film.record(x, row - top, &sample, (dx * dx + dy * dy).sqrt());
+        match settings.filter_method {
+            FilterMethod::Importance => film.add(x, row - top, &sample, weight),
+            FilterMethod::Splat => splat(film, top, h, filter, (fx, fy), &sample),
+        }
+    }
+}
+
+/// Adds `sample` to every pixel of `film` whose filter covers the camera-space
+/// position `p`.
+fn splat(
+    film: &mut Film,
+    top: usize,
+    h: usize,
+    filter: &dyn Filter,
+    p: (f64, f64),
+    sample: &CameraSample,
+) {
+    let r = filter.radius();
+    // Pixels whose centres lie within the radius of `p`.
+    let range = |v: f64, size: usize| {
+        let lo = (v - r - 0.5).ceil().max(0.) as usize;
+        let hi = ((v + r - 0.5).floor() as i64).min(size as i64 - 1);
+        lo as i64..=hi
+    };
+    for y in range(p.1, h) {
+        let row = h - 1 - y as usize;
+        if row < top || row >= top + film.height {
+            continue;
+        }
+        for x in range(p.0, film.width) {
+            let weight = filter.eval(p.0 - (x as f64 + 0.5), p.1 - (y as f64 + 0.5));
+            if weight != 0. {
+                film.add(x as usize, row - top, sample, weight);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_renders_are_repeatable() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 16,
+            height: 12,
+            samples: 1,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let a = render(&scene, &settings);
+        let b = render(&scene, &settings);
+        assert_eq!(a.width, 16);
+        assert_eq!(a.pixels.len(), 16 * 12);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn renders_do_not_depend_on_thread_count() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 24,
+            height: 10,
+            samples: 2,
+            seed: Some(9),
+            ..Default::default()
+        };
+        let images: Vec<Image> = [1, 3]
+            .into_iter()
+            .map(|threads| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap()
+                    .install(|| render(&scene, &settings))
+            })
+            .collect();
+        assert_eq!(images[0], images[1]);
+    }
+
+    #[test]
+    fn splatting_does_not_depend_on_thread_count() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 20,
+            height: 19,
+            samples: 1,
+            filter: FilterType::Mitchell {
+                radius: 2.,
+                b: 1. / 3.,
+                c: 1. / 3.,
+            },
+            filter_method: FilterMethod::Splat,
+            seed: Some(4),
+            ..Default::default()
+        };
+        let images: Vec<Image> = [1, 2]
+            .into_iter()
+            .map(|threads| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap()
+                    .install(|| render(&scene, &settings))
+            })
+            .collect();
+        assert_eq!(images[0], images[1]);
+    }
+
+    #[test]
+    fn filters_preserve_a_constant_image() {
+        // Inside an emissive sphere every sample sees the same radiance, so
+        // every pixel must come out as exactly that, negative lobes and image
+        // borders included.
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "spheres": [
+                    { "radius": 1000, "position": [0, 0, 0], "emission": [1, 2, 3],
+                      "color": [0, 0, 0], "material": "DIFF" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        for filter in ["box", "tent", "gaussian", "mitchell", "lanczos"] {
+            for filter_method in [FilterMethod::Importance, FilterMethod::Splat] {
+                let settings = RenderSettings {
+                    width: 9,
+                    height: 7,
+                    samples: 1,
+                    filter: filter.parse().unwrap(),
+                    filter_method,
+                    seed: Some(5),
+                    ..Default::default()
+                };
+                for p in render(&scene, &settings).pixels {
+                    assert!(
+                        (p - Tup(1., 2., 3.)).dot(p - Tup(1., 2., 3.)) < 1e-18,
+                        "{} {} {:?}",
+                        filter,
+                        filter_method,
+                        p
+                    );
+                }
+            }
+        }
+    }
+
+    /// Unlike smallpt, the default render neither clamps samples nor keeps
+    /// them within their subpixel: a one-pixel tent spreads a bright half of
+    /// the image into the first dark column next to it.
+    #[test]
+    fn default_reconstruction_is_an_unclamped_pixel_tent() {
+        let settings = RenderSettings::default();
+        assert_eq!(settings.filter, FilterType::Tent { radius: 1. });
+        assert_eq!(settings.filter_method, FilterMethod::Importance);
+
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "look_at": [0, 0, -1], "fov": 40 },
+                "quads": [
+                    { "corner": [0, -100, -5], "edges": [[100, 0, 0], [0, 200, 0]],
+                      "emission": [4, 4, 4], "color": [0, 0, 0], "material": "DIFF" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let image = render(
+            &scene,
+            &RenderSettings {
+                width: 8,
+                height: 2,
+                samples: 16,
+                seed: Some(8),
+                ..settings
+            },
+        );
+        let row: Vec<f64> = image.pixels[..8].iter().map(|p| p.0).collect();
+        assert!(row[7] > 3.9 && row[6] > 3.9, "{:?}", row);
+        assert!(row[3] > 0. && row[3] < 1., "{:?}", row);
+        assert_eq!(row[2], 0., "{:?}", row);
+    }
+
+    /// The mean of a single 8 spp render varies by about 2.5% with the seed,
+    /// and any change to which dimensions a path uses draws different numbers
+    /// for it, so one seed makes this test depend on the dimension layout.
+    /// Averaged over a few seeds the samplers agree to about 1%.
+    #[test]
+    fn samplers_agree_on_the_mean() {
+        let scene = Scene::cornell_box();
+        let mean = |sampler| {
+            let seeds = 6;
+            (0..seeds)
+                .map(|seed| {
+                    let settings = RenderSettings {
+                        width: 32,
+                        height: 24,
+                        samples: 8,
+                        sampler,
+                        seed: Some(seed),
+                        ..Default::default()
+                    };
+                    let image = render(&scene, &settings);
+                    image.pixels.iter().map(|p| p.0 + p.1 + p.2).sum::<f64>()
+                        / image.pixels.len() as f64
+                })
+                .sum::<f64>()
+                / seeds as f64
+        };
+        let independent = mean(SamplerType::Independent);
+        for t in [
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+        ] {
+            let m = mean(t);
+            assert!(
+                (m - independent).abs() < 0.05 * independent,
+                "{} {} {}",
+                t,
+                m,
+                independent
+            );
+        }
+    }
+
+    /// The plane walls sit where the wall spheres touch the box, so with the
+    /// same seed nearly every path takes the same course in both worlds.
+    #[test]
+    fn plane_walls_render_like_sphere_walls() {
+        let spheres = Scene::cornell_box();
+        let planes = Scene {
+            world: crate::world::World::cornell_planes(),
+            ..Scene::cornell_box()
+        };
+        let settings = RenderSettings {
+            width: 32,
+            height: 24,
+            samples: 4,
+            seed: Some(3),
+            ..Default::default()
+        };
+        let a = render(&spheres, &settings);
+        let b = render(&planes, &settings);
+        let sum = |image: &Image| image.pixels.iter().map(|p| p.0 + p.1 + p.2).sum::<f64>();
+        let diff: f64 = a
+            .pixels
+            .iter()
+            .zip(&b.pixels)
+            .map(|(p, q)| (p.0 - q.0).abs() + (p.1 - q.1).abs() + (p.2 - q.2).abs())
+            .sum();
+        assert!(
+            (sum(&a) - sum(&b)).abs() < 0.01 * sum(&a),
+            "{} {}",
+            sum(&a),
+            sum(&b)
+        );
+        assert!(diff < 0.05 * sum(&a), "{} {}", diff, sum(&a));
+    }
+
+    #[test]
+    fn progressive_passes_add_up_to_a_full_render() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 12,
+            height: 9,
+            samples: 2,
+            seed: Some(3),
+            ..Default::default()
+        };
+        let full = render_film(&scene, &settings, |_, _| {});
+        let mut progressive = ProgressiveRender::new(&settings);
+        let mut passes = 0;
+        while !progressive.is_done(&settings) {
+            progressive.pass(&scene, &settings, 3, |_, _| {});
+            passes += 1;
+        }
+        // 8 samples per pixel in passes of 3, 3 and 2.
+        assert_eq!(passes, 3);
+        assert_eq!(progressive.samples, 8);
+        assert!(progressive.film.pixels.iter().all(|p| p.samples == 8));
+        // The same samples, only summed in a different order.
+        for (p, q) in progressive
+            .film
+            .image()
+            .pixels
+            .iter()
+            .zip(&full.image().pixels)
+        {
+            assert!((*p - *q).dot(*p - *q) < 1e-20, "{:?} {:?}", p, q);
+        }
+    }
+
+    #[test]
+    fn interrupted_passes_leave_the_render_alone() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 8,
+            height: 8,
+            samples: 1,
+            seed: Some(3),
+            ..Default::default()
+        };
+        let mut progressive = ProgressiveRender::new(&settings);
+        progressive.pass(&scene, &settings, 1, |_, _| {});
+        let before = progressive.clone();
+        let interrupt = AtomicBool::new(true);
+        assert!(!progressive.interruptible_pass(&scene, &settings, 1, &interrupt, |_, _| {}));
+        assert_eq!(progressive, before);
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_converged_pixels() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 16,
+            height: 12,
+            samples: 16,
+            adaptive_threshold: Some(0.1),
+            adaptive_min_samples: 8,
+            seed: Some(6),
+            ..Default::default()
+        };
+        let film = render_film(&scene, &settings, |_, _| {});
+        let counts: Vec<u32> = film.pixels.iter().map(|p| p.samples).collect();
+        assert!(counts.iter().all(|&n| (8..=64).contains(&n) && n % 4 == 0));
+        assert!(counts.contains(&64), "{:?}", counts);
+        assert!(counts.iter().any(|&n| n < 64), "{:?}", counts);
+        // Pixels that stopped early did so because their neighbourhood was
+        // below the threshold.
+        for (i, p) in film.pixels.iter().enumerate() {
+            if p.samples < 64 {
+                assert!(p.relative_error() <= 0.1, "{} {:?}", i, p);
+            }
+        }
+        assert_eq!(film, render_film(&scene, &settings, |_, _| {}));
+    }
+
+    #[test]
+    fn scene_settings_override_defaults() {
+        let scene = Scene::cornell_box();
+        let settings = RenderSettings {
+            width: 1,
+            samples: 1,
+            ..Default::default()
+        }
+        .with_scene_settings(&scene.settings);
+        assert_eq!(settings.width, 640);
+        assert_eq!(settings.samples, 50);
+        assert_eq!(settings.samples_per_pixel(), 200);
+    }
+}
@@ -1,21 +1,309 @@
-use rand::{rngs::ThreadRng, thread_rng, Rng};
+use std::fmt;
+use std::str::FromStr;
 
-pub struct Sampler {
-    rng: ThreadRng
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use super::halton::HaltonSampler;
+use super::sobol::SobolSampler;
+use super::stratified::StratifiedSampler;
+
+/// A source of sample values for one path at a time. A sample is identified by
+/// its pixel and its index within the pixel, and consists of a sequence of
+/// dimensions that `next` and `next_2d` walk through. Samplers depend only on
+/// their seed, the pixel, the index and the dimension, so a render never
+/// depends on how the work is split between threads.
+pub trait Sampler: Send {
+    /// Moves to sample `index` of pixel `pixel`, at dimension zero.
+    fn start_pixel_sample(&mut self, pixel: u64, index: u64);
+
+    /// Moves to `dimension` of the current sample. Integrators use this to keep
+    /// the same dimensions for the same decisions, whatever happened earlier on
+    /// the path.
+    fn set_dimension(&mut self, dimension: u32);
+
+    /// Returns the value of the current dimension and moves to the next one.
+    fn next(&mut self) -> f64;
+
+    /// Returns a 2D value taken from the next two dimensions.
+    fn next_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplerType {
+    /// Independent uniform random numbers.
+    #[default]
+    Independent,
+    /// Jittered stratified sampling.
+    Stratified,
+    /// Halton sequence with random digit permutations.
+    Halton,
+    /// Owen-scrambled Sobol sequence.
+    Sobol,
+}
+
+impl SamplerType {
+    /// Creates a sampler for renders with `spp` samples per pixel. The sample
+    /// count only guides the stratification; more samples may still be drawn.
+    pub fn build(self, seed: u64, spp: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(seed, spp)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(seed, spp)),
+        }
+    }
+}
+
+impl FromStr for SamplerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "independent" => Ok(SamplerType::Independent),
+            "stratified" => Ok(SamplerType::Stratified),
+            "halton" => Ok(SamplerType::Halton),
+            "sobol" => Ok(SamplerType::Sobol),
+            _ => Err(format!(
+                "unknown sampler '{}' (expected 'independent', 'stratified', 'halton' or 'sobol')",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SamplerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SamplerType::Independent => "independent",
+            SamplerType::Stratified => "stratified",
+            SamplerType::Halton => "halton",
+            SamplerType::Sobol => "sobol",
+        })
+    }
 }
 
-impl Sampler {
-    pub fn new() -> Self {
-        Sampler {
-            rng: thread_rng()
+/// Independent uniform random numbers. Each (pixel, sample) pair gets its own
+/// ChaCha stream position and every dimension a fixed offset within it.
+pub struct IndependentSampler {
+    rng: ChaCha8Rng,
+    index: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            index: 0,
         }
     }
+}
 
-    pub fn next(&mut self) -> f64 {
+impl Sampler for IndependentSampler {
+    /// Every sample has 2^32 words of the pixel's stream to itself.
+    fn start_pixel_sample(&mut self, pixel: u64, index: u64) {
+        self.index = index;
+        self.rng.set_stream(pixel);
+        self.rng.set_word_pos(u128::from(index) << 32);
+    }
+
+    /// Each dimension takes two words, one `f64`.
+    fn set_dimension(&mut self, dimension: u32) {
+        self.rng
+            .set_word_pos((u128::from(self.index) << 32) + 2 * u128::from(dimension));
+    }
+
+    fn next(&mut self) -> f64 {
         self.rng.gen::<f64>()
     }
 
-    pub fn next_2d(&mut self) -> (f64, f64) {
+    fn next_2d(&mut self) -> (f64, f64) {
         (self.rng.gen::<f64>(), self.rng.gen::<f64>())
     }
-}
\ No newline at end of file
+}
+
+/// The largest `f64` below one.
+pub(crate) const ONE_MINUS_EPSILON: f64 = 1. - f64::EPSILON / 2.;
+
+/// Scrambles the bits of `v`; the finalizer from pbrt's `MixBits`.
+pub(crate) fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9e3779b97f4a7c15, |h, &v| mix_bits(h ^ mix_bits(v)))
+}
+
+/// Uniform value in [0, 1) derived from a hash.
+pub(crate) fn hash_float(values: &[u64]) -> f64 {
+    (hash(values) >> 11) as f64 * (1. / (1u64 << 53) as f64)
+}
+
+/// Element `i` of a pseudo-random permutation of `0..l` chosen by `p`
+/// (Kensler, "Correlated Multi-Jittered Sampling").
+pub(crate) fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    ((u64::from(i) + u64::from(p)) % u64::from(l)) as u32
+}
+
+/// Permutes sample indices in blocks of `l`, with a different permutation for
+/// every block.
+pub(crate) fn permute_index(index: u64, l: u32, seed: u64) -> u64 {
+    let l64 = u64::from(l);
+    let block = index / l64;
+    let p = hash(&[seed, block]) as u32;
+    block * l64 + u64::from(permutation_element((index % l64) as u32, l, p))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Draws `n` 2D samples for one pixel from dimension `dim`.
+    pub fn points(sampler: &mut dyn Sampler, n: u64, dim: u32) -> Vec<(f64, f64)> {
+        (0..n)
+            .map(|i| {
+                sampler.start_pixel_sample(7, i);
+                sampler.set_dimension(dim);
+                sampler.next_2d()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn samples_depend_only_on_seed_pixel_index_and_dimension() {
+        for t in [
+            SamplerType::Independent,
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+        ] {
+            let mut a = t.build(3, 16);
+            let mut b = t.build(3, 16);
+            a.start_pixel_sample(10, 5);
+            let first = (a.next(), a.next_2d());
+            a.start_pixel_sample(11, 0);
+            a.next();
+            a.start_pixel_sample(10, 5);
+            assert_eq!((a.next(), a.next_2d()), first, "{}", t);
+
+            b.start_pixel_sample(10, 5);
+            b.set_dimension(1);
+            assert_eq!(b.next_2d(), first.1, "{}", t);
+        }
+    }
+
+    #[test]
+    fn samples_are_in_unit_interval() {
+        for t in [
+            SamplerType::Independent,
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+        ] {
+            let mut s = t.build(1, 64);
+            for i in 0..200 {
+                s.start_pixel_sample(i % 7, i);
+                for _ in 0..40 {
+                    let v = s.next();
+                    assert!((0. ..1.).contains(&v), "{} {}", t, v);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn streams_differ() {
+        let mut s = IndependentSampler::new(3);
+        s.start_pixel_sample(0, 0);
+        let a = s.next();
+        s.start_pixel_sample(1, 0);
+        let b = s.next();
+        s.start_pixel_sample(0, 1);
+        let c = s.next();
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(
+            IndependentSampler::new(4).next(),
+            IndependentSampler::new(3).next()
+        );
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_beat_independent_sampling() {
+        // Integrate f(x, y) = x * y = 1/4 over the unit square with 256 samples,
+        // averaged over several pixels.
+        let error = |t: SamplerType| {
+            let mut s = t.build(5, 256);
+            (0..16)
+                .map(|pixel| {
+                    let mean = (0..256)
+                        .map(|i| {
+                            s.start_pixel_sample(pixel, i);
+                            s.set_dimension(6);
+                            let (x, y) = s.next_2d();
+                            x * y
+                        })
+                        .sum::<f64>()
+                        / 256.;
+                    (mean - 0.25).abs()
+                })
+                .sum::<f64>()
+                / 16.
+        };
+        let independent = error(SamplerType::Independent);
+        for t in [
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+        ] {
+            assert!(error(t) < independent / 4., "{} {}", t, error(t));
+        }
+    }
+
+    #[test]
+    fn permutations_are_bijective() {
+        for l in [1, 2, 5, 16, 100] {
+            let mut seen: Vec<u32> = (0..l).map(|i| permutation_element(i, l, 1234)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..l).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,1770 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::camera::Camera;
+use super::conductor::Conductor;
+use super::cuboid::Cuboid;
+use super::dielectric::Dielectric;
+use super::disk::Disk;
+use super::disney::Principled;
+use super::filter::{FilterMethod, FilterType};
+use super::input;
+use super::integrator::IntegrationType;
+use super::material::{Diffuse, Glass, Material, Mirror};
+use super::mesh::Mesh;
+use super::obj::{self, ObjError, Part};
+use super::plane::Plane;
+use super::quad::Quad;
+use super::sampler::SamplerType;
+use super::shape::Shape;
+use super::sphere::Sphere;
+use super::texture::{Axis, Checker, Gradient, ImageTexture, Marble, Noise, Texture, Wrap};
+use super::textured::{self, Textured};
+use super::tup::Tup;
+use super::world::{Object, World};
+
+/// The Cornell box, bundled so that a render without a scene file still works.
+pub const CORNELL_BOX: &str = include_str!("../scenes/cornell.json");
+
+/// Render settings stored in a scene file. Anything left out falls back to the
+/// command line or the renderer defaults.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneSettings {
+    pub width: Option<NonZeroU32>,
+    pub height: Option<NonZeroU32>,
+    pub samples: Option<NonZeroU32>,
+    pub subpixels: Option<NonZeroU32>,
+    pub max_depth: Option<NonZeroU32>,
+    pub integrator: Option<IntegrationType>,
+    pub sampler: Option<SamplerType>,
+    pub filter: Option<FilterType>,
+    pub filter_method: Option<FilterMethod>,
+    pub adaptive_threshold: Option<f64>,
+    pub adaptive_min_samples: Option<NonZeroU32>,
+}
+
+pub struct Scene {
+    pub camera: Camera,
+    pub world: World,
+    pub settings: SceneSettings,
+    /// Hash of the scene description, used to tell whether a checkpoint
+    /// belongs to this scene.
+    pub hash: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    camera: Camera,
+    #[serde(default)]
+    settings: SceneSettings,
+    #[serde(default)]
+    spheres: Vec<SphereEntry>,
+    #[serde(default)]
+    meshes: Vec<MeshEntry>,
+    #[serde(default)]
+    planes: Vec<PlaneEntry>,
+    #[serde(default)]
+    disks: Vec<DiskEntry>,
+    #[serde(default)]
+    quads: Vec<QuadEntry>,
+    #[serde(default)]
+    boxes: Vec<BoxEntry>,
+    #[serde(default)]
+    models: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereEntry {
+    #[serde(deserialize_with = "radius")]
+    radius: f64,
+    position: Tup,
+    #[serde(default = "black")]
+    emission: TextureEntry,
+    color: TextureEntry,
+    material: MaterialEntry,
+}
+
+/// An object whose textures are still to be loaded.
+struct ObjectEntry {
+    shape: Box<dyn Shape>,
+    emission: TextureEntry,
+    color: TextureEntry,
+    material: MaterialEntry,
+}
+
+impl ObjectEntry {
+    fn new(
+        shape: impl Shape + 'static,
+        emission: TextureEntry,
+        color: TextureEntry,
+        material: MaterialEntry,
+    ) -> Self {
+        ObjectEntry {
+            shape: Box::new(shape),
+            emission,
+            color,
+            material,
+        }
+    }
+
+    fn load(self, dir: &Path, hash: &mut u64) -> Result<Object, SceneError> {
+        Ok(Object {
+            shape: self.shape,
+            emission: self.emission.load(dir, hash)?,
+            color: self.color.load(dir, hash)?,
+            material: self.material.load(dir, hash)?,
+        })
+    }
+}
+
+/// A color, or a texture given as an object with a `type` and its
+/// parameters.
+enum TextureEntry {
+    Texture(Box<dyn Texture>),
+    /// An image file, read once the scene has been parsed and its directory is
+    /// known.
+    Image {
+        file: PathBuf,
+        wrap: Wrap,
+    },
+}
+
+impl<'de> Deserialize<'de> for TextureEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = TextureEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a color or an object with a `type`")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                let color = Tup::deserialize(SeqAccessDeserializer::new(seq))?;
+                Ok(TextureEntry::Texture(Box::new(color)))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let params = TextureParams::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(params.into())
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+impl TextureEntry {
+    /// The texture, reading its image relative to `dir` if it has one and
+    /// adding the file to `hash`.
+    fn load(self, dir: &Path, hash: &mut u64) -> Result<Box<dyn Texture>, SceneError> {
+        let (file, wrap) = match self {
+            TextureEntry::Texture(texture) => return Ok(texture),
+            TextureEntry::Image { file, wrap } => (file, wrap),
+        };
+        let path = dir.join(file);
+        let error = |e: &dyn fmt::Display| SceneError::Image {
+            path: path.clone(),
+            message: e.to_string(),
+        };
+        let data = fs::read(&path).map_err(|e| error(&e))?;
+        *hash = fnv1a(*hash, &data);
+        let image = input::read(&path, &data).map_err(|e| error(&e))?;
+        Ok(Box::new(
+            ImageTexture::new(image, wrap).map_err(|e| error(&e))?,
+        ))
+    }
+}
+
+fn black() -> TextureEntry {
+    TextureEntry::Texture(Box::new(Tup::zeros()))
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum TextureParams {
+    Checker {
+        #[serde(deserialize_with = "color")]
+        even: Tup,
+        #[serde(deserialize_with = "color")]
+        odd: Tup,
+        #[serde(deserialize_with = "scale")]
+        scale: f64,
+    },
+    Gradient {
+        #[serde(deserialize_with = "color")]
+        from: Tup,
+        #[serde(deserialize_with = "color")]
+        to: Tup,
+        #[serde(default = "vertical")]
+        axis: Axis,
+    },
+    Noise {
+        #[serde(deserialize_with = "color")]
+        low: Tup,
+        #[serde(deserialize_with = "color")]
+        high: Tup,
+        #[serde(deserialize_with = "scale")]
+        scale: f64,
+        #[serde(default = "octaves")]
+        octaves: u32,
+    },
+    Marble {
+        #[serde(deserialize_with = "color")]
+        low: Tup,
+        #[serde(deserialize_with = "color")]
+        high: Tup,
+        #[serde(deserialize_with = "scale")]
+        scale: f64,
+        #[serde(default = "octaves")]
+        octaves: u32,
+    },
+    /// A PPM, PNG or PFM file, relative to the scene file.
+    Image {
+        file: PathBuf,
+        #[serde(default)]
+        wrap: Wrap,
+    },
+}
+
+/// A color, or a number for a gray, as in textures of scalar material
+/// parameters.
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tup, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Color {
+        Gray(f64),
+        Rgb(Tup),
+    }
+    Ok(match Color::deserialize(deserializer)? {
+        Color::Gray(v) => Tup(v, v, v),
+        Color::Rgb(c) => c,
+    })
+}
+
+fn vertical() -> Axis {
+    Axis::V
+}
+
+fn octaves() -> u32 {
+    4
+}
+
+impl From<TextureParams> for TextureEntry {
+    fn from(t: TextureParams) -> Self {
+        let texture: Box<dyn Texture> = match t {
+            TextureParams::Checker { even, odd, scale } => Box::new(Checker { even, odd, scale }),
+            TextureParams::Gradient { from, to, axis } => Box::new(Gradient { from, to, axis }),
+            TextureParams::Noise {
+                low,
+                high,
+                scale,
+                octaves,
+            } => Box::new(Noise {
+                low,
+                high,
+                scale,
+                octaves,
+            }),
+            TextureParams::Marble {
+                low,
+                high,
+                scale,
+                octaves,
+            } => Box::new(Marble {
+                low,
+                high,
+                scale,
+                octaves,
+            }),
+            TextureParams::Image { file, wrap } => return TextureEntry::Image { file, wrap },
+        };
+        TextureEntry::Texture(texture)
+    }
+}
+
+/// A material, given either by its name in smallpt, `DIFF`, `SPEC` or
+/// `REFR`, or as an object with a `type` and its parameters.
+enum MaterialEntry {
+    Material(Box<dyn Material>),
+    /// Parameters, some of which may be textures still to be loaded.
+    Params(Box<MaterialParams>),
+}
+
+impl<'de> Deserialize<'de> for MaterialEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = MaterialEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a material name or an object with a `type`")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                let material: Box<dyn Material> = match name {
+                    "DIFF" => Box::new(Diffuse),
+                    "SPEC" => Box::new(Mirror),
+                    "REFR" => Box::new(Glass::default()),
+                    _ => {
+                        return Err(E::custom(format!(
+                            "unknown material '{}' (expected 'DIFF', 'SPEC' or 'REFR')",
+                            name
+                        )))
+                    }
+                };
+                Ok(MaterialEntry::Material(material))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let params = MaterialParams::deserialize(MapAccessDeserializer::new(map))?;
+                params.check().map_err(de::Error::custom)?;
+                Ok(MaterialEntry::Params(Box::new(params)))
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+impl MaterialEntry {
+    /// The material, reading the images of its textures relative to `dir` and
+    /// adding them to `hash`.
+    fn load(self, dir: &Path, hash: &mut u64) -> Result<Box<dyn Material>, SceneError> {
+        let params = match self {
+            MaterialEntry::Material(material) => return Ok(material),
+            MaterialEntry::Params(params) => params,
+        };
+        Ok(match *params {
+            MaterialParams::Diffuse {} => Box::new(Diffuse),
+            MaterialParams::Mirror {} => Box::new(Mirror),
+            MaterialParams::Glass { ior, absorption } => {
+                let ior = ior.load(dir, hash)?;
+                let absorption = absorption.load(dir, hash)?;
+                textured(
+                    ior.is_constant() && absorption.is_constant(),
+                    move |uv, p| {
+                        Glass::new(positive_ior(ior.at(uv, p)))
+                            .with_absorption(non_negative(absorption.at(uv, p)))
+                    },
+                )
+            }
+            MaterialParams::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+            } => {
+                let roughness = roughness.load(dir, hash)?;
+                let (eta, k) = match metal {
+                    // `check` made sure the metal is known.
+                    Some(name) => {
+                        let metal = Conductor::preset(&name).expect("known metal");
+                        (Param::Value(metal.eta), Param::Value(metal.k))
+                    }
+                    None => (
+                        eta.expect("checked").load(dir, hash)?,
+                        k.expect("checked").load(dir, hash)?,
+                    ),
+                };
+                textured(
+                    eta.is_constant() && k.is_constant() && roughness.is_constant(),
+                    move |uv, p| {
+                        Conductor::new(non_negative(eta.at(uv, p)), non_negative(k.at(uv, p)))
+                            .with_roughness(unit_pair(roughness.at(uv, p)))
+                    },
+                )
+            }
+            MaterialParams::Dielectric {
+                ior,
+                absorption,
+                roughness,
+            } => {
+                let ior = ior.load(dir, hash)?;
+                let absorption = absorption.load(dir, hash)?;
+                let roughness = roughness.load(dir, hash)?;
+                textured(
+                    ior.is_constant() && absorption.is_constant() && roughness.is_constant(),
+                    move |uv, p| {
+                        Dielectric::new(positive_ior(ior.at(uv, p)))
+                            .with_absorption(non_negative(absorption.at(uv, p)))
+                            .with_roughness(unit_pair(roughness.at(uv, p)))
+                    },
+                )
+            }
+            MaterialParams::Principled(params) => {
+                let mut load = |param: ParamEntry<f64>| param.load(dir, hash);
+                let metallic = load(params.metallic)?;
+                let roughness = load(params.roughness)?;
+                let specular = load(params.specular)?;
+                let sheen = load(params.sheen)?;
+                let clearcoat = load(params.clearcoat)?;
+                let clearcoat_gloss = load(params.clearcoat_gloss)?;
+                let transmission = load(params.transmission)?;
+                let ior = load(params.ior)?;
+                let constant = [
+                    &metallic,
+                    &roughness,
+                    &specular,
+                    &sheen,
+                    &clearcoat,
+                    &clearcoat_gloss,
+                    &transmission,
+                    &ior,
+                ]
+                .iter()
+                .all(|param| param.is_constant());
+                textured(constant, move |uv, p| Principled {
+                    metallic: metallic.at(uv, p).clamp(0., 1.),
+                    roughness: roughness.at(uv, p).clamp(0., 1.),
+                    specular: specular.at(uv, p).clamp(0., 1.),
+                    sheen: sheen.at(uv, p).clamp(0., 1.),
+                    clearcoat: clearcoat.at(uv, p).clamp(0., 1.),
+                    clearcoat_gloss: clearcoat_gloss.at(uv, p).clamp(0., 1.),
+                    transmission: transmission.at(uv, p).clamp(0., 1.),
+                    ior: positive_ior(ior.at(uv, p)),
+                })
+            }
+        })
+    }
+}
+
+/// The material `at` makes, or if some of its parameters are textures, one
+/// that `at` makes anew at each point.
+fn textured<M: Material + 'static>(
+    constant: bool,
+    at: impl Fn((f64, f64), Tup) -> M + Send + Sync + 'static,
+) -> Box<dyn Material> {
+    if constant {
+        Box::new(at((0., 0.), Tup::zeros()))
+    } else {
+        Box::new(Textured::new(at))
+    }
+}
+
+// Textures can hold any value, so those looked up in them are brought into
+// the range their parameter allows; values given directly are checked
+// instead.
+
+fn positive_ior(ior: f64) -> f64 {
+    ior.max(1e-3)
+}
+
+fn non_negative(t: Tup) -> Tup {
+    Tup(t.0.max(0.), t.1.max(0.), t.2.max(0.))
+}
+
+fn unit_pair((x, y): (f64, f64)) -> (f64, f64) {
+    (x.clamp(0., 1.), y.clamp(0., 1.))
+}
+
+/// A material parameter: a value, or a texture given as an object with a
+/// `type` to look it up in at each hit.
+enum ParamEntry<T> {
+    Value(T),
+    Texture(TextureEntry),
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for ParamEntry<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ParamVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for ParamVisitor<T> {
+            type Value = ParamEntry<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a value or a texture object with a `type`")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                T::deserialize(v.into_deserializer()).map(ParamEntry::Value)
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                T::deserialize(v.into_deserializer()).map(ParamEntry::Value)
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                T::deserialize(v.into_deserializer()).map(ParamEntry::Value)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                T::deserialize(v.into_deserializer()).map(ParamEntry::Value)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                T::deserialize(SeqAccessDeserializer::new(seq)).map(ParamEntry::Value)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let params = TextureParams::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(ParamEntry::Texture(params.into()))
+            }
+        }
+
+        deserializer.deserialize_any(ParamVisitor(PhantomData))
+    }
+}
+
+impl<T: Default> Default for ParamEntry<T> {
+    fn default() -> Self {
+        ParamEntry::Value(T::default())
+    }
+}
+
+impl<T> ParamEntry<T> {
+    /// Converts or checks a value given directly.
+    fn try_map<U>(self, f: impl FnOnce(T) -> Result<U, String>) -> Result<ParamEntry<U>, String> {
+        Ok(match self {
+            ParamEntry::Value(v) => ParamEntry::Value(f(v)?),
+            ParamEntry::Texture(t) => ParamEntry::Texture(t),
+        })
+    }
+
+    fn value(&self) -> Option<&T> {
+        match self {
+            ParamEntry::Value(v) => Some(v),
+            ParamEntry::Texture(_) => None,
+        }
+    }
+
+    fn load(self, dir: &Path, hash: &mut u64) -> Result<Param<T>, SceneError> {
+        Ok(match self {
+            ParamEntry::Value(v) => Param::Value(v),
+            ParamEntry::Texture(t) => Param::Texture(t.load(dir, hash)?),
+        })
+    }
+}
+
+/// A loaded [`ParamEntry`].
+enum Param<T> {
+    Value(T),
+    Texture(Box<dyn Texture>),
+}
+
+impl<T: FromTexture + Copy> Param<T> {
+    fn is_constant(&self) -> bool {
+        matches!(self, Param::Value(_))
+    }
+
+    /// The value at texture coordinates `uv` of the point `p`.
+    fn at(&self, uv: (f64, f64), p: Tup) -> T {
+        match self {
+            Param::Value(v) => *v,
+            Param::Texture(t) => T::from_texture(t.as_ref(), uv, p),
+        }
+    }
+}
+
+/// The types of material parameters textures can stand for.
+trait FromTexture {
+    fn from_texture(t: &dyn Texture, uv: (f64, f64), p: Tup) -> Self;
+}
+
+impl FromTexture for f64 {
+    fn from_texture(t: &dyn Texture, uv: (f64, f64), p: Tup) -> Self {
+        textured::scalar(t, uv, p)
+    }
+}
+
+impl FromTexture for Tup {
+    fn from_texture(t: &dyn Texture, uv: (f64, f64), p: Tup) -> Self {
+        t.value(uv, p)
+    }
+}
+
+/// Textured roughness is the same in every direction.
+impl FromTexture for (f64, f64) {
+    fn from_texture(t: &dyn Texture, uv: (f64, f64), p: Tup) -> Self {
+        let r = textured::scalar(t, uv, p);
+        (r, r)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum MaterialParams {
+    // Empty structs rather than unit variants, which would accept any field.
+    Diffuse {},
+    Mirror {},
+    Glass {
+        #[serde(default = "glass_ior", deserialize_with = "ior")]
+        ior: ParamEntry<f64>,
+        #[serde(default, deserialize_with = "absorption")]
+        absorption: ParamEntry<Tup>,
+    },
+    /// A named `metal`, or one given by the real and imaginary parts of its
+    /// index of refraction.
+    Conductor {
+        metal: Option<String>,
+        eta: Option<ParamEntry<Tup>>,
+        k: Option<ParamEntry<Tup>>,
+        #[serde(default, deserialize_with = "roughness")]
+        roughness: ParamEntry<(f64, f64)>,
+    },
+    Dielectric {
+        #[serde(default = "glass_ior", deserialize_with = "ior")]
+        ior: ParamEntry<f64>,
+        #[serde(default, deserialize_with = "absorption")]
+        absorption: ParamEntry<Tup>,
+        #[serde(default, deserialize_with = "roughness")]
+        roughness: ParamEntry<(f64, f64)>,
+    },
+    /// Disney's principled BSDF, with the object's color as base color.
+    Principled(PrincipledParams),
+}
+
+/// The parameters of a principled BSDF. Those left out take the defaults of
+/// [`Principled`].
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PrincipledParams {
+    metallic: ParamEntry<f64>,
+    roughness: ParamEntry<f64>,
+    specular: ParamEntry<f64>,
+    sheen: ParamEntry<f64>,
+    clearcoat: ParamEntry<f64>,
+    clearcoat_gloss: ParamEntry<f64>,
+    transmission: ParamEntry<f64>,
+    #[serde(deserialize_with = "ior")]
+    ior: ParamEntry<f64>,
+}
+
+impl Default for PrincipledParams {
+    fn default() -> Self {
+        let d = Principled::default();
+        PrincipledParams {
+            metallic: ParamEntry::Value(d.metallic),
+            roughness: ParamEntry::Value(d.roughness),
+            specular: ParamEntry::Value(d.specular),
+            sheen: ParamEntry::Value(d.sheen),
+            clearcoat: ParamEntry::Value(d.clearcoat),
+            clearcoat_gloss: ParamEntry::Value(d.clearcoat_gloss),
+            transmission: ParamEntry::Value(d.transmission),
+            ior: ParamEntry::Value(d.ior),
+        }
+    }
+}
+
+impl MaterialParams {
+    /// Checks what can be checked before textures are loaded.
+    fn check(&self) -> Result<(), String> {
+        match self {
+            MaterialParams::Conductor { metal, eta, k, .. } => match (metal, eta, k) {
+                (Some(name), None, None) => {
+                    if Conductor::preset(name).is_none() {
+                        return Err(format!(
+                            "unknown metal '{}' (expected 'gold', 'copper', 'aluminium' \
+                             or 'silver')",
+                            name
+                        ));
+                    }
+                }
+                (None, Some(_), Some(_)) => {}
+                _ => return Err("a conductor needs either a `metal` or both `eta` and `k`".into()),
+            },
+            MaterialParams::Principled(p) => {
+                for (name, param) in [
+                    ("metallic", &p.metallic),
+                    ("roughness", &p.roughness),
+                    ("specular", &p.specular),
+                    ("sheen", &p.sheen),
+                    ("clearcoat", &p.clearcoat),
+                    ("clearcoat_gloss", &p.clearcoat_gloss),
+                    ("transmission", &p.transmission),
+                ] {
+                    if let Some(value) = param.value().filter(|v| !(0.0..=1.0).contains(*v)) {
+                        return Err(format!("{} must be between 0 and 1, got {}", name, value));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn glass_ior() -> ParamEntry<f64> {
+    ParamEntry::Value(Glass::default().ior)
+}
+
+/// An index of refraction, as a number or the name of a preset.
+fn ior<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ParamEntry<f64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Ior {
+        Value(f64),
+        Preset(String),
+    }
+    ParamEntry::<Ior>::deserialize(deserializer)?
+        .try_map(|ior| match ior {
+            Ior::Value(ior) if ior > 0.0 && ior.is_finite() => Ok(ior),
+            Ior::Value(ior) => Err(format!("ior must be positive, got {}", ior)),
+            Ior::Preset(name) => Glass::preset_ior(&name).ok_or_else(|| {
+                format!(
+                    "unknown ior '{}' (expected a number, 'water', 'ice', 'glass', \
+                     'sapphire' or 'diamond')",
+                    name
+                )
+            }),
+        })
+        .map_err(de::Error::custom)
+}
+
+fn absorption<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ParamEntry<Tup>, D::Error> {
+    ParamEntry::<Tup>::deserialize(deserializer)?
+        .try_map(|a| {
+            if [a.0, a.1, a.2].iter().all(|c| *c >= 0.0 && c.is_finite()) {
+                Ok(a)
+            } else {
+                Err(format!("absorption must not be negative, got {:?}", a))
+            }
+        })
+        .map_err(de::Error::custom)
+}
+
+/// Microfacet roughness between 0 and 1, either the same in every direction
+/// or as `[rx, ry]` along the two tangents of the surface.
+fn roughness<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ParamEntry<(f64, f64)>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Roughness {
+        Isotropic(f64),
+        Anisotropic(f64, f64),
+    }
+    ParamEntry::<Roughness>::deserialize(deserializer)?
+        .try_map(|r| {
+            let r = match r {
+                Roughness::Isotropic(r) => (r, r),
+                Roughness::Anisotropic(rx, ry) => (rx, ry),
+            };
+            if [r.0, r.1].iter().all(|r| (0.0..=1.0).contains(r)) {
+                Ok(r)
+            } else {
+                Err(format!("roughness must be between 0 and 1, got {:?}", r))
+            }
+        })
+        .map_err(de::Error::custom)
+}
+
+fn radius<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    positive(deserializer, "radius")
+}
+
+fn scale<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    positive(deserializer, "scale")
+}
+
+fn positive<'de, D: Deserializer<'de>>(deserializer: D, name: &str) -> Result<f64, D::Error> {
+    let r = f64::deserialize(deserializer)?;
+    if r > 0.0 && r.is_finite() {
+        Ok(r)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "{} must be positive, got {}",
+            name, r
+        )))
+    }
+}
+
+/// An indexed triangle mesh given inline. `normals` and `uvs`, when present,
+/// hold one entry per position.
+#[derive(Deserialize)]
+#[serde(try_from = "MeshFields")]
+struct MeshEntry(ObjectEntry);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshFields {
+    positions: Vec<Tup>,
+    triangles: Vec<[usize; 3]>,
+    normals: Option<Vec<Tup>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    #[serde(default = "black")]
+    emission: TextureEntry,
+    color: TextureEntry,
+    material: MaterialEntry,
+}
+
+impl TryFrom<MeshFields> for MeshEntry {
+    type Error = String;
+
+    fn try_from(f: MeshFields) -> Result<Self, Self::Error> {
+        let mut mesh = Mesh::new(f.positions, f.triangles)?;
+        if let Some(normals) = f.normals {
+            mesh = mesh.with_normals(normals)?;
+        }
+        if let Some(uvs) = f.uvs {
+            mesh = mesh.with_uvs(uvs)?;
+        }
+        Ok(MeshEntry(ObjectEntry::new(
+            mesh, f.emission, f.color, f.material,
+        )))
+    }
+}
+
+/// An infinite plane through `point`.
+#[derive(Deserialize)]
+#[serde(try_from = "PlaneFields")]
+struct PlaneEntry(ObjectEntry);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlaneFields {
+    point: Tup,
+    normal: Tup,
+    #[serde(default = "black")]
+    emission: TextureEntry,
+    color: TextureEntry,
+    material: MaterialEntry,
+}
+
+impl TryFrom<PlaneFields> for PlaneEntry {
+    type Error = String;
+
+    fn try_from(f: PlaneFields) -> Result<Self, Self::Error> {
+        let shape = Plane::new(f.point, f.normal)?;
+        Ok(PlaneEntry(ObjectEntry::new(
+            shape, f.emission, f.color, f.material,
+        )))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "DiskFields")]
+struct DiskEntry(ObjectEntry);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiskFields {
+    center: Tup,
+    normal: Tup,
+    radius: f64,
+    #[serde(default = "black")]
+    emission: TextureEntry,
+    color: TextureEntry,
+    material: MaterialEntry,
+}
+
+impl TryFrom<DiskFields> for DiskEntry {
+    type Error = String;
+
+    fn try_from(f: DiskFields) -> Result<Self, Self::Error> {
+        let shape = Disk::new(f.center, f.normal, f.radius)?;
+        Ok(DiskEntry(ObjectEntry::new(
+            shape, f.emission, f.color, f.material,
+        )))
+    }
+}
+
+/// A parallelogram spanned by two `edges` from `corner`.
+#[derive(Deserialize)]
+#[serde(try_from = "QuadFields")]
+struct QuadEntry(ObjectEntry);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuadFields {
+    corner: Tup,
+    edges: [Tup; 2],
+    #[serde(default = "black")]
+    emission: TextureEntry,
+    color: TextureEntry,
+    material: MaterialEntry,
+}
+
+impl TryFrom<QuadFields> for QuadEntry {
+    type Error = String;
+
+    fn try_from(f: QuadFields) -> Result<Self, Self::Error> {
+        let shape = Quad::new(f.corner, f.edges[0], f.edges[1])?;
+        Ok(QuadEntry(ObjectEntry::new(
+            shape, f.emission, f.color, f.material,
+        )))
+    }
+}
+
+/// An axis-aligned box between the corners `min` and `max`.
+#[derive(Deserialize)]
+#[serde(try_from = "BoxFields")]
+struct BoxEntry(ObjectEntry);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoxFields {
+    min: Tup,
+    max: Tup,
+    #[serde(default = "black")]
+    emission: TextureEntry,
+    color: TextureEntry,
+    material: MaterialEntry,
+}
+
+impl TryFrom<BoxFields> for BoxEntry {
+    type Error = String;
+
+    fn try_from(f: BoxFields) -> Result<Self, Self::Error> {
+        let shape = Cuboid::new(f.min, f.max)?;
+        Ok(BoxEntry(ObjectEntry::new(
+            shape, f.emission, f.color, f.material,
+        )))
+    }
+}
+
+/// A Wavefront OBJ file, found relative to the scene file, scaled and then
+/// translated into place.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelEntry {
+    file: PathBuf,
+    #[serde(default = "one", deserialize_with = "scale")]
+    scale: f64,
+    #[serde(default = "Tup::zeros")]
+    translate: Tup,
+}
+
+fn one() -> f64 {
+    1.
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Obj(ObjError),
+    /// An image texture that could not be read.
+    Image {
+        path: PathBuf,
+        message: String,
+    },
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{}", e),
+            SceneError::Obj(e) => write!(f, "{}", e),
+            SceneError::Image { path, message } => write!(f, "{}: {}", path.display(), message),
+            SceneError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(e: ObjError) -> Self {
+        SceneError::Obj(e)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(e: serde_json::Error) -> Self {
+        // serde_json appends " at line X column Y" to its message; strip it since
+        // we report the position separately.
+        let message = e.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(i) => message[..i].to_string(),
+            None => message,
+        };
+        SceneError::Parse {
+            line: e.line(),
+            column: e.column(),
+            message,
+        }
+    }
+}
+
+impl Scene {
+    /// Parses a scene, finding the models it refers to relative to the
+    /// current directory.
+    pub fn from_json(source: &str) -> Result<Self, SceneError> {
+        Scene::parse(source, Path::new(""))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        Scene::parse(
+            &fs::read_to_string(path)?,
+            path.parent().unwrap_or(Path::new("")),
+        )
+    }
+
+    fn parse(source: &str, dir: &Path) -> Result<Self, SceneError> {
+        let file: SceneFile = serde_json::from_str(source)?;
+        // Images and models are part of the scene, so their files count
+        // towards the hash.
+        let mut hash = fnv1a(FNV_OFFSET, source.as_bytes());
+        let mut objects = file
+            .spheres
+            .into_iter()
+            .map(|s| {
+                ObjectEntry::new(
+                    Sphere::new(s.radius, s.position),
+                    s.emission,
+                    s.color,
+                    s.material,
+                )
+            })
+            .chain(file.planes.into_iter().map(|p| p.0))
+            .chain(file.disks.into_iter().map(|d| d.0))
+            .chain(file.quads.into_iter().map(|q| q.0))
+            .chain(file.boxes.into_iter().map(|b| b.0))
+            .chain(file.meshes.into_iter().map(|m| m.0))
+            .map(|entry| entry.load(dir, &mut hash))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut read = |path: &Path| {
+            let text = fs::read_to_string(path)?;
+            hash = fnv1a(hash, text.as_bytes());
+            Ok(text)
+        };
+        for model in file.models {
+            let path = dir.join(&model.file);
+            let source = read(&path).map_err(|error| ObjError::Io {
+                path: path.clone(),
+                error,
+            })?;
+            objects.extend(
+                obj::parse(&source, &path, &mut read)?
+                    .into_iter()
+                    .map(|part| {
+                        Part {
+                            mesh: part.mesh.place(model.scale, model.translate),
+                            ..part
+                        }
+                        .into_object()
+                    }),
+            );
+        }
+        Ok(Scene {
+            camera: file.camera,
+            world: World::from_objects(objects),
+            settings: file.settings,
+            hash,
+        })
+    }
+
+    pub fn cornell_box() -> Self {
+        Scene::from_json(CORNELL_BOX).expect("bundled scene is valid")
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// 64-bit FNV-1a, continuing from `hash`.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+impl World {
+    /// Loads only the objects of a scene file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        Scene::load(path).map(|scene| scene.world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Surface;
+
+    #[test]
+    fn bundled_scene_matches_world_new() {
+        let scene = Scene::cornell_box();
+        assert_eq!(
+            format!("{:?}", scene.world.objects),
+            format!("{:?}", World::new().objects)
+        );
+        assert_eq!(scene.camera.origin, Tup(50., 52., 295.6));
+        assert_eq!(scene.settings.width, NonZeroU32::new(640));
+        assert_eq!(fnv1a(FNV_OFFSET, b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(scene.hash, Scene::cornell_box().hash);
+        assert_ne!(
+            scene.hash,
+            Scene::from_json(&CORNELL_BOX.replace("295.6", "295.7"))
+                .unwrap()
+                .hash
+        );
+    }
+
+    #[test]
+    fn depth_of_field_scene_loads() {
+        let scene = Scene::from_json(include_str!("../scenes/cornell-dof.json")).unwrap();
+        assert_eq!(
+            format!("{:?}", scene.world.objects),
+            format!("{:?}", World::new().objects)
+        );
+        assert_eq!(scene.camera.aperture, 4.);
+        assert_eq!(scene.camera.focus_distance, 205.);
+    }
+
+    #[test]
+    fn minimal_scene() {
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "spheres": [
+                    { "radius": 1, "position": [0, 0, -5], "color": [1, 0, 0], "material": "DIFF" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(scene.camera.fov_scale, 0.5135);
+        assert_eq!(scene.settings, SceneSettings::default());
+        assert_eq!(
+            scene.world.objects[0]
+                .emission
+                .value((0., 0.), Tup::zeros()),
+            Tup::zeros()
+        );
+    }
+
+    #[test]
+    fn loads_meshes() {
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "meshes": [
+                    {
+                        "positions": [[-1, -1, -5], [1, -1, -5], [0, 1, -5]],
+                        "triangles": [[0, 1, 2]],
+                        "normals": [[0, 0, 1], [0, 0, 1], [0, 0, 1]],
+                        "color": [1, 1, 1],
+                        "material": "DIFF"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let hit = scene
+            .world
+            .intersect(&crate::ray::Ray {
+                o: Tup::zeros(),
+                d: Tup(0., 0., -1.),
+            })
+            .unwrap();
+        assert_eq!((hit.object, hit.t), (0, 5.));
+
+        let err = Scene::from_json(
+            r#"{"camera": {"origin": [0, 0, 0], "direction": [0, 0, -1]}, "spheres": [],
+"meshes": [{"positions": [[0, 0, 0]], "triangles": [[0, 0, 1]], "color": [1, 1, 1], "material": "DIFF"}]}"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().starts_with("line 2"), "{}", err);
+        assert!(err.to_string().contains("vertex index 1"), "{}", err);
+    }
+
+    #[test]
+    fn plane_scene_matches_world_cornell_planes() {
+        let scene = Scene::load("scenes/cornell-planes.json").unwrap();
+        assert_eq!(
+            format!("{:?}", scene.world.objects),
+            format!("{:?}", World::cornell_planes().objects)
+        );
+    }
+
+    #[test]
+    fn loads_primitives() {
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "disks": [
+                    { "center": [0, 0, -2], "normal": [0, 0, 1], "radius": 0.5, "color": [1, 1, 1], "material": "DIFF" }
+                ],
+                "quads": [
+                    { "corner": [-1, -1, -3], "edges": [[2, 0, 0], [0, 2, 0]], "color": [1, 1, 1], "material": "DIFF" }
+                ],
+                "boxes": [
+                    { "min": [-1, -1, -6], "max": [1, 1, -4], "color": [1, 1, 1], "material": "DIFF" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let first = |o: Tup| {
+            let hit = scene
+                .world
+                .intersect(&crate::ray::Ray {
+                    o,
+                    d: Tup(0., 0., -1.),
+                })
+                .unwrap();
+            (hit.object, hit.t)
+        };
+        assert_eq!(first(Tup::zeros()), (0, 2.));
+        assert_eq!(first(Tup(0.75, 0., 0.)), (1, 3.));
+        assert_eq!(first(Tup(0.75, 0., -3.5)), (2, 0.5));
+
+        let err = Scene::from_json(
+            r#"{"camera": {"origin": [0, 0, 0], "direction": [0, 0, -1]},
+"planes": [{"point": [0, 0, 0], "normal": [0, 0, 0], "color": [1, 1, 1], "material": "DIFF"}]}"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().starts_with("line 2"), "{}", err);
+        assert!(
+            err.to_string().contains("normal must not be zero"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn loads_obj_models() {
+        let scene = Scene::load("scenes/cornell-obj.json").unwrap();
+        // Eight spheres, then the cube and the gem.
+        assert_eq!(scene.world.objects.len(), 10);
+        assert_eq!(
+            scene.world.objects[8].color.value((0., 0.), Tup::zeros()),
+            Tup(0.75, 0.6, 0.3)
+        );
+        assert_eq!(
+            format!("{:?}", scene.world.objects[9].material),
+            format!("{:?}", Glass::default())
+        );
+        let hit = scene
+            .world
+            .intersect(&crate::ray::Ray {
+                o: Tup(70., 10., 150.),
+                d: Tup(0., 0., -1.),
+            })
+            .unwrap();
+        assert_eq!((hit.object, hit.t), (8, 54.));
+
+        let dir = std::env::temp_dir().join(format!("smallpt-models-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let scene_path = dir.join("scene.json");
+        fs::write(
+            &scene_path,
+            r#"{"camera": {"origin": [0, 0, 0], "direction": [0, 0, -1]},
+"models": [{"file": "model.obj"}]}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("model.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        let first = Scene::load(&scene_path).unwrap().hash;
+        fs::write(
+            dir.join("model.obj"),
+            "v 0 0 0\nv 2 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        let second = Scene::load(&scene_path).unwrap().hash;
+        fs::write(dir.join("model.obj"), "v 0 0 0\nf 1 2 3\n").unwrap();
+        let err = Scene::load(&scene_path).err().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_ne!(first, second);
+        assert!(
+            err.to_string()
+                .ends_with("model.obj, line 2: vertex index 2 is out of range, 1 defined so far"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let err = Scene::from_json(
+            "{\n  \"camera\": { \"origin\": [0, 0, 0], \"direction\": [0, 0, -1] },\n  \"spheres\": [\n    { \"radius\": 1, \"position\": [0, 0, -5], \"color\": [1, 0, 0], \"material\": \"GLOSSY\" }\n  ]\n}",
+        )
+        .err()
+        .unwrap();
+        match err {
+            SceneError::Parse { line, message, .. } => {
+                assert_eq!(line, 4);
+                assert!(message.contains("GLOSSY"), "{}", message);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn loads_material_parameters() {
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "spheres": [
+                    { "radius": 1, "position": [0, 0, -5], "color": [1, 1, 1],
+                      "material": { "type": "glass", "ior": "water", "absorption": [0.1, 0.2, 0.3] } },
+                    { "radius": 1, "position": [0, 0, -9], "color": [1, 1, 1],
+                      "material": { "type": "glass", "ior": 1.7 } },
+                    { "radius": 1, "position": [0, 0, -13], "color": [1, 1, 1],
+                      "material": { "type": "mirror" } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let materials: Vec<_> = scene
+            .world
+            .objects
+            .iter()
+            .map(|o| format!("{:?}", o.material))
+            .collect();
+        assert_eq!(
+            materials,
+            [
+                format!(
+                    "{:?}",
+                    Glass::new(1.333).with_absorption(Tup(0.1, 0.2, 0.3))
+                ),
+                format!("{:?}", Glass::new(1.7)),
+                format!("{:?}", Mirror),
+            ]
+        );
+        let scene = Scene::load("scenes/cornell-glass.json").unwrap();
+        assert_eq!(
+            format!("{:?}", scene.world.objects[7].material),
+            format!("{:?}", Glass::new(2.417))
+        );
+
+        for (material, message) in [
+            (r#""GLASS""#, "unknown material 'GLASS'"),
+            (
+                r#"{"type": "glass", "ior": "honey"}"#,
+                "unknown ior 'honey'",
+            ),
+            (r#"{"type": "glass", "ior": -1}"#, "ior must be positive"),
+            (
+                r#"{"type": "glass", "absorption": [0, -1, 0]}"#,
+                "absorption must not be negative",
+            ),
+            (r#"{"type": "diffuse", "ior": 2}"#, "unknown field `ior`"),
+        ] {
+            let err = Scene::from_json(&format!(
+                r#"{{"camera": {{"origin": [0, 0, 0], "direction": [0, 0, -1]}},
+"spheres": [{{"radius": 1, "position": [0, 0, 0], "color": [1, 1, 1], "material": {}}}]}}"#,
+                material
+            ))
+            .err()
+            .expect(material);
+            assert!(err.to_string().starts_with("line 2"), "{}", err);
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn loads_microfacet_materials() {
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "spheres": [
+                    { "radius": 1, "position": [0, 0, -5], "color": [1, 1, 1],
+                      "material": { "type": "conductor", "metal": "gold", "roughness": 0.3 } },
+                    { "radius": 1, "position": [0, 0, -9], "color": [1, 1, 1],
+                      "material": { "type": "conductor", "eta": [1, 2, 3], "k": [4, 5, 6],
+                                    "roughness": [0.1, 0.5] } },
+                    { "radius": 1, "position": [0, 0, -13], "color": [1, 1, 1],
+                      "material": { "type": "dielectric", "ior": "water", "roughness": 0.2,
+                                    "absorption": [0.1, 0.2, 0.3] } },
+                    { "radius": 1, "position": [0, 0, -17], "color": [1, 1, 1],
+                      "material": { "type": "dielectric" } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let materials: Vec<_> = scene
+            .world
+            .objects
+            .iter()
+            .map(|o| format!("{:?}", o.material))
+            .collect();
+        assert_eq!(
+            materials,
+            [
+                format!(
+                    "{:?}",
+                    Conductor::preset("gold")
+                        .unwrap()
+                        .with_roughness((0.3, 0.3))
+                ),
+                format!(
+                    "{:?}",
+                    Conductor::new(Tup(1., 2., 3.), Tup(4., 5., 6.)).with_roughness((0.1, 0.5))
+                ),
+                format!(
+                    "{:?}",
+                    Dielectric::new(1.333)
+                        .with_roughness((0.2, 0.2))
+                        .with_absorption(Tup(0.1, 0.2, 0.3))
+                ),
+                format!("{:?}", Dielectric::new(1.5)),
+            ]
+        );
+        Scene::load("scenes/cornell-metal.json").unwrap();
+
+        for (material, message) in [
+            (
+                r#"{"type": "conductor", "metal": "mithril"}"#,
+                "unknown metal 'mithril'",
+            ),
+            (
+                r#"{"type": "conductor", "eta": [1, 1, 1]}"#,
+                "needs either a `metal` or both `eta` and `k`",
+            ),
+            (
+                r#"{"type": "conductor", "metal": "gold", "k": [1, 1, 1]}"#,
+                "needs either a `metal` or both `eta` and `k`",
+            ),
+            (
+                r#"{"type": "dielectric", "roughness": 1.5}"#,
+                "roughness must be between 0 and 1",
+            ),
+            (
+                r#"{"type": "conductor", "metal": "gold", "roughness": [0.5, -0.1]}"#,
+                "roughness must be between 0 and 1",
+            ),
+        ] {
+            let err = Scene::from_json(&format!(
+                r#"{{"camera": {{"origin": [0, 0, 0], "direction": [0, 0, -1]}},
+"spheres": [{{"radius": 1, "position": [0, 0, 0], "color": [1, 1, 1], "material": {}}}]}}"#,
+                material
+            ))
+            .err()
+            .expect(material);
+            assert!(err.to_string().starts_with("line 2"), "{}", err);
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn loads_principled_materials() {
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "spheres": [
+                    { "radius": 1, "position": [0, 0, -5], "color": [1, 1, 1],
+                      "material": { "type": "principled", "metallic": 0.6, "clearcoat": 1,
+                                    "clearcoat_gloss": 0.9 } },
+                    { "radius": 1, "position": [0, 0, -9], "color": [1, 1, 1],
+                      "material": { "type": "principled", "roughness": 0.1, "sheen": 0.5,
+                                    "specular": 0.3, "transmission": 1, "ior": "water" } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let materials: Vec<_> = scene
+            .world
+            .objects
+            .iter()
+            .map(|o| format!("{:?}", o.material))
+            .collect();
+        assert_eq!(
+            materials,
+            [
+                format!(
+                    "{:?}",
+                    Principled {
+                        metallic: 0.6,
+                        clearcoat: 1.,
+                        clearcoat_gloss: 0.9,
+                        ..Default::default()
+                    }
+                ),
+                format!(
+                    "{:?}",
+                    Principled {
+                        roughness: 0.1,
+                        sheen: 0.5,
+                        specular: 0.3,
+                        transmission: 1.,
+                        ior: 1.333,
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        Scene::load("scenes/cornell-principled.json").unwrap();
+
+        for (material, message) in [
+            (
+                r#"{"type": "principled", "metallic": 2}"#,
+                "metallic must be between 0 and 1, got 2",
+            ),
+            (
+                r#"{"type": "principled", "clearcoat_gloss": -0.5}"#,
+                "clearcoat_gloss must be between 0 and 1, got -0.5",
+            ),
+            (
+                r#"{"type": "principled", "base_color": [1, 0, 0]}"#,
+                "unknown field `base_color`",
+            ),
+        ] {
+            let err = Scene::from_json(&format!(
+                r#"{{"camera": {{"origin": [0, 0, 0], "direction": [0, 0, -1]}},
+"spheres": [{{"radius": 1, "position": [0, 0, 0], "color": [1, 1, 1], "material": {}}}]}}"#,
+                material
+            ))
+            .err()
+            .expect(material);
+            assert!(err.to_string().starts_with("line 2"), "{}", err);
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    /// Whether two textures agree at a spread of texture coordinates and
+    /// positions.
+    fn same_texture(a: &dyn Texture, b: &dyn Texture) -> bool {
+        (0..50).all(|i| {
+            let t = i as f64 / 50.;
+            let uv = (t, (7. * t).fract());
+            let p = Tup(3. * t, -5. * t, (11. * t).sin());
+            a.value(uv, p) == b.value(uv, p)
+        })
+    }
+
+    #[test]
+    fn loads_textures() {
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "spheres": [
+                    { "radius": 1, "position": [0, 0, -5], "material": "DIFF",
+                      "color": { "type": "checker", "even": [1, 1, 1], "odd": [0, 0, 0], "scale": 8 } },
+                    { "radius": 1, "position": [0, 0, -9], "material": "DIFF", "color": [1, 1, 1],
+                      "emission": { "type": "gradient", "from": [0, 0, 0], "to": [4, 4, 4] } },
+                    { "radius": 1, "position": [0, 0, -13], "material": "DIFF",
+                      "color": { "type": "noise", "low": [0, 0, 0], "high": [1, 1, 1], "scale": 0.5 } },
+                    { "radius": 1, "position": [0, 0, -17], "material": "DIFF",
+                      "color": { "type": "marble", "low": [0, 0, 0], "high": [1, 1, 1], "scale": 0.2,
+                                 "octaves": 6 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let black = Tup::zeros();
+        let white = Tup::ones();
+        let expected: [(Box<dyn Texture>, Box<dyn Texture>); 4] = [
+            (
+                Box::new(Checker {
+                    even: white,
+                    odd: black,
+                    scale: 8.,
+                }),
+                Box::new(black),
+            ),
+            (
+                Box::new(white),
+                Box::new(Gradient {
+                    from: black,
+                    to: Tup(4., 4., 4.),
+                    axis: Axis::V,
+                }),
+            ),
+            (
+                Box::new(Noise {
+                    low: black,
+                    high: white,
+                    scale: 0.5,
+                    octaves: 4,
+                }),
+                Box::new(black),
+            ),
+            (
+                Box::new(Marble {
+                    low: black,
+                    high: white,
+                    scale: 0.2,
+                    octaves: 6,
+                }),
+                Box::new(black),
+            ),
+        ];
+        assert_eq!(scene.world.objects.len(), expected.len());
+        for (o, (color, emission)) in scene.world.objects.iter().zip(&expected) {
+            assert!(same_texture(&*o.color, &**color));
+            assert!(same_texture(&*o.emission, &**emission));
+        }
+        // Textured emission still makes a light.
+        assert_eq!(scene.world.lights, [1]);
+        Scene::load("scenes/cornell-textures.json").unwrap();
+
+        let dir = std::env::temp_dir().join(format!("smallpt-textures-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let scene_path = dir.join("scene.json");
+        fs::write(
+            &scene_path,
+            r#"{"camera": {"origin": [0, 0, 0], "direction": [0, 0, -1]},
+"spheres": [{"radius": 1, "position": [0, 0, 0], "material": "DIFF",
+             "color": {"type": "image", "file": "wood.pfm", "wrap": "mirror"}}]}"#,
+        )
+        .unwrap();
+        let mut image = crate::image::Image::new(2, 1);
+        image.pixels[1] = Tup(0.5, 0.25, 1.);
+        image.save(dir.join("wood.pfm")).unwrap();
+        let first = Scene::load(&scene_path).unwrap();
+        assert!(same_texture(
+            &*first.world.objects[0].color,
+            &ImageTexture::new(image, Wrap::Mirror).unwrap()
+        ));
+        crate::image::Image::new(2, 1)
+            .save(dir.join("wood.pfm"))
+            .unwrap();
+        let second = Scene::load(&scene_path).unwrap();
+        fs::remove_file(dir.join("wood.pfm")).unwrap();
+        let err = Scene::load(&scene_path).err().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_ne!(first.hash, second.hash);
+        assert!(
+            matches!(&err, SceneError::Image { path, .. } if path == &dir.join("wood.pfm")),
+            "{}",
+            err
+        );
+
+        for (color, message) in [
+            (
+                r#"{"type": "checker", "even": [1, 1, 1], "odd": [0, 0, 0], "scale": 0}"#,
+                "scale must be positive",
+            ),
+            (
+                r#"{"type": "image", "file": "wood.png", "wrap": "tile"}"#,
+                "unknown variant `tile`",
+            ),
+            (r#"{"type": "bricks"}"#, "unknown variant `bricks`"),
+            (r#"[1, 1]"#, "invalid length 2"),
+        ] {
+            let err = Scene::from_json(&format!(
+                r#"{{"camera": {{"origin": [0, 0, 0], "direction": [0, 0, -1]}},
+"spheres": [{{"radius": 1, "position": [0, 0, 0], "material": "DIFF", "color": {}}}]}}"#,
+                color
+            ))
+            .err()
+            .expect(color);
+            assert!(err.to_string().starts_with("line 2"), "{}", err);
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn loads_textured_material_parameters() {
+        let scene = Scene::from_json(
+            r#"{
+                "camera": { "origin": [0, 0, 0], "direction": [0, 0, -1] },
+                "spheres": [
+                    { "radius": 1, "position": [0, 0, -5], "color": [1, 1, 1],
+                      "material": { "type": "principled", "metallic": 1,
+                                    "roughness": { "type": "checker", "even": 0.25, "odd": 0.75,
+                                                   "scale": 2 } } },
+                    { "radius": 1, "position": [0, 0, -9], "color": [1, 1, 1],
+                      "material": { "type": "dielectric", "ior": "diamond",
+                                    "roughness": { "type": "gradient", "from": 0, "to": 1,
+                                                   "axis": "u" },
+                                    "absorption": { "type": "gradient", "from": [0, 0, 0],
+                                                    "to": [0, 1, 2], "axis": "u" } } },
+                    { "radius": 1, "position": [0, 0, -13], "color": [1, 1, 1],
+                      "material": { "type": "conductor", "metal": "gold", "roughness": 0.3 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let surface = |uv| Surface {
+            n: Tup(0., 0., 1.),
+            n1: Tup(0., 0., 1.),
+            color: Tup::ones(),
+            point: Tup::zeros(),
+            uv,
+        };
+        let (wo, wi) = (Tup(0.6, 0., 0.8), Tup(-0.48, 0.36, 0.8));
+        let principled = scene.world.objects[0].material.as_ref();
+        for (uv, roughness) in [((0.25, 0.25), 0.25), ((0.75, 0.25), 0.75)] {
+            let s = surface(uv);
+            let expected = Principled {
+                metallic: 1.,
+                roughness,
+                ..Default::default()
+            };
+            assert_eq!(principled.eval(&s, wo, wi), expected.eval(&s, wo, wi));
+            assert_eq!(principled.pdf(&s, wo, wi), expected.pdf(&s, wo, wi));
+        }
+
+        let dielectric = scene.world.objects[1].material.as_ref();
+        let s = surface((0.5, 0.));
+        let expected = Dielectric::new(2.417)
+            .with_roughness((0.5, 0.5))
+            .with_absorption(Tup(0., 0.5, 1.));
+        assert_eq!(dielectric.eval(&s, wo, wi), expected.eval(&s, wo, wi));
+        assert_eq!(
+            dielectric.transmittance(&s, 2.),
+            expected.transmittance(&s, 2.)
+        );
+        // Materials without textures are made once.
+        assert_eq!(
+            format!("{:?}", scene.world.objects[2].material),
+            format!(
+                "{:?}",
+                Conductor::preset("gold")
+                    .unwrap()
+                    .with_roughness((0.3, 0.3))
+            )
+        );
+
+        for (material, message) in [
+            (
+                r#"{"type": "principled", "sheen": {"type": "bricks"}}"#,
+                "unknown variant `bricks`",
+            ),
+            (
+                r#"{"type": "glass", "ior": {"type": "noise", "low": 1, "high": 2}}"#,
+                "missing field `scale`",
+            ),
+            (
+                r#"{"type": "dielectric", "roughness": [0.1, 2]}"#,
+                "roughness must be",
+            ),
+        ] {
+            let err = Scene::from_json(&format!(
+                r#"{{"camera": {{"origin": [0, 0, 0], "direction": [0, 0, -1]}},
+"spheres": [{{"radius": 1, "position": [0, 0, 0], "color": [1, 1, 1], "material": {}}}]}}"#,
+                material
+            ))
+            .err()
+            .expect(material);
+            assert!(err.to_string().starts_with("line 2"), "{}", err);
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn rejects_negative_radius() {
+        let err = Scene::from_json(
+            r#"{"camera": {"origin": [0, 0, 0], "direction": [0, 0, -1]},
+"spheres": [{"radius": -1, "position": [0, 0, 0], "color": [1, 1, 1], "material": "DIFF"}]}"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().starts_with("line 2"), "{}", err);
+        assert!(err.to_string().contains("radius must be positive"), "{}", err);
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt::Debug;
+
+use super::bvh::Aabb;
+use super::ray::Ray;
+use super::tup::Tup;
+
+/// Distance below which intersections are ignored, so that rays leaving a
+/// surface do not hit it again straight away.
+pub const EPS: f64 = 1e-4;
+
+/// The cheap part of an intersection: enough to find the closest one, with the
+/// surface data left for [`Shape::hit`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Intersection {
+    pub t: f64,
+    /// Which part of the shape was hit, such as the triangle of a mesh.
+    pub primitive: usize,
+    /// Coordinates within the primitive, such as barycentrics on a triangle.
+    pub b: (f64, f64),
+}
+
+/// Surface data at a ray hit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub t: f64,
+    pub point: Tup,
+    /// Unit geometric normal. It points out of closed shapes and to the front
+    /// of open ones, whichever side the ray came from.
+    pub normal: Tup,
+    /// Unit normal used for shading, on the same side as `normal`.
+    pub shading_normal: Tup,
+    pub uv: (f64, f64),
+    /// Index into `World::objects`.
+    pub object: usize,
+    /// Index of the primitive within the object, such as the triangle of a mesh.
+    pub primitive: usize,
+}
+
+/// Geometry that rays can hit.
+pub trait Shape: Debug + Send + Sync {
+    fn bounds(&self) -> Aabb;
+
+    /// The closest intersection along `ray` nearer than `t_max`.
+    fn intersect(&self, ray: &Ray, t_max: f64) -> Option<Intersection>;
+
+    /// Surface data for an intersection returned by [`Shape::intersect`]. The
+    /// object index is left at zero for the caller to fill in.
+    fn hit(&self, ray: &Ray, i: &Intersection) -> Hit;
+
+    /// Samples a direction from `x` towards the shape, returning it with its
+    /// solid angle pdf. Shapes that cannot be sampled return `None`.
+    fn sample_toward(&self, _x: Tup, _u: (f64, f64)) -> Option<(Tup, f64)> {
+        None
+    }
+
+    /// The solid angle pdf of `sample_toward` from `x` for a direction `d`
+    /// that hits the shape.
+    fn pdf_toward(&self, _x: Tup, _d: Tup) -> f64 {
+        0.
+    }
+
+    /// Whether `sample_toward` gives directions from `x`.
+    fn samples_from(&self, _x: Tup) -> bool {
+        false
+    }
+}
@@ -0,0 +1,135 @@
+use super::sampler::{hash, mix_bits, permute_index, Sampler, ONE_MINUS_EPSILON};
+
+/// Generator matrix of the second Sobol dimension, one column per index bit.
+/// The first dimension is the van der Corput sequence.
+const SOBOL_1: [u32; 32] = {
+    let mut m = [0u32; 32];
+    m[0] = 1 << 31;
+    let mut i = 1;
+    while i < 32 {
+        m[i] = m[i - 1] ^ (m[i - 1] >> 1);
+        i += 1;
+    }
+    m
+};
+
+fn sobol_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+fn sobol_1(mut index: u32) -> u32 {
+    let mut v = 0;
+    let mut i = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            v ^= SOBOL_1[i];
+        }
+        index >>= 1;
+        i += 1;
+    }
+    v
+}
+
+/// Nested uniform (Owen) scrambling: each bit is flipped depending on a hash
+/// of the bits above it.
+fn owen_scramble(mut v: u32, seed: u32) -> u32 {
+    if seed & 1 != 0 {
+        v ^= 1 << 31;
+    }
+    for b in 1..32 {
+        let mask = !0u32 << (32 - b);
+        if (mix_bits(u64::from((v & mask) ^ seed)) as u32) & (1 << b) != 0 {
+            v ^= 1 << (31 - b);
+        }
+    }
+    v
+}
+
+fn to_float(v: u32) -> f64 {
+    (v as f64 * (1. / 4294967296.)).min(ONE_MINUS_EPSILON)
+}
+
+/// Owen-scrambled Sobol points, padded: every dimension (or pair of dimensions
+/// for `next_2d`) uses the first two Sobol dimensions with its own scrambling
+/// and its own shuffle of the sample indices. The first `spp` samples of a
+/// pixel therefore form a scrambled (0, 2)-net in every pair when `spp` is a
+/// power of two.
+pub struct SobolSampler {
+    seed: u64,
+    spp: u32,
+    pixel: u64,
+    index: u64,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64, spp: u32) -> Self {
+        SobolSampler {
+            seed,
+            spp: spp.max(1),
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Hash and shuffled sample index for the current dimension.
+    fn start_dimension(&mut self, count: u32) -> (u64, u32) {
+        let h = hash(&[self.seed, self.pixel, u64::from(self.dimension)]);
+        self.dimension += count;
+        (h, permute_index(self.index, self.spp, h) as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn next(&mut self) -> f64 {
+        let (h, i) = self.start_dimension(1);
+        to_float(owen_scramble(sobol_0(i), (h >> 32) as u32))
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let (h, i) = self.start_dimension(2);
+        (
+            to_float(owen_scramble(sobol_0(i), (h >> 32) as u32)),
+            to_float(owen_scramble(sobol_1(i), h as u32)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::tests::points;
+
+    #[test]
+    fn second_dimension_matches_known_values() {
+        let v: Vec<f64> = (0..4).map(|i| to_float(sobol_1(i))).collect();
+        assert_eq!(v, vec![0., 0.5, 0.75, 0.25]);
+    }
+
+    #[test]
+    fn scrambled_points_form_a_net() {
+        // Every 2^a x 2^b grid with a + b = 4 holds one of the 16 points.
+        let mut sampler = SobolSampler::new(6, 16);
+        let pts = points(&mut sampler, 16, 10);
+        for a in 0..=4 {
+            let (nx, ny) = (1u32 << a, 1u32 << (4 - a));
+            let mut cells: Vec<u32> = pts
+                .iter()
+                .map(|&(x, y)| (y * ny as f64) as u32 * nx + (x * nx as f64) as u32)
+                .collect();
+            cells.sort();
+            assert_eq!(cells, (0..16).collect::<Vec<_>>(), "{} x {}", nx, ny);
+        }
+    }
+}
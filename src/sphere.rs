@@ -1,29 +1,23 @@
+use std::f64::consts::PI;
+
+use super::bvh::Aabb;
 use super::ray::Ray;
+use super::shape::{Hit, Intersection, Shape, EPS};
 use super::tup::Tup;
 
-#[derive(Debug, PartialEq)]
-pub enum RflType {
-    DIFF,
-    SPEC,
-    REFR,
-}
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Sphere {
     pub r: f64,
     pub p: Tup,
-    pub e: Tup,
-    pub c: Tup,
-    pub rfl: RflType,
 }
 
 impl Sphere {
-    pub fn new(r: f64, p: Tup, e: Tup, c: Tup, rfl: RflType) -> Self {
-        Sphere { r, p, e, c, rfl }
+    pub fn new(r: f64, p: Tup) -> Self {
+        Sphere { r, p }
     }
 
-    pub fn intersect(&self, ray: &Ray) -> f64 {
-        let eps = 1e-4;
+    /// Distance to the first intersection in front of the ray, or 0 for a miss.
+    pub fn distance(&self, ray: &Ray) -> f64 {
         let op = self.p - ray.o;
         let b = op.dot(ray.d);
         let det = b * b - op.dot(op) + self.r * self.r;
@@ -34,18 +28,117 @@ impl Sphere {
         let det_sqrt = det.sqrt();
         let mut t = b - det_sqrt;
 
-        if t > eps {
+        if t > EPS {
             return t;
         }
 
         t = b + det_sqrt;
 
-        if t > eps {
+        if t > EPS {
             return t;
         }
 
         0.0
     }
+
+    /// Whether `x` lies inside or on the sphere.
+    pub fn contains(&self, x: Tup) -> bool {
+        let d = self.p - x;
+        d.dot(d) <= self.r * self.r
+    }
+
+    /// Samples a direction from `x` towards the sphere, uniformly over the cone
+    /// of directions it subtends. Returns the direction and its solid angle
+    /// pdf, or `None` when `x` is inside the sphere.
+    pub fn sample_cone(&self, x: Tup, u: (f64, f64)) -> Option<(Tup, f64)> {
+        if self.contains(x) {
+            return None;
+        }
+        let to_center = self.p - x;
+        let sin2_max = self.r * self.r / to_center.dot(to_center);
+        let one_minus_cos_max = one_minus_cos_max(sin2_max);
+
+        let cos_theta = 1.0 - u.0 * one_minus_cos_max;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+
+        let w = to_center.norm();
+        let (u_axis, v_axis) = w.basis();
+        let d =
+            (u_axis * (phi.cos() * sin_theta) + v_axis * (phi.sin() * sin_theta) + w * cos_theta)
+                .norm();
+        Some((d, 1.0 / (2.0 * PI * one_minus_cos_max)))
+    }
+
+    /// The solid angle pdf of `sample_cone` from `x` for any direction that
+    /// hits the sphere.
+    pub fn cone_pdf(&self, x: Tup) -> f64 {
+        let to_center = self.p - x;
+        let sin2_max = self.r * self.r / to_center.dot(to_center);
+        1.0 / (2.0 * PI * one_minus_cos_max(sin2_max))
+    }
+}
+
+impl Shape for Sphere {
+    /// Bounds padded slightly so that hits which rounding places just outside
+    /// the sphere are still inside the box.
+    fn bounds(&self) -> Aabb {
+        let scale = self.r + self.p.0.abs().max(self.p.1.abs()).max(self.p.2.abs());
+        let r = self.r + scale * 1e-7;
+        Aabb {
+            min: self.p - Tup(r, r, r),
+            max: self.p + Tup(r, r, r),
+        }
+    }
+
+    fn intersect(&self, ray: &Ray, t_max: f64) -> Option<Intersection> {
+        let t = self.distance(ray);
+        (t != 0.0 && t < t_max).then_some(Intersection {
+            t,
+            primitive: 0,
+            b: (0., 0.),
+        })
+    }
+
+    /// The uv coordinates are longitude and latitude: u goes once around the
+    /// y axis starting from +x, and v runs from the bottom pole to the top.
+    fn hit(&self, ray: &Ray, i: &Intersection) -> Hit {
+        let point = ray.o + ray.d * i.t;
+        let normal = (point - self.p).norm();
+        let phi = normal.2.atan2(normal.0);
+        let u = if phi < 0. {
+            phi / (2. * PI) + 1.
+        } else {
+            phi / (2. * PI)
+        };
+        let v = (-normal.1).clamp(-1., 1.).acos() / PI;
+        Hit {
+            t: i.t,
+            point,
+            normal,
+            shading_normal: normal,
+            uv: (u, v),
+            object: 0,
+            primitive: 0,
+        }
+    }
+
+    fn sample_toward(&self, x: Tup, u: (f64, f64)) -> Option<(Tup, f64)> {
+        self.sample_cone(x, u)
+    }
+
+    fn pdf_toward(&self, x: Tup, _d: Tup) -> f64 {
+        self.cone_pdf(x)
+    }
+
+    fn samples_from(&self, x: Tup) -> bool {
+        !self.contains(x)
+    }
+}
+
+/// 1 - cos(theta_max), written to avoid cancellation for small or distant spheres.
+fn one_minus_cos_max(sin2_max: f64) -> f64 {
+    sin2_max / (1.0 + (1.0 - sin2_max).sqrt())
 }
 
 #[cfg(test)]
@@ -56,69 +149,78 @@ mod tests {
     fn a_sphere() {
         let r = 1.0;
         let p = Tup(1.0, 2.0, 3.0);
-        let e = Tup(1.0, 1.0, 1.0);
-        let c = Tup(1.0, 1.0, 1.0);
-
-        let sphere = Sphere {
-            r,
-            p,
-            e,
-            c,
-            rfl: RflType::DIFF,
-        };
+
+        let sphere = Sphere { r, p };
 
         assert_eq!(sphere.r, r);
         assert_eq!(sphere.p, p);
-        assert_eq!(sphere.e, e);
-        assert_eq!(sphere.c, c);
-        assert_eq!(sphere.rfl, RflType::DIFF);
     }
 
     #[test]
     fn ray_intersects_sphere() {
-        let r = 1.0;
-        let p = Tup(0.0, 0.0, 0.0);
-        let e = Tup(1.0, 1.0, 1.0);
-        let c = Tup(1.0, 1.0, 1.0);
-
-        let sphere = Sphere {
-            r,
-            p,
-            e,
-            c,
-            rfl: RflType::DIFF,
-        };
+        let sphere = Sphere::new(1.0, Tup(0.0, 0.0, 0.0));
 
         let ray = Ray {
             o: Tup(0.0, 0.0, -5.0),
             d: Tup(0.0, 0.0, 1.0),
         };
 
-        let xs = sphere.intersect(&ray);
+        let xs = sphere.distance(&ray);
         assert_eq!(xs, 4.0);
+        assert_eq!(sphere.intersect(&ray, f64::INFINITY).unwrap().t, 4.0);
+        assert!(sphere.intersect(&ray, 4.0).is_none());
     }
 
     #[test]
     fn ray_misses_sphere() {
-        let r = 1.0;
-        let p = Tup(0.0, 0.0, 0.0);
-        let e = Tup(1.0, 1.0, 1.0);
-        let c = Tup(1.0, 1.0, 1.0);
-
-        let sphere = Sphere {
-            r,
-            p,
-            e,
-            c,
-            rfl: RflType::DIFF,
-        };
+        let sphere = Sphere::new(1.0, Tup(0.0, 0.0, 0.0));
 
         let ray = Ray {
             o: Tup(0.0, 2.0, -5.0),
             d: Tup(0.0, 0.0, 1.0),
         };
 
-        let xs = sphere.intersect(&ray);
+        let xs = sphere.distance(&ray);
         assert_eq!(xs, 0.0);
+        assert!(sphere.intersect(&ray, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn hits_carry_normals_and_uvs() {
+        let sphere = Sphere::new(2.0, Tup(1.0, 0.0, 0.0));
+        let uv = |o: Tup, d: Tup| {
+            let ray = Ray { o, d };
+            let hit = sphere.hit(&ray, &sphere.intersect(&ray, f64::INFINITY).unwrap());
+            assert!(
+                (sphere.p + hit.normal * 2. - hit.point)
+                    .dot(Tup::ones())
+                    .abs()
+                    < 1e-12
+            );
+            assert_eq!(hit.normal, hit.shading_normal);
+            hit.uv
+        };
+        assert_eq!(uv(Tup(10., 0., 0.), Tup(-1., 0., 0.)), (0., 0.5));
+        assert_eq!(uv(Tup(1., 0., 10.), Tup(0., 0., -1.)), (0.25, 0.5));
+        assert_eq!(uv(Tup(1., -10., 0.), Tup(0., 1., 0.)), (0., 0.));
+        let (u, v) = uv(Tup(1., 10., 0.), Tup(0., -1., 0.));
+        assert_eq!(v, 1.);
+        assert!((0. ..1.).contains(&u));
+    }
+
+    #[test]
+    fn cone_samples_hit_the_sphere() {
+        let sphere = Sphere::new(2.0, Tup(0., 10., 0.));
+        let x = Tup(1., 0., 0.5);
+        for i in 0..100 {
+            let u = (i as f64 / 100., (i * 37 % 100) as f64 / 100.);
+            let (d, pdf) = sphere.sample_cone(x, u).unwrap();
+            assert!(sphere.distance(&Ray { o: x, d }) > 0.);
+            let dist2 = (sphere.p - x).dot(sphere.p - x);
+            let cos_max = (1. - 4. / dist2).sqrt();
+            assert!((pdf - 1. / (2. * PI * (1. - cos_max))).abs() < 1e-9 * pdf);
+            assert_eq!(pdf, sphere.cone_pdf(x));
+        }
+        assert!(sphere.sample_cone(Tup(0., 9., 0.), (0.5, 0.5)).is_none());
     }
 }
@@ -0,0 +1,90 @@
+use super::sampler::{hash, hash_float, permute_index, Sampler, ONE_MINUS_EPSILON};
+
+/// Jittered stratified sampling. Each 1D dimension is split into `spp` strata
+/// and each 2D pair into a grid of about `spp` cells. Samples of a pixel visit
+/// the strata in a random order that differs per dimension, and are jittered
+/// uniformly within them.
+pub struct StratifiedSampler {
+    seed: u64,
+    spp: u32,
+    nx: u32,
+    ny: u32,
+    pixel: u64,
+    index: u64,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, spp: u32) -> Self {
+        let spp = spp.max(1);
+        let nx = (spp as f64).sqrt().ceil() as u32;
+        let ny = spp.div_ceil(nx);
+        StratifiedSampler {
+            seed,
+            spp,
+            nx,
+            ny,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn stratum(&self, strata: u32) -> u32 {
+        let h = hash(&[self.seed, self.pixel, u64::from(self.dimension)]);
+        (permute_index(self.index, strata, h) % u64::from(strata)) as u32
+    }
+
+    fn jitter(&self, offset: u32) -> f64 {
+        hash_float(&[
+            self.seed,
+            self.pixel,
+            self.index,
+            u64::from(self.dimension + offset),
+        ])
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn next(&mut self) -> f64 {
+        let s = self.stratum(self.spp);
+        let v = (s as f64 + self.jitter(0)) / self.spp as f64;
+        self.dimension += 1;
+        v.min(ONE_MINUS_EPSILON)
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let s = self.stratum(self.nx * self.ny);
+        let x = ((s % self.nx) as f64 + self.jitter(0)) / self.nx as f64;
+        let y = ((s / self.nx) as f64 + self.jitter(1)) / self.ny as f64;
+        self.dimension += 2;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::tests::points;
+
+    #[test]
+    fn one_sample_per_cell() {
+        let mut sampler = StratifiedSampler::new(2, 16);
+        let mut cells: Vec<u32> = points(&mut sampler, 16, 4)
+            .into_iter()
+            .map(|(x, y)| (y * 4.) as u32 * 4 + (x * 4.) as u32)
+            .collect();
+        cells.sort();
+        assert_eq!(cells, (0..16).collect::<Vec<_>>());
+    }
+}
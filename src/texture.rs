@@ -0,0 +1,389 @@
+use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::image::Image;
+use super::sampler::hash;
+use super::tup::Tup;
+
+/// A color that varies over a surface, looked up by the texture coordinates
+/// and position of a hit.
+pub trait Texture: Debug + Send + Sync {
+    /// The value at texture coordinates `uv` of the point `p`.
+    fn value(&self, uv: (f64, f64), p: Tup) -> Tup;
+
+    /// Whether the value is zero everywhere. Objects are lights unless their
+    /// emission is.
+    fn is_black(&self) -> bool {
+        false
+    }
+}
+
+/// A color is the same everywhere.
+impl Texture for Tup {
+    fn value(&self, _uv: (f64, f64), _p: Tup) -> Tup {
+        *self
+    }
+
+    fn is_black(&self) -> bool {
+        *self == Tup::zeros()
+    }
+}
+
+impl<T: Texture + 'static> From<T> for Box<dyn Texture> {
+    fn from(t: T) -> Self {
+        Box::new(t)
+    }
+}
+
+fn lerp(t: f64, a: Tup, b: Tup) -> Tup {
+    a * (1. - t) + b * t
+}
+
+/// Squares of two colors alternating in texture space, `scale` of them per
+/// unit of u and of v.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Checker {
+    pub even: Tup,
+    pub odd: Tup,
+    pub scale: f64,
+}
+
+impl Texture for Checker {
+    fn value(&self, uv: (f64, f64), _p: Tup) -> Tup {
+        let square = (uv.0 * self.scale).floor() + (uv.1 * self.scale).floor();
+        if square.rem_euclid(2.) == 0. {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// A texture coordinate.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    U,
+    V,
+}
+
+/// A linear blend from `from` at 0 to `to` at 1 along one texture
+/// coordinate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gradient {
+    pub from: Tup,
+    pub to: Tup,
+    pub axis: Axis,
+}
+
+impl Texture for Gradient {
+    fn value(&self, uv: (f64, f64), _p: Tup) -> Tup {
+        let t = match self.axis {
+            Axis::U => uv.0,
+            Axis::V => uv.1,
+        };
+        lerp(t.clamp(0., 1.), self.from, self.to)
+    }
+}
+
+/// Fractal noise in space between the colors `low` and `high`: `octaves`
+/// layers of Perlin noise, each at twice the frequency and half the amplitude
+/// of the last, with features about `1 / scale` units apart in the first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Noise {
+    pub low: Tup,
+    pub high: Tup,
+    pub scale: f64,
+    pub octaves: u32,
+}
+
+impl Texture for Noise {
+    fn value(&self, _uv: (f64, f64), p: Tup) -> Tup {
+        let n = fbm(p * self.scale, self.octaves);
+        lerp((0.5 * (1. + n)).clamp(0., 1.), self.low, self.high)
+    }
+}
+
+/// Veins of `high` through `low`: stripes across the x axis, `scale` radians
+/// per unit, bent by turbulence of `octaves` layers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Marble {
+    pub low: Tup,
+    pub high: Tup,
+    pub scale: f64,
+    pub octaves: u32,
+}
+
+impl Texture for Marble {
+    fn value(&self, _uv: (f64, f64), p: Tup) -> Tup {
+        let p = p * self.scale;
+        let t = 0.5 * (1. + (p.0 + 10. * turbulence(p, self.octaves)).sin());
+        lerp(t, self.low, self.high)
+    }
+}
+
+/// What image textures show outside the unit square of texture space.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    /// The image tiles the plane.
+    #[default]
+    Repeat,
+    /// The edge texels stretch out.
+    Clamp,
+    /// The image tiles the plane, flipped every other time.
+    Mirror,
+}
+
+impl Wrap {
+    /// The texel for index `i` along an axis of `n` texels.
+    fn index(self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::Clamp => i.clamp(0, n - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                if i < n {
+                    i
+                } else {
+                    2 * n - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+/// An image stretched over the unit square of texture space, with its top
+/// at v = 1, and filtered bilinearly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageTexture {
+    image: Image,
+    wrap: Wrap,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image, wrap: Wrap) -> Result<Self, String> {
+        if image.width == 0 || image.height == 0 {
+            return Err("texture image is empty".to_string());
+        }
+        Ok(ImageTexture { image, wrap })
+    }
+
+    /// Reads a PPM, PNG or PFM file; see [`Image::load`].
+    pub fn load(path: impl AsRef<Path>, wrap: Wrap) -> io::Result<Self> {
+        ImageTexture::new(Image::load(path)?, wrap)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Tup {
+        self.image.get(
+            self.wrap.index(x, self.image.width),
+            self.wrap.index(y, self.image.height),
+        )
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, uv: (f64, f64), _p: Tup) -> Tup {
+        // Texel centers sit at half-integer coordinates.
+        let x = uv.0 * self.image.width as f64 - 0.5;
+        let y = (1. - uv.1) * self.image.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        lerp(
+            ty,
+            lerp(tx, self.texel(x0, y0), self.texel(x0 + 1, y0)),
+            lerp(tx, self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1)),
+        )
+    }
+}
+
+/// Gradient directions of Perlin noise: the edges of a cube.
+const GRADIENTS: [Tup; 12] = [
+    Tup(1., 1., 0.),
+    Tup(-1., 1., 0.),
+    Tup(1., -1., 0.),
+    Tup(-1., -1., 0.),
+    Tup(1., 0., 1.),
+    Tup(-1., 0., 1.),
+    Tup(1., 0., -1.),
+    Tup(-1., 0., -1.),
+    Tup(0., 1., 1.),
+    Tup(0., -1., 1.),
+    Tup(0., 1., -1.),
+    Tup(0., -1., -1.),
+];
+
+/// Ken Perlin's improved gradient noise, roughly between -1 and 1 and zero
+/// at integer points. Gradients are picked by hashing the lattice points
+/// rather than from a permutation table, so the noise never repeats.
+pub fn perlin(p: Tup) -> f64 {
+    let cell = Tup(p.0.floor(), p.1.floor(), p.2.floor());
+    let f = p - cell;
+    let corner = |i: i64, j: i64, k: i64| {
+        let lattice = [
+            (cell.0 as i64 + i) as u64,
+            (cell.1 as i64 + j) as u64,
+            (cell.2 as i64 + k) as u64,
+        ];
+        let g = GRADIENTS[(hash(&lattice) % 12) as usize];
+        g.dot(f - Tup(i as f64, j as f64, k as f64))
+    };
+    let fade = |t: f64| t * t * t * (t * (t * 6. - 15.) + 10.);
+    let mix = |t: f64, a: f64, b: f64| a + fade(t) * (b - a);
+    let plane = |k| {
+        mix(
+            f.1,
+            mix(f.0, corner(0, 0, k), corner(1, 0, k)),
+            mix(f.0, corner(0, 1, k), corner(1, 1, k)),
+        )
+    };
+    mix(f.2, plane(0), plane(1))
+}
+
+/// Octaves of Perlin noise, normalized to the range of one.
+pub fn fbm(p: Tup, octaves: u32) -> f64 {
+    let (mut sum, mut total, mut amplitude, mut p) = (0., 0., 1., p);
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * perlin(p);
+        total += amplitude;
+        amplitude *= 0.5;
+        p = p * 2.;
+    }
+    sum / total
+}
+
+/// Like [`fbm`], but adding the magnitude of each octave, which gives
+/// sharp creases where the noise crosses zero.
+pub fn turbulence(p: Tup, octaves: u32) -> f64 {
+    let (mut sum, mut amplitude, mut p) = (0., 1., p);
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * perlin(p).abs();
+        amplitude *= 0.5;
+        p = p * 2.;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const RED: Tup = Tup(1., 0., 0.);
+    const BLUE: Tup = Tup(0., 0., 1.);
+
+    #[test]
+    fn checker_alternates() {
+        let checker = Checker {
+            even: RED,
+            odd: BLUE,
+            scale: 4.,
+        };
+        let at = |u, v| checker.value((u, v), Tup::zeros());
+        assert_eq!(at(0.1, 0.1), RED);
+        assert_eq!(at(0.3, 0.1), BLUE);
+        assert_eq!(at(0.3, 0.3), RED);
+        assert_eq!(at(-0.1, 0.1), BLUE);
+    }
+
+    #[test]
+    fn gradient_blends_along_its_axis() {
+        let gradient = Gradient {
+            from: RED,
+            to: BLUE,
+            axis: Axis::V,
+        };
+        assert_eq!(gradient.value((0.7, 0.), Tup::zeros()), RED);
+        assert_eq!(
+            gradient.value((0.7, 0.25), Tup::zeros()),
+            Tup(0.75, 0., 0.25)
+        );
+        assert_eq!(gradient.value((0., 2.), Tup::zeros()), BLUE);
+    }
+
+    #[test]
+    fn perlin_noise_is_smooth_and_bounded() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(perlin(Tup(3., -2., 7.)), 0.);
+        let (mut min, mut max) = (0f64, 0f64);
+        for _ in 0..10_000 {
+            let p = Tup(rng.gen(), rng.gen(), rng.gen()) * 20. - Tup(10., 10., 10.);
+            let n = perlin(p);
+            assert!(n.abs() <= 1.1, "{}", n);
+            (min, max) = (min.min(n), max.max(n));
+            let step = perlin(p + Tup(1e-6, -1e-6, 1e-6)) - n;
+            assert!(step.abs() < 1e-5, "{}", step);
+            assert!(fbm(p, 5).abs() <= 1.1);
+        }
+        // Spread over most of the range, not stuck near zero.
+        assert!(min < -0.6 && max > 0.6, "{} {}", min, max);
+    }
+
+    #[test]
+    fn procedural_textures_stay_between_their_colors() {
+        let noise = Noise {
+            low: RED,
+            high: BLUE,
+            scale: 0.3,
+            octaves: 4,
+        };
+        let marble = Marble {
+            low: RED,
+            high: BLUE,
+            scale: 0.3,
+            octaves: 4,
+        };
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..1000 {
+            let p = Tup(rng.gen(), rng.gen(), rng.gen()) * 50.;
+            for c in [noise.value((0., 0.), p), marble.value((0., 0.), p)] {
+                assert!(
+                    (c.0 + c.2 - 1.).abs() < 1e-12 && c.0 >= 0. && c.2 >= 0.,
+                    "{:?}",
+                    c
+                );
+            }
+        }
+    }
+
+    fn two_by_two(wrap: Wrap) -> ImageTexture {
+        let image = Image {
+            width: 2,
+            height: 2,
+            pixels: vec![RED, BLUE, Tup(0., 1., 0.), Tup(1., 1., 1.)],
+        };
+        ImageTexture::new(image, wrap).unwrap()
+    }
+
+    #[test]
+    fn images_are_filtered_bilinearly() {
+        let texture = two_by_two(Wrap::Clamp);
+        let at = |u, v| texture.value((u, v), Tup::zeros());
+        // Texel centers give the texels, with the first row at the top.
+        assert_eq!(at(0.25, 0.75), RED);
+        assert_eq!(at(0.75, 0.75), BLUE);
+        assert_eq!(at(0.25, 0.25), Tup(0., 1., 0.));
+        assert_eq!(at(0.5, 0.75), Tup(0.5, 0., 0.5));
+        assert_eq!(at(0.5, 0.5), Tup(0.5, 0.5, 0.5));
+        assert!(ImageTexture::new(Image::new(0, 4), Wrap::Repeat).is_err());
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let at = |wrap, u| two_by_two(wrap).value((u, 0.75), Tup::zeros());
+        assert_eq!(at(Wrap::Repeat, 1.25), RED);
+        assert_eq!(at(Wrap::Clamp, 1.25), BLUE);
+        assert_eq!(at(Wrap::Mirror, 1.25), BLUE);
+        assert_eq!(at(Wrap::Mirror, 1.75), RED);
+        // Repeating images blend across the seam.
+        assert_eq!(at(Wrap::Repeat, 0.), Tup(0.5, 0., 0.5));
+        assert_eq!(at(Wrap::Clamp, 0.), RED);
+    }
+}
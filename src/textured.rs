@@ -0,0 +1,110 @@
+use std::fmt;
+
+use super::material::{BsdfSample, Material, Surface};
+use super::texture::Texture;
+use super::tup::Tup;
+
+/// A material whose parameters vary over the surface, such as a roughness
+/// map. `at` makes the material for the texture coordinates and position of
+/// each point it scatters light at, typically from values looked up in
+/// textures, and every call is handed on to it.
+pub struct Textured<M> {
+    at: Box<dyn Fn((f64, f64), Tup) -> M + Send + Sync>,
+}
+
+impl<M: Material> Textured<M> {
+    pub fn new(at: impl Fn((f64, f64), Tup) -> M + Send + Sync + 'static) -> Self {
+        Textured { at: Box::new(at) }
+    }
+
+    /// The material at the point of `s`.
+    pub fn at(&self, s: &Surface) -> M {
+        (self.at)(s.uv, s.point)
+    }
+}
+
+impl<M> fmt::Debug for Textured<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Textured<{}>", std::any::type_name::<M>())
+    }
+}
+
+impl<M: Material> Material for Textured<M> {
+    fn sample(&self, s: &Surface, wo: Tup, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        self.at(s).sample(s, wo, uc, u)
+    }
+
+    fn eval(&self, s: &Surface, wo: Tup, wi: Tup) -> Tup {
+        self.at(s).eval(s, wo, wi)
+    }
+
+    fn pdf(&self, s: &Surface, wo: Tup, wi: Tup) -> f64 {
+        self.at(s).pdf(s, wo, wi)
+    }
+
+    // `is_specular` keeps its default: whether the material is specular may
+    // depend on the point, and lights sampled at specular points only ever
+    // get zero from `eval`.
+
+    fn transmittance(&self, s: &Surface, distance: f64) -> Tup {
+        self.at(s).transmittance(s, distance)
+    }
+
+    fn lobes(&self, s: &Surface, wo: Tup) -> Vec<BsdfSample> {
+        self.at(s).lobes(s, wo)
+    }
+}
+
+/// Texture values read as a number, for material parameters such as
+/// roughness: the mean of the channels, which are all the same in grayscale
+/// textures.
+pub fn scalar(texture: &dyn Texture, uv: (f64, f64), p: Tup) -> f64 {
+    let v = texture.value(uv, p);
+    (v.0 + v.1 + v.2) / 3.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disney::Principled;
+    use crate::texture::Checker;
+
+    fn surface(uv: (f64, f64)) -> Surface {
+        Surface {
+            n: Tup(0., 0., 1.),
+            n1: Tup(0., 0., 1.),
+            color: Tup::ones(),
+            point: Tup::zeros(),
+            uv,
+        }
+    }
+
+    #[test]
+    fn uses_the_material_at_each_point() {
+        let roughness = Checker {
+            even: Tup(0.25, 0.25, 0.25),
+            odd: Tup(0.75, 0.75, 0.75),
+            scale: 2.,
+        };
+        let textured = Textured::new(move |uv, p| Principled {
+            roughness: scalar(&roughness, uv, p),
+            ..Default::default()
+        });
+        let (wo, wi) = (Tup(0.6, 0., 0.8), Tup(-0.6, 0., 0.8));
+        for (uv, roughness) in [((0.25, 0.25), 0.25), ((0.75, 0.25), 0.75)] {
+            let s = surface(uv);
+            let material = Principled {
+                roughness,
+                ..Default::default()
+            };
+            assert_eq!(textured.at(&s), material);
+            assert_eq!(textured.eval(&s, wo, wi), material.eval(&s, wo, wi));
+            assert_eq!(textured.pdf(&s, wo, wi), material.pdf(&s, wo, wi));
+            let (a, b) = (
+                textured.sample(&s, wo, 0.5, (0.3, 0.6)),
+                material.sample(&s, wo, 0.5, (0.3, 0.6)),
+            );
+            assert_eq!(a, b);
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::image::Image;
+use super::tup::Tup;
+
+/// Maps scene radiance to display values in [0, 1]. Values outside that range
+/// are left for the final quantization to clamp.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Operator {
+    /// No compression; everything above 1 clips.
+    #[default]
+    Linear,
+    /// `L / (1 + L)` on luminance.
+    Reinhard,
+    /// Reinhard with luminance `white` mapped to 1. Without a white point the
+    /// brightest pixel of the image is used.
+    ExtendedReinhard { white: Option<f64> },
+    /// Stephen Hill's fit of the ACES reference rendering and output
+    /// transforms.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear" => Ok(Operator::Linear),
+            "reinhard" => Ok(Operator::Reinhard),
+            "reinhard-extended" => Ok(Operator::ExtendedReinhard { white: None }),
+            "aces" => Ok(Operator::Aces),
+            "hable" => Ok(Operator::Hable),
+            _ => Err(format!(
+                "unknown tone mapping operator '{}' (expected 'linear', 'reinhard', \
+                 'reinhard-extended', 'aces' or 'hable')",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operator::Linear => "linear",
+            Operator::Reinhard => "reinhard",
+            Operator::ExtendedReinhard { .. } => "reinhard-extended",
+            Operator::Aces => "aces",
+            Operator::Hable => "hable",
+        })
+    }
+}
+
+/// Exposure followed by a tone mapping operator. The result is linear; the
+/// sRGB transfer function is applied when the image is quantized.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    /// Exposure adjustment in stops.
+    pub exposure: f64,
+    pub operator: Operator,
+}
+
+impl ToneMapping {
+    pub fn apply(&self, image: &Image) -> Image {
+        let scale = self.exposure.exp2();
+        let white = match self.operator {
+            Operator::ExtendedReinhard { white: None } => image
+                .pixels
+                .iter()
+                .map(|&p| luminance(p * scale))
+                .fold(0., f64::max),
+            Operator::ExtendedReinhard { white: Some(white) } => white,
+            _ => 1.,
+        };
+        Image {
+            width: image.width,
+            height: image.height,
+            pixels: image
+                .pixels
+                .iter()
+                .map(|&p| self.operator.map(p * scale, white))
+                .collect(),
+        }
+    }
+}
+
+/// Relative luminance of linear sRGB (Rec. 709 primaries).
+pub fn luminance(c: Tup) -> f64 {
+    0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2
+}
+
+impl Operator {
+    /// Maps one colour; `white` is only used by the extended Reinhard operator.
+    fn map(self, c: Tup, white: f64) -> Tup {
+        match self {
+            Operator::Linear => c,
+            Operator::Reinhard => scale_luminance(c, |l| l / (1. + l)),
+            Operator::ExtendedReinhard { .. } => {
+                let w2 = (white * white).max(f64::MIN_POSITIVE);
+                scale_luminance(c, |l| l * (1. + l / w2) / (1. + l))
+            }
+            Operator::Aces => aces_fitted(c),
+            Operator::Hable => {
+                const W: f64 = 11.2;
+                const EXPOSURE_BIAS: f64 = 2.;
+                let white_scale = 1. / hable(W);
+                Tup(
+                    hable(c.0 * EXPOSURE_BIAS) * white_scale,
+                    hable(c.1 * EXPOSURE_BIAS) * white_scale,
+                    hable(c.2 * EXPOSURE_BIAS) * white_scale,
+                )
+            }
+        }
+    }
+}
+
+fn scale_luminance(c: Tup, f: impl Fn(f64) -> f64) -> Tup {
+    let l = luminance(c);
+    if l <= 0. {
+        return Tup::zeros();
+    }
+    c * (f(l) / l)
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+fn mul(m: &[[f64; 3]; 3], c: Tup) -> Tup {
+    Tup(
+        m[0][0] * c.0 + m[0][1] * c.1 + m[0][2] * c.2,
+        m[1][0] * c.0 + m[1][1] * c.1 + m[1][2] * c.2,
+        m[2][0] * c.0 + m[2][1] * c.1 + m[2][2] * c.2,
+    )
+}
+
+fn aces_fitted(c: Tup) -> Tup {
+    // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    // ODT_SAT => XYZ => D60_2_D65 => sRGB
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let rrt_and_odt_fit = |v: f64| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    };
+    let c = mul(&INPUT, c);
+    let c = Tup(
+        rrt_and_odt_fit(c.0),
+        rrt_and_odt_fit(c.1),
+        rrt_and_odt_fit(c.2),
+    );
+    mul(&OUTPUT, c)
+}
+
+/// The sRGB transfer function, from linear to encoded values.
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// The inverse of [`srgb_encode`], from encoded to linear values.
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(operator: Operator, v: f64) -> Tup {
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![Tup(v, v, v), Tup(4., 4., 4.)],
+        };
+        ToneMapping {
+            exposure: 0.,
+            operator,
+        }
+        .apply(&image)
+        .pixels[0]
+    }
+
+    #[test]
+    fn srgb_transfer_function() {
+        assert_eq!(srgb_encode(0.), 0.);
+        assert!((srgb_encode(1.) - 1.).abs() < 1e-12);
+        assert!((srgb_encode(0.0031308) - 0.04045).abs() < 1e-6);
+        // Continuous where the linear segment meets the curve.
+        let e = 1e-9;
+        assert!((srgb_encode(0.0031308 + e) - srgb_encode(0.0031308)).abs() < 1e-6);
+        assert!((srgb_encode(0.5) - 0.735357).abs() < 1e-6);
+        for x in [0., 0.001, 0.0031308, 0.2, 1.] {
+            assert!((srgb_decode(srgb_encode(x)) - x).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn exposure_scales_in_stops() {
+        let image = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Tup(0.25, 0.5, 1.)],
+        };
+        let t = ToneMapping {
+            exposure: 1.,
+            operator: Operator::Linear,
+        };
+        assert_eq!(t.apply(&image).pixels[0], Tup(0.5, 1., 2.));
+    }
+
+    #[test]
+    fn operators_compress_highlights() {
+        for op in [
+            Operator::Reinhard,
+            Operator::ExtendedReinhard { white: None },
+            Operator::Aces,
+            Operator::Hable,
+        ] {
+            assert!(map(op, 0.).0.abs() < 1e-3, "{}", op);
+            let mut last = 0.;
+            for v in [0.01, 0.1, 0.5, 1., 2., 3.9] {
+                let m = map(op, v).0;
+                assert!(m > last && m < 1., "{} {} {}", op, v, m);
+                last = m;
+            }
+        }
+        assert!((map(Operator::Reinhard, 1.).0 - 0.5).abs() < 1e-12);
+        // The brightest pixel becomes white.
+        assert!((map(Operator::ExtendedReinhard { white: None }, 4.).0 - 1.).abs() < 1e-12);
+        assert!((map(Operator::ExtendedReinhard { white: Some(2.) }, 2.).0 - 1.).abs() < 1e-12);
+        assert!((map(Operator::Hable, 11.2 / 2.).0 - 1.).abs() < 1e-12);
+        assert!((map(Operator::Aces, 100.).0 - 1.).abs() < 0.05);
+    }
+
+    #[test]
+    fn operator_names_round_trip() {
+        for op in [
+            Operator::Linear,
+            Operator::Reinhard,
+            Operator::ExtendedReinhard { white: None },
+            Operator::Aces,
+            Operator::Hable,
+        ] {
+            assert_eq!(op.to_string().parse::<Operator>(), Ok(op));
+        }
+        assert!("drago".parse::<Operator>().is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use super::bvh::Aabb;
+use super::ray::Ray;
+use super::shape::{Hit, Intersection, Shape, EPS};
+use super::tup::Tup;
+
+/// A single triangle. Without vertex normals it is shaded flat, and without
+/// vertex uvs its uv coordinates are the barycentrics of the second and third
+/// vertices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triangle {
+    pub vertices: [Tup; 3],
+    pub normals: Option<[Tup; 3]>,
+    pub uvs: Option<[(f64, f64); 3]>,
+}
+
+impl Triangle {
+    pub fn new(a: Tup, b: Tup, c: Tup) -> Self {
+        Triangle {
+            vertices: [a, b, c],
+            normals: None,
+            uvs: None,
+        }
+    }
+
+    pub fn with_normals(self, normals: [Tup; 3]) -> Self {
+        Triangle {
+            normals: Some(normals.map(Tup::norm)),
+            ..self
+        }
+    }
+
+    pub fn with_uvs(self, uvs: [(f64, f64); 3]) -> Self {
+        Triangle {
+            uvs: Some(uvs),
+            ..self
+        }
+    }
+}
+
+impl Shape for Triangle {
+    fn bounds(&self) -> Aabb {
+        triangle_bounds(&self.vertices)
+    }
+
+    fn intersect(&self, ray: &Ray, t_max: f64) -> Option<Intersection> {
+        intersect_triangle(ray, &self.vertices, t_max).map(|(t, b)| Intersection {
+            t,
+            primitive: 0,
+            b,
+        })
+    }
+
+    fn hit(&self, ray: &Ray, i: &Intersection) -> Hit {
+        triangle_hit(
+            ray,
+            i,
+            &self.vertices,
+            self.normals.as_ref(),
+            self.uvs.as_ref(),
+        )
+    }
+}
+
+pub(crate) fn triangle_bounds(p: &[Tup; 3]) -> Aabb {
+    Aabb::empty().grow(p[0]).grow(p[1]).grow(p[2])
+}
+
+/// Möller-Trumbore intersection. Returns the distance and the barycentric
+/// coordinates of the second and third vertices.
+pub(crate) fn intersect_triangle(ray: &Ray, p: &[Tup; 3], t_max: f64) -> Option<(f64, (f64, f64))> {
+    let e1 = p[1] - p[0];
+    let e2 = p[2] - p[0];
+    let pv = ray.d.cross(e2);
+    let det = e1.dot(pv);
+    if det == 0. {
+        return None;
+    }
+    let inv_det = 1. / det;
+    let s = ray.o - p[0];
+    let b1 = s.dot(pv) * inv_det;
+    if !(0. ..=1.).contains(&b1) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let b2 = ray.d.dot(q) * inv_det;
+    if b2 < 0. || b1 + b2 > 1. {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    (t > EPS && t < t_max).then_some((t, (b1, b2)))
+}
+
+/// Surface data at a triangle hit. With vertex normals the geometric normal is
+/// turned to their side, since the winding order of imported meshes is not
+/// always consistent with them.
+pub(crate) fn triangle_hit(
+    ray: &Ray,
+    i: &Intersection,
+    p: &[Tup; 3],
+    normals: Option<&[Tup; 3]>,
+    uvs: Option<&[(f64, f64); 3]>,
+) -> Hit {
+    let (b1, b2) = i.b;
+    let b0 = 1. - b1 - b2;
+    let mut normal = (p[1] - p[0]).cross(p[2] - p[0]).norm();
+    let shading_normal = match normals {
+        Some(n) => {
+            let s = n[0] * b0 + n[1] * b1 + n[2] * b2;
+            if s.dot(s) > 0. {
+                let s = s.norm();
+                if s.dot(normal) < 0. {
+                    normal = normal * -1.;
+                }
+                s
+            } else {
+                normal
+            }
+        }
+        None => normal,
+    };
+    let uv = match uvs {
+        Some(uv) => (
+            uv[0].0 * b0 + uv[1].0 * b1 + uv[2].0 * b2,
+            uv[0].1 * b0 + uv[1].1 * b1 + uv[2].1 * b2,
+        ),
+        None => (b1, b2),
+    };
+    Hit {
+        t: i.t,
+        point: ray.o + ray.d * i.t,
+        normal,
+        shading_normal,
+        uv,
+        object: 0,
+        primitive: i.primitive,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_triangle() -> Triangle {
+        Triangle::new(Tup(0., 0., 0.), Tup(1., 0., 0.), Tup(0., 1., 0.))
+    }
+
+    #[test]
+    fn ray_hits_triangle() {
+        let triangle = unit_triangle();
+        let ray = Ray {
+            o: Tup(0.25, 0.5, 2.),
+            d: Tup(0., 0., -1.),
+        };
+        let i = triangle.intersect(&ray, f64::INFINITY).unwrap();
+        assert_eq!(i.t, 2.);
+        assert_eq!(i.b, (0.25, 0.5));
+        let hit = triangle.hit(&ray, &i);
+        assert_eq!(hit.point, Tup(0.25, 0.5, 0.));
+        assert_eq!(hit.normal, Tup(0., 0., 1.));
+        assert_eq!(hit.shading_normal, Tup(0., 0., 1.));
+        assert_eq!(hit.uv, (0.25, 0.5));
+        assert!(triangle.intersect(&ray, 2.).is_none());
+    }
+
+    #[test]
+    fn ray_misses_triangle() {
+        let triangle = unit_triangle();
+        for (o, d) in [
+            (Tup(0.6, 0.6, 1.), Tup(0., 0., -1.)),
+            (Tup(-0.1, 0.5, 1.), Tup(0., 0., -1.)),
+            (Tup(0.2, 0.2, 1.), Tup(0., 0., 1.)),
+            (Tup(0.2, 0.2, 1.), Tup(1., 0., 0.)),
+        ] {
+            assert!(triangle.intersect(&Ray { o, d }, f64::INFINITY).is_none());
+        }
+    }
+
+    #[test]
+    fn hits_from_behind_keep_the_front_normal() {
+        let triangle = unit_triangle();
+        let ray = Ray {
+            o: Tup(0.25, 0.25, -1.),
+            d: Tup(0., 0., 1.),
+        };
+        let hit = triangle.hit(&ray, &triangle.intersect(&ray, f64::INFINITY).unwrap());
+        assert_eq!(hit.normal, Tup(0., 0., 1.));
+    }
+
+    #[test]
+    fn interpolates_normals_and_uvs() {
+        let triangle = unit_triangle()
+            .with_normals([Tup(0., 0., -1.), Tup(1., 0., -1.), Tup(0., 0., -1.)])
+            .with_uvs([(0., 0.), (2., 0.), (0., 4.)]);
+        let ray = Ray {
+            o: Tup(0.5, 0.25, 1.),
+            d: Tup(0., 0., -1.),
+        };
+        let hit = triangle.hit(&ray, &triangle.intersect(&ray, f64::INFINITY).unwrap());
+        // The normals face away from the winding order, so the geometric
+        // normal is flipped to match them.
+        assert_eq!(hit.normal, Tup(0., 0., -1.));
+        let expected = (Tup(0., 0., -0.5) + Tup(1., 0., -1.).norm() * 0.5).norm();
+        assert!((hit.shading_normal - expected).dot(Tup::ones()).abs() < 1e-12);
+        assert_eq!(hit.uv, (1., 1.));
+    }
+}
@@ -1,6 +1,8 @@
 use std::ops;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub struct Tup(pub f64, pub f64, pub f64);
 
 impl Tup {
@@ -27,6 +29,17 @@ impl Tup {
             self.0 * rhs.1 - self.1 * rhs.0,
         )
     }
+
+    /// Two unit vectors that complete the unit vector `self` to an orthonormal
+    /// basis, built the same way smallpt builds its diffuse sampling frame.
+    pub fn basis(self) -> (Tup, Tup) {
+        let u = if self.0.abs() > 0.1 {
+            Tup(0., 1., 0.).cross(self).norm()
+        } else {
+            Tup(1., 0., 0.).cross(self).norm()
+        };
+        (u, self.cross(u))
+    }
 }
 
 impl ops::Add<Tup> for Tup {
@@ -1,95 +1,216 @@
-use core::f64;
-
+use super::bvh::Bvh;
+use super::material::{Diffuse, Glass, Material, Mirror};
+use super::plane::Plane;
 use super::ray::Ray;
-use super::sphere::{RflType, Sphere};
+use super::shape::{Hit, Intersection, Shape};
+use super::sphere::Sphere;
+use super::texture::Texture;
 use super::tup::Tup;
 
+const LINEAR_SCAN_LIMIT: usize = 16;
+
+/// A shape and the surface it is made of. Its emission and color are plain
+/// colors or textures over it.
+#[derive(Debug)]
+pub struct Object {
+    pub shape: Box<dyn Shape>,
+    pub emission: Box<dyn Texture>,
+    pub color: Box<dyn Texture>,
+    pub material: Box<dyn Material>,
+}
+
+impl Object {
+    pub fn new(
+        shape: impl Shape + 'static,
+        emission: impl Into<Box<dyn Texture>>,
+        color: impl Into<Box<dyn Texture>>,
+        material: impl Into<Box<dyn Material>>,
+    ) -> Self {
+        Object {
+            shape: Box::new(shape),
+            emission: emission.into(),
+            color: color.into(),
+            material: material.into(),
+        }
+    }
+
+    /// Light given off at `hit`.
+    pub fn emission_at(&self, hit: &Hit) -> Tup {
+        self.emission.value(hit.uv, hit.point)
+    }
+
+    /// The color of the surface at `hit`.
+    pub fn color_at(&self, hit: &Hit) -> Tup {
+        self.color.value(hit.uv, hit.point)
+    }
+}
+
 pub struct World {
-    pub spheres: Vec<Sphere>,
+    pub objects: Vec<Object>,
+    /// Indices of the emissive objects.
+    pub lights: Vec<usize>,
+    bvh: Bvh,
+    /// The objects in `bvh`, by their index in it.
+    bounded: Vec<usize>,
+    /// Objects without finite bounds, such as planes, which are tested apart.
+    unbounded: Vec<usize>,
 }
 
 impl World {
     pub fn new() -> Self {
+        World::from_objects(vec![
+            // Scene: radius, position, emission, color, material
+            Object::new(
+                Sphere::new(1e5, Tup(1e5 + 1.0, 40.8, 81.6)),
+                Tup::zeros(),
+                Tup(0.75, 0.25, 0.25),
+                Diffuse,
+            ), // Left
+            Object::new(
+                Sphere::new(1e5, Tup(-1e5 + 99., 40.8, 81.6)),
+                Tup::zeros(),
+                Tup(0.25, 0.25, 0.75),
+                Diffuse,
+            ), // Right
+            Object::new(
+                Sphere::new(1e5, Tup(50., 40.8, 1e5)),
+                Tup::zeros(),
+                Tup(0.75, 0.75, 0.75),
+                Diffuse,
+            ), // Back
+            Object::new(
+                Sphere::new(1e5, Tup(50., 40.8, -1e5 + 170.)),
+                Tup::zeros(),
+                Tup::zeros(),
+                Diffuse,
+            ), // Front
+            Object::new(
+                Sphere::new(1e5, Tup(50., 1e5, 81.6)),
+                Tup::zeros(),
+                Tup(0.75, 0.75, 0.75),
+                Diffuse,
+            ), // Bottom
+            Object::new(
+                Sphere::new(1e5, Tup(50., -1e5 + 81.6, 81.6)),
+                Tup::zeros(),
+                Tup(0.75, 0.75, 0.75),
+                Diffuse,
+            ), // Top
+            Object::new(
+                Sphere::new(16.5, Tup(27.0, 16.5, 47.0)),
+                Tup::zeros(),
+                Tup(1., 1., 1.) * 0.999,
+                Mirror,
+            ), // Mirror
+            Object::new(
+                Sphere::new(16.5, Tup(73., 16.5, 78.)),
+                Tup::zeros(),
+                Tup(1., 1., 1.) * 0.999,
+                Glass::default(),
+            ), // Glass
+            Object::new(
+                Sphere::new(600., Tup(50., 681.6 - 0.27, 81.6)),
+                Tup(12., 12., 12.),
+                Tup::zeros(),
+                Diffuse,
+            ), // Light
+        ])
+    }
+
+    /// The Cornell box of [`World::new`] with planes for walls instead of
+    /// huge spheres.
+    pub fn cornell_planes() -> Self {
+        let wall = |point: Tup, normal: Tup, color: Tup| {
+            let plane = Plane::new(point, normal).expect("normal is not zero");
+            Object::new(plane, Tup::zeros(), color, Diffuse)
+        };
+        // The mirror, the glass and the light, as in the spheres scene.
+        let mut objects: Vec<Object> = World::new().objects.drain(6..).collect();
+        objects.extend([
+            wall(Tup(1., 0., 0.), Tup(1., 0., 0.), Tup(0.75, 0.25, 0.25)), // Left
+            wall(Tup(99., 0., 0.), Tup(-1., 0., 0.), Tup(0.25, 0.25, 0.75)), // Right
+            wall(Tup(0., 0., 0.), Tup(0., 0., 1.), Tup(0.75, 0.75, 0.75)), // Back
+            wall(Tup(0., 0., 170.), Tup(0., 0., -1.), Tup::zeros()),       // Front
+            wall(Tup(0., 0., 0.), Tup(0., 1., 0.), Tup(0.75, 0.75, 0.75)), // Bottom
+            wall(Tup(0., 81.6, 0.), Tup(0., -1., 0.), Tup(0.75, 0.75, 0.75)), // Top
+        ]);
+        World::from_objects(objects)
+    }
+
+    pub fn from_objects(objects: Vec<Object>) -> Self {
+        let (bounded, unbounded): (Vec<usize>, Vec<usize>) =
+            (0..objects.len()).partition(|&i| objects[i].shape.bounds().is_finite());
+        let bounds: Vec<_> = bounded.iter().map(|&i| objects[i].shape.bounds()).collect();
+        let lights = (0..objects.len())
+            .filter(|&i| !objects[i].emission.is_black())
+            .collect();
         World {
-            spheres: vec![
-                // Scene: radius, position, emission, color, material
-                Sphere::new(
-                    1e5,
-                    Tup(1e5 + 1.0, 40.8, 81.6),
-                    Tup::zeros(),
-                    Tup(0.75, 0.25, 0.25),
-                    RflType::DIFF,
-                ), // Left
-                Sphere::new(
-                    1e5,
-                    Tup(-1e5 + 99., 40.8, 81.6),
-                    Tup::zeros(),
-                    Tup(0.25, 0.25, 0.75),
-                    RflType::DIFF,
-                ), // Right
-                Sphere::new(
-                    1e5,
-                    Tup(50., 40.8, 1e5),
-                    Tup::zeros(),
-                    Tup(0.75, 0.75, 0.75),
-                    RflType::DIFF,
-                ), // Back
-                Sphere::new(
-                    1e5,
-                    Tup(50., 40.8, -1e5 + 170.),
-                    Tup::zeros(),
-                    Tup::zeros(),
-                    RflType::DIFF,
-                ), // Front
-                Sphere::new(
-                    1e5,
-                    Tup(50., 1e5, 81.6),
-                    Tup::zeros(),
-                    Tup(0.75, 0.75, 0.75),
-                    RflType::DIFF,
-                ), // Bottom
-                Sphere::new(
-                    1e5,
-                    Tup(50., -1e5 + 81.6, 81.6),
-                    Tup::zeros(),
-                    Tup(0.75, 0.75, 0.75),
-                    RflType::DIFF,
-                ), // Top
-                Sphere::new(
-                    16.5,
-                    Tup(27.0, 16.5, 47.0),
-                    Tup::zeros(),
-                    Tup(1., 1., 1.) * 0.999,
-                    RflType::SPEC,
-                ), // Mirror
-                Sphere::new(
-                    16.5,
-                    Tup(73., 16.5, 78.),
-                    Tup::zeros(),
-                    Tup(1., 1., 1.) * 0.999,
-                    RflType::REFR,
-                ), // Glass
-                Sphere::new(
-                    600.,
-                    Tup(50., 681.6 - 0.27, 81.6),
-                    Tup(12., 12., 12.),
-                    Tup::zeros(),
-                    RflType::DIFF,
-                ), // Light
-            ],
+            bvh: Bvh::build(&bounds),
+            bounded,
+            unbounded,
+            lights,
+            objects,
+        }
+    }
+
+    /// The closest surface along `ray`.
+    pub fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let (object, i) = self.first_object(ray)?;
+        Some(Hit {
+            object,
+            ..self.objects[object].shape.hit(ray, &i)
+        })
+    }
+
+    /// The closest object along `ray` and where it was hit, without the
+    /// surface data. Enough for visibility tests.
+    pub fn first_object(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        // Small scenes like the Cornell box are dominated by huge overlapping
+        // spheres, where the box tests cost more than they save.
+        if self.objects.len() <= LINEAR_SCAN_LIMIT {
+            return self.intersect_brute_force(ray);
+        }
+        self.intersect_bvh(ray)
+    }
+
+    fn intersect_bvh(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        let mut closest: Option<(usize, Intersection)> = None;
+        // Ties are kept so that the highest index wins, like the linear scan
+        // below.
+        let mut visit = |i: usize| {
+            let t_max = closest.map_or(f64::INFINITY, |(_, c)| c.t.next_up());
+            match self.objects[i].shape.intersect(ray, t_max) {
+                Some(x) if closest.is_none_or(|(id, c)| x.t < c.t || i > id) => {
+                    closest = Some((i, x));
+                }
+                _ => {}
+            }
+            closest.map_or(f64::INFINITY, |(_, c)| c.t)
+        };
+        for &i in &self.unbounded {
+            visit(i);
         }
+        self.bvh
+            .traverse(ray, f64::INFINITY, |i| visit(self.bounded[i]));
+        closest
     }
 
-    pub fn intersect(&self, ray: &Ray, t: &mut f64, id: &mut usize) -> bool {
-        *t = f64::INFINITY;
-        for i in (0..self.spheres.len()).rev() {
-            let d = self.spheres[i].intersect(ray);
-            if d != 0.0 && d < *t {
-                *t = d;
-                *id = i;
+    /// Tests every object in turn. Gives the same answer as the BVH.
+    pub fn intersect_brute_force(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        let mut closest: Option<(usize, Intersection)> = None;
+        for i in (0..self.objects.len()).rev() {
+            let t_max = closest.map_or(f64::INFINITY, |(_, c)| c.t);
+            if let Some(x) = self.objects[i].shape.intersect(ray, t_max) {
+                closest = Some((i, x));
             }
         }
-        *t < f64::INFINITY
+        closest
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
     }
 }
 
@@ -97,32 +218,38 @@ impl World {
 mod tests {
 
     use super::*;
+    use crate::mesh::Mesh;
+    use crate::triangle::Triangle;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn new_world() {
-        let sphere = Sphere {
-            r: 1e5,
-            p: Tup(1e5 + 1.0, 40.8, 81.6),
-            e: Tup(0., 0., 0.),
-            c: Tup(0.75, 0.25, 0.25),
-            rfl: RflType::DIFF,
-        };
         let world = World::new();
-        assert_eq!(world.spheres.len(), 9);
-        assert_eq!(world.spheres[0], sphere)
+        assert_eq!(world.objects.len(), 9);
+        assert_eq!(
+            format!("{:?}", world.objects[0].shape),
+            format!("{:?}", Sphere::new(1e5, Tup(1e5 + 1.0, 40.8, 81.6)))
+        );
+        assert_eq!(
+            world.objects[0].color.value((0., 0.), Tup::zeros()),
+            Tup(0.75, 0.25, 0.25)
+        );
+        assert_eq!(format!("{:?}", world.objects[0].material), "Diffuse");
+        assert_eq!(world.lights, vec![8]);
     }
 
     #[test]
     fn ray_intersects() {
         let world = World::new();
         let ray = Ray {
-            o: Tup(0.0, 0.0, -5.0),
-            d: Tup(0.0, 0.0, 1.0),
+            o: Tup(50.0, 40.8, 100.0),
+            d: Tup(0.0, 0.0, -1.0),
         };
-        let mut t = f64::INFINITY;
-        let mut id = 0;
-        let its = world.intersect(&ray, &mut t, &mut id);
-        assert!(its);
+        let hit = world.intersect(&ray).unwrap();
+        assert_eq!(hit.object, 2);
+        assert_eq!(hit.t, 100.0);
+        assert_eq!(hit.point, Tup(50.0, 40.8, 0.0));
+        assert_eq!(hit.normal, Tup(0.0, 0.0, -1.0));
     }
 
     #[test]
@@ -132,9 +259,128 @@ mod tests {
             o: Tup(0.0, 0.0, -200000.0),
             d: Tup(0.0, 0.0, 0.0),
         };
-        let mut t = f64::INFINITY;
-        let mut id = 0;
-        let its = world.intersect(&ray, &mut t, &mut id);
-        assert!(!its);
+        assert!(world.intersect(&ray).is_none());
+    }
+
+    #[test]
+    fn hits_report_the_object_and_primitive() {
+        let quad = Mesh::new(
+            vec![
+                Tup(-1., -1., 0.),
+                Tup(1., -1., 0.),
+                Tup(1., 1., 0.),
+                Tup(-1., 1., 0.),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+        .unwrap();
+        let world = World::from_objects(vec![
+            Object::new(
+                Sphere::new(1., Tup(0., 0., -5.)),
+                Tup::zeros(),
+                Tup::ones(),
+                Diffuse,
+            ),
+            Object::new(quad, Tup::zeros(), Tup::ones(), Diffuse),
+        ]);
+        let hit = world
+            .intersect(&Ray {
+                o: Tup(-0.5, 0.5, 1.),
+                d: Tup(0., 0., -1.),
+            })
+            .unwrap();
+        assert_eq!((hit.object, hit.primitive), (1, 1));
+        assert_eq!(hit.point, Tup(-0.5, 0.5, 0.));
+        let hit = world
+            .intersect(&Ray {
+                o: Tup(0., 0., -2.),
+                d: Tup(0., 0., -1.),
+            })
+            .unwrap();
+        assert_eq!((hit.object, hit.t), (0, 2.));
+    }
+
+    fn random_world(rng: &mut StdRng, n: usize) -> World {
+        World::from_objects(
+            (0..n)
+                .map(|i| {
+                    let p = Tup(
+                        rng.gen_range(-50.0..50.0),
+                        rng.gen_range(-50.0..50.0),
+                        rng.gen_range(-50.0..50.0),
+                    );
+                    let r = rng.gen_range(0.1..5.0);
+                    let shape: Box<dyn Shape> = if i % 50 == 49 {
+                        let normal = Tup(rng.gen_range(-1.0..1.0), 1., rng.gen_range(-1.0..1.0));
+                        Box::new(Plane::new(p, normal).unwrap())
+                    } else if i % 3 == 2 {
+                        let mut corner = || {
+                            p + Tup(
+                                rng.gen_range(-r..r),
+                                rng.gen_range(-r..r),
+                                rng.gen_range(-r..r),
+                            )
+                        };
+                        Box::new(Triangle::new(corner(), corner(), corner()))
+                    } else {
+                        Box::new(Sphere::new(r, p))
+                    };
+                    Object {
+                        shape,
+                        emission: Box::new(Tup::zeros()),
+                        color: Box::new(Tup::ones()),
+                        material: Box::new(Diffuse),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn bvh_matches_brute_force_on_random_scenes() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for n in [1, 2, 7, 100, 1000] {
+            let world = random_world(&mut rng, n);
+            for _ in 0..2000 {
+                let ray = Ray {
+                    o: Tup(
+                        rng.gen_range(-60.0..60.0),
+                        rng.gen_range(-60.0..60.0),
+                        rng.gen_range(-60.0..60.0),
+                    ),
+                    d: Tup(
+                        rng.gen_range(-1.0..1.0),
+                        rng.gen_range(-1.0..1.0),
+                        rng.gen_range(-1.0..1.0),
+                    )
+                    .norm(),
+                };
+                assert_eq!(world.intersect_bvh(&ray), world.intersect_brute_force(&ray));
+            }
+        }
+    }
+
+    #[test]
+    fn bvh_matches_brute_force_in_cornell_box() {
+        let world = World::new();
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..10000 {
+            let ray = Ray {
+                o: Tup(
+                    rng.gen_range(2.0..98.0),
+                    rng.gen_range(1.0..80.0),
+                    rng.gen_range(1.0..169.0),
+                ),
+                d: Tup(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .norm(),
+            };
+            let hit = world.intersect_bvh(&ray);
+            assert!(hit.is_some());
+            assert_eq!(hit, world.intersect_brute_force(&ray));
+        }
     }
 }